@@ -0,0 +1,84 @@
+//! Compares evaluating every story against evaluating only the stories that depend on a
+//! changed fact. Run with `cargo bench --bench story_evaluation`.
+
+use barnacle_beats::beats::data::{Condition, FactsOfTheWorld, Rule, Story, StoryBeat, StoryEngine};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const STORIES: usize = 10_000;
+const FACTS: usize = 100_000;
+const ROUNDS: u32 = 100;
+const WARMUP_ROUNDS: u32 = 3;
+
+fn build_world() -> (StoryEngine, FactsOfTheWorld) {
+    let mut facts = FactsOfTheWorld::new();
+    for fact in 0..FACTS {
+        facts.store_int(format!("fact_{}", fact), 0);
+    }
+
+    let mut story_engine = StoryEngine::new();
+    for story in 0..STORIES {
+        // Every story waits for two facts that never get high enough, so none of them advance
+        let beats = (0..3)
+            .map(|beat| {
+                StoryBeat::new(
+                    format!("beat_{}", beat),
+                    vec![Rule::new(
+                        format!("rule_{}", beat),
+                        vec![
+                            Condition::IntMoreThan {
+                                fact_name: format!("fact_{}", (story * 10 + beat) % FACTS),
+                                expected_value: 1_000,
+                            },
+                            Condition::BoolEquals {
+                                fact_name: format!("flag_{}", story),
+                                expected_value: true,
+                            },
+                        ],
+                    )],
+                    vec![],
+                )
+            })
+            .collect();
+        story_engine.add_story(Story::new(format!("story_{}", story), vec![], beats));
+    }
+    (story_engine, facts)
+}
+
+// Evaluates every story the way evaluate_changed evaluates the stories it picks
+fn full_scan(story_engine: &mut StoryEngine, facts: &FactsOfTheWorld) -> usize {
+    let mut story_steps = Vec::new();
+    for (story_index, story) in story_engine.stories.iter_mut().enumerate() {
+        story_steps.extend(story.evaluate(&facts.facts).into_iter().map(|step| (story_index, step)));
+    }
+    story_steps.len()
+}
+
+fn time(rounds: u32, mut run: impl FnMut() -> usize) -> Duration {
+    let start = Instant::now();
+    for _ in 0..rounds {
+        black_box(run());
+    }
+    start.elapsed() / rounds
+}
+
+fn main() {
+    let (mut story_engine, mut facts) = build_world();
+    // The first evaluation starts every newly added story
+    story_engine.evaluate_changed([], &facts.facts);
+
+    let changed = "fact_4242";
+    facts.store_int(changed.to_string(), 1);
+
+    // The first rounds build the index, check the first beats of the stories that just started and
+    // remember which watched rules hold, only the rounds after that are timed
+    time(WARMUP_ROUNDS, || full_scan(&mut story_engine, &facts));
+    time(WARMUP_ROUNDS, || story_engine.evaluate_changed([changed], &facts.facts).len());
+
+    let full = time(ROUNDS, || full_scan(&mut story_engine, &facts));
+    let indexed = time(ROUNDS, || story_engine.evaluate_changed([changed], &facts.facts).len());
+
+    println!("{} stories, {} facts, one fact changed", STORIES, FACTS);
+    println!("full scan: {:?} per evaluation", full);
+    println!("indexed:   {:?} per evaluation", indexed);
+}
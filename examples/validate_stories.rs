@@ -0,0 +1,76 @@
+//! Checks story files before they ship, see `barnacle_beats::beats::validation`.
+//!
+//!     cargo run --example validate_stories -- assets/story_example.story assets/side_quests.stories.ron
+//!     cargo run --example validate_stories -- --schema facts.ron assets/*.story
+//!
+//! The schema is a RON `FactSchema` with the facts the game sets in code, as in
+//! `(facts: {"button_pressed": Int})`. Rules that can never hold and beats that can never be
+//! reached are listed after the problems, see `barnacle_beats::beats::analysis`. Exits with 1 when
+//! there are errors.
+
+use barnacle_beats::beats::analysis::analyze;
+use barnacle_beats::beats::data::{FactsOfTheWorld, Story, StoryEngine};
+use barnacle_beats::beats::parser::parse_stories;
+use barnacle_beats::beats::validation::{has_errors, validate, FactSchema};
+use std::process::ExitCode;
+
+fn read_stories(path: &str) -> Result<Vec<Story>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+    if path.ends_with(".stories.ron") {
+        ron::de::from_str(&contents).map_err(|error| format!("{}: {}", path, error))
+    } else {
+        parse_stories(&contents).map_err(|error| format!("{}: {}", path, error))
+    }
+}
+
+fn main() -> ExitCode {
+    let mut schema = FactSchema::new();
+    let mut story_engine = StoryEngine::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let loaded = if arg == "--schema" {
+            let Some(path) = args.next() else {
+                eprintln!("--schema needs the path of a schema file");
+                return ExitCode::from(2);
+            };
+            std::fs::read_to_string(&path)
+                .map_err(|error| error.to_string())
+                .and_then(|contents| ron::de::from_str(&contents).map_err(|error| error.to_string()))
+                .map(|loaded| schema = loaded)
+                .map_err(|error| format!("{}: {}", path, error))
+        } else {
+            read_stories(&arg).map(|stories| {
+                for story in stories {
+                    if story.is_template() {
+                        story_engine.add_template(story);
+                    } else {
+                        story_engine.add_story(story);
+                    }
+                }
+            })
+        };
+        if let Err(error) = loaded {
+            eprintln!("{}", error);
+            return ExitCode::from(2);
+        }
+    }
+
+    let diagnostics = validate(&story_engine, &schema);
+    for diagnostic in diagnostics.iter() {
+        println!("{}", diagnostic);
+    }
+    println!("{} problem(s) in {} stories", diagnostics.len(), story_engine.stories.len() + story_engine.templates.len());
+    let report = analyze(&story_engine, &FactsOfTheWorld::new(), &schema);
+    print!("{}", report);
+    println!(
+        "{} contradictory rule(s), {} dead beat(s), {} undecided beat(s)",
+        report.contradictions.len(),
+        report.dead_beats.len(),
+        report.undecided_beats.len()
+    );
+    if has_errors(&diagnostics) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
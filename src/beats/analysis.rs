@@ -0,0 +1,962 @@
+use crate::beats::conversations::Conversations;
+use crate::beats::data::{Condition, Effect, Fact, FactScope, FactsOfTheWorld, Rule, Story, StoryEngine};
+use crate::beats::dialogue::ResponseTable;
+use crate::beats::storylets::StoryletPool;
+use crate::beats::validation::{all_stories, beat_rules, comparisons, content_schema, story_effects, FactSchema, FactType};
+use bevy::log::{info, warn};
+use bevy::prelude::Res;
+use bevy::utils::hashbrown::{HashMap, HashSet};
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+
+/*
+Finds rules that can never hold and beats that can never be reached.
+
+Every rule is checked on its own first. Its conditions are taken apart into alternatives of plain
+comparisons, and an alternative can hold unless its comparisons contradict each other, like
+`x > 5 && x < 3`, `flag && !flag`, `name == "a" && name == "b"` or a fact checked as an integer
+and as a boolean.
+
+Then every story is played through. Starting from the facts of the world, the beats whose rules,
+objectives and transitions can hold are finished, their effects are applied and the beats they
+lead to are visited, until no new beat and facts are found or MAX_STATES is reached. Facts the game
+sets in code, the ones in the FactSchema, facts other stories set and facts of entities can have
+any value; the others only have the values the effects of the story give them. The beats that are
+never visited are dead, with the reasons the beats before them never lead to them.
+ */
+
+// The most beat and facts combinations visited per story
+pub const MAX_STATES: usize = 10_000;
+// The most alternatives the conditions of a rule are taken apart into, beyond that they are
+// assumed to be possible
+const MAX_ALTERNATIVES: usize = 256;
+
+// A rule whose conditions can never all hold
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Contradiction {
+    pub story: String,
+    // None for the pre-requisites and failures of the story
+    pub beat: Option<String>,
+    pub rule: String,
+    pub reason: String,
+}
+
+impl Display for Contradiction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "story '{}'", self.story)?;
+        if let Some(beat) = &self.beat {
+            write!(f, ", beat '{}'", beat)?;
+        }
+        write!(f, ": rule '{}' can never hold, {}", self.rule, self.reason)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeadBeat {
+    pub story: String,
+    pub beat: String,
+    pub reason: String,
+}
+
+impl Display for DeadBeat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "story '{}', beat '{}': {}", self.story, self.beat, self.reason)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnalysisReport {
+    pub contradictions: Vec<Contradiction>,
+    pub dead_beats: Vec<DeadBeat>,
+    // Beats not reached before the search stopped at MAX_STATES, they may still be reachable
+    pub undecided_beats: Vec<DeadBeat>,
+}
+
+impl AnalysisReport {
+    pub fn is_empty(&self) -> bool {
+        self.contradictions.is_empty() && self.dead_beats.is_empty() && self.undecided_beats.is_empty()
+    }
+}
+
+impl Display for AnalysisReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for contradiction in self.contradictions.iter() {
+            writeln!(f, "{}", contradiction)?;
+        }
+        for dead_beat in self.dead_beats.iter() {
+            writeln!(f, "dead beat, {}", dead_beat)?;
+        }
+        for undecided_beat in self.undecided_beats.iter() {
+            writeln!(f, "undecided beat, {}", undecided_beat)?;
+        }
+        Ok(())
+    }
+}
+
+// Checks the rules of all stories and story templates for contradictions, and plays every story
+// through from the facts to find the beats that can never be reached
+pub fn analyze(story_engine: &StoryEngine, facts: &FactsOfTheWorld, schema: &FactSchema) -> AnalysisReport {
+    let mut report = AnalysisReport::default();
+    let stories: Vec<&Story> = all_stories(story_engine).collect();
+    for (index, story) in stories.iter().enumerate() {
+        report.contradictions.extend(contradictions(story));
+
+        // Facts set in code or by other stories can have any value while this one runs
+        let mut free: HashSet<&str> = schema.facts.keys().map(String::as_str).collect();
+        for (_, other) in stories.iter().enumerate().filter(|(other, _)| *other != index) {
+            free.extend(story_effects(other).map(Effect::fact_name));
+        }
+        // Facts named after parameters of a template are only known once it is instantiated
+        free.extend(parameter_facts(story));
+        let search = Search::run(story, facts, &free);
+        report.dead_beats.extend(search.unreached(story, true));
+        report.undecided_beats.extend(search.unreached(story, false));
+    }
+    report
+}
+
+pub fn story_analyzer(
+    story_engine: Res<StoryEngine>,
+    conversations: Res<Conversations>,
+    storylets: Res<StoryletPool>,
+    response_table: Res<ResponseTable>,
+    schema: Res<FactSchema>,
+    cool_fact_store: Res<FactsOfTheWorld>,
+) {
+    let schema = content_schema(&schema, &conversations, &storylets, &response_table);
+    let report = analyze(&story_engine, &cool_fact_store, &schema);
+    for contradiction in report.contradictions.iter() {
+        warn!("{}", contradiction);
+    }
+    for dead_beat in report.dead_beats.iter() {
+        warn!("dead beat, {}", dead_beat);
+    }
+    for undecided_beat in report.undecided_beats.iter() {
+        info!("undecided beat, {}", undecided_beat);
+    }
+}
+
+fn contradictions(story: &Story) -> Vec<Contradiction> {
+    let no_facts = HashMap::new();
+    let known = Known {
+        facts: &no_facts,
+        free: None,
+    };
+    let story_rules = story
+        .pre_requisites
+        .iter()
+        .chain(story.failures.iter().flat_map(|failure| failure.rules.iter()))
+        .map(|rule| (None, rule));
+    let beat_rules = story
+        .beats
+        .iter()
+        .flat_map(|beat| beat_rules(beat).map(move |rule| (Some(beat.name.clone()), rule)));
+    story_rules
+        .chain(beat_rules)
+        .filter_map(|(beat, rule): (Option<String>, &Rule)| {
+            let reason = known.unsatisfiable([rule])?;
+            Some(Contradiction {
+                story: story.name.clone(),
+                beat,
+                rule: rule.name.clone(),
+                reason,
+            })
+        })
+        .collect()
+}
+
+fn parameter_facts(story: &Story) -> Vec<&str> {
+    let mut found = Vec::new();
+    let rules = story
+        .pre_requisites
+        .iter()
+        .chain(story.failures.iter().flat_map(|failure| failure.rules.iter()))
+        .chain(story.beats.iter().flat_map(beat_rules));
+    for condition in rules.flat_map(|rule| rule.conditions.iter()) {
+        comparisons(condition, &mut found);
+    }
+    let checked = found.into_iter().filter_map(FactType::checked_by).map(|(name, _)| name);
+    checked
+        .chain(story_effects(story).map(Effect::fact_name))
+        .filter(|name| story.mentions_parameter(name))
+        .collect()
+}
+
+fn beat_position(story: &Story, name: &str) -> Option<usize> {
+    story.beats.iter().position(|beat| beat.name == name)
+}
+
+// The beat a beat is entered from, None for the failures of the story, and the beat entered
+type Edge = (Option<usize>, usize);
+
+// A breadth first search over the beats of a story and the facts they are entered with
+struct Search {
+    visited: HashSet<(usize, Vec<Fact>)>,
+    queue: VecDeque<(usize, HashMap<String, Fact>)>,
+    reached: HashSet<usize>,
+    // Why beats never finished and why the ways between beats were never taken
+    blocked: HashMap<usize, String>,
+    edges_blocked: HashMap<Edge, String>,
+    never_starts: Option<String>,
+    // False when the search stopped at MAX_STATES
+    complete: bool,
+}
+
+impl Search {
+    fn run(story: &Story, facts: &FactsOfTheWorld, free: &HashSet<&str>) -> Self {
+        let mut search = Search {
+            visited: HashSet::new(),
+            queue: VecDeque::new(),
+            reached: HashSet::new(),
+            blocked: HashMap::new(),
+            edges_blocked: HashMap::new(),
+            never_starts: None,
+            complete: true,
+        };
+        if story.beats.is_empty() {
+            return search;
+        }
+        // The facts that can have any value are left out and checked symbolically
+        let world = Known {
+            facts: &facts.facts,
+            free: Some(free),
+        };
+        let initial: HashMap<String, Fact> = facts
+            .facts
+            .iter()
+            .filter(|(name, _)| !world.is_free(name))
+            .map(|(name, fact)| (name.clone(), fact.clone()))
+            .collect();
+        let known = Known {
+            facts: &initial,
+            free: Some(free),
+        };
+        search.never_starts = known.unsatisfiable(story.pre_requisites.iter());
+        if search.never_starts.is_none() {
+            search.visit(0, initial);
+        }
+
+        while let Some((index, facts)) = search.queue.pop_front() {
+            if !search.complete {
+                break;
+            }
+            let beat = &story.beats[index];
+
+            // Objectives can complete on later evaluations, after the effects of the others
+            let mut completed_facts = facts.clone();
+            let mut completed = vec![false; beat.objectives.len()];
+            let mut progressed = true;
+            while progressed {
+                progressed = false;
+                for (objective, completed) in beat.objectives.iter().zip(completed.iter_mut()).filter(|(_, completed)| !**completed) {
+                    let known = Known {
+                        facts: &completed_facts,
+                        free: Some(free),
+                    };
+                    if known.unsatisfiable(objective.rules.iter()).is_none() {
+                        completed_facts = known.apply(&objective.effects);
+                        *completed = true;
+                        progressed = true;
+                    }
+                }
+            }
+
+            // Failures are checked on every evaluation, before and after the objectives complete
+            let failures = beat
+                .failures
+                .iter()
+                .map(|failure| (Some(index), failure))
+                .chain(story.failures.iter().map(|failure| (None, failure)));
+            for (from, failure) in failures {
+                let Some(recovery) = failure.recovery.as_deref().and_then(|recovery| beat_position(story, recovery)) else {
+                    continue;
+                };
+                for failure_facts in [&facts, &completed_facts] {
+                    let known = Known {
+                        facts: failure_facts,
+                        free: Some(free),
+                    };
+                    match known.unsatisfiable(failure.rules.iter()) {
+                        None => search.visit(recovery, known.apply(&failure.effects)),
+                        Some(reason) => {
+                            let reason = format!("failure '{}' never happens, {}", failure.name, reason);
+                            search.edges_blocked.entry((from, recovery)).or_insert(reason);
+                        }
+                    }
+                }
+            }
+
+            let known = Known {
+                facts: &completed_facts,
+                free: Some(free),
+            };
+            let completed_count = completed.iter().filter(|completed| **completed).count();
+            if !beat.completion.is_met(completed_count, beat.objectives.len()) {
+                let incomplete: Vec<String> = beat
+                    .objectives
+                    .iter()
+                    .zip(completed.iter())
+                    .filter(|(_, completed)| !**completed)
+                    .filter_map(|(objective, _)| {
+                        let reason = known.unsatisfiable(objective.rules.iter())?;
+                        Some(format!("objective '{}' never completes, {}", objective.name, reason))
+                    })
+                    .collect();
+                search.blocked.entry(index).or_insert(incomplete.join("; "));
+                continue;
+            }
+            if let Some(reason) = known.unsatisfiable(beat.rules.iter()) {
+                search.blocked.entry(index).or_insert(reason);
+                continue;
+            }
+            let finished_facts = known.apply(&beat.effects);
+            if beat.transitions.is_empty() {
+                if index + 1 < story.beats.len() {
+                    search.visit(index + 1, finished_facts);
+                }
+                continue;
+            }
+            let mut finishes = false;
+            for transition in beat.transitions.iter() {
+                let target = transition.target.as_deref().and_then(|target| beat_position(story, target));
+                match known.unsatisfiable(beat.rules.iter().chain(transition.rules.iter())) {
+                    None => {
+                        finishes = true;
+                        if let Some(target) = target {
+                            search.visit(target, finished_facts.clone());
+                        }
+                    }
+                    Some(reason) => {
+                        if let Some(target) = target {
+                            let reason = format!("transition '{}' never holds, {}", transition.name, reason);
+                            search.edges_blocked.entry((Some(index), target)).or_insert(reason);
+                        }
+                    }
+                }
+            }
+            if !finishes {
+                search.blocked.entry(index).or_insert("none of its transitions ever hold".to_string());
+            }
+        }
+        search
+    }
+
+    fn visit(&mut self, beat: usize, facts: HashMap<String, Fact>) {
+        let mut key: Vec<Fact> = facts.values().cloned().collect();
+        key.sort_unstable_by(|fact, other| fact.name().cmp(other.name()));
+        let key = (beat, key);
+        if self.visited.contains(&key) {
+            return;
+        }
+        if self.visited.len() >= MAX_STATES {
+            self.complete = false;
+            return;
+        }
+        self.visited.insert(key);
+        self.reached.insert(beat);
+        self.queue.push_back((beat, facts));
+    }
+
+    // The beats that were never reached, the dead ones when the search is complete and the
+    // undecided ones when it stopped early
+    fn unreached(&self, story: &Story, dead: bool) -> Vec<DeadBeat> {
+        if dead != self.complete {
+            return Vec::new();
+        }
+        (0..story.beats.len())
+            .filter(|index| !self.reached.contains(index))
+            .map(|index| DeadBeat {
+                story: story.name.clone(),
+                beat: story.beats[index].name.clone(),
+                reason: if dead {
+                    self.dead_reason(story, index)
+                } else {
+                    format!("not reached within {} states", MAX_STATES)
+                },
+            })
+            .collect()
+    }
+
+    fn dead_reason(&self, story: &Story, index: usize) -> String {
+        if let Some(reason) = &self.never_starts {
+            return format!("the story can never start, {}", reason);
+        }
+        let name = story.beats[index].name.as_str();
+        let predecessors = story.beats.iter().enumerate().filter(|(from, beat)| {
+            beat.transitions.iter().any(|transition| transition.target.as_deref() == Some(name))
+                || (beat.transitions.is_empty() && from + 1 == index)
+                || beat.failures.iter().any(|failure| failure.recovery.as_deref() == Some(name))
+        });
+        let mut reasons: Vec<String> = predecessors
+            .map(|(from, beat)| {
+                if !self.reached.contains(&from) {
+                    format!("'{}' is never reached", beat.name)
+                } else if let Some(reason) = self.edges_blocked.get(&(Some(from), index)) {
+                    format!("from '{}', {}", beat.name, reason)
+                } else if let Some(reason) = self.blocked.get(&from) {
+                    format!("'{}' never finishes, {}", beat.name, reason)
+                } else {
+                    format!("'{}' never leads to it", beat.name)
+                }
+            })
+            .collect();
+        if let Some(reason) = self.edges_blocked.get(&(None, index)) {
+            reasons.push(format!("from any beat, {}", reason));
+        }
+        if reasons.is_empty() {
+            "no beat leads to it".to_string()
+        } else {
+            reasons.join("; ")
+        }
+    }
+}
+
+// What is known about the facts: a fact has the value it has in the facts, or is absent, unless
+// it is free and can have any value. Without a set of free facts every fact is free.
+struct Known<'a> {
+    facts: &'a HashMap<String, Fact>,
+    free: Option<&'a HashSet<&'a str>>,
+}
+
+impl Known<'_> {
+    fn is_free(&self, name: &str) -> bool {
+        FactScope::of(name).0 != FactScope::Global || self.free.is_none_or(|free| free.contains(name))
+    }
+
+    // Why the rules can never all hold together, None when they can
+    fn unsatisfiable<'r>(&self, rules: impl IntoIterator<Item = &'r Rule>) -> Option<String> {
+        let alternatives = all_of(rules.into_iter().flat_map(|rule| rule.conditions.iter()), true)?;
+        let mut reasons: Vec<String> = Vec::new();
+        for alternative in alternatives.iter() {
+            let reason = self.contradiction(alternative)?;
+            if !reasons.contains(&reason) {
+                reasons.push(reason);
+            }
+        }
+        Some(reasons.join(", or "))
+    }
+
+    // Why the comparisons of an alternative can't all hold, None when they can
+    fn contradiction(&self, literals: &[Literal]) -> Option<String> {
+        let mut by_fact: HashMap<&str, Vec<Literal>> = HashMap::new();
+        for literal in literals {
+            by_fact.entry(literal.fact_name).or_default().push(*literal);
+        }
+        let mut fact_names: Vec<&str> = by_fact.keys().copied().collect();
+        fact_names.sort_unstable();
+        for name in fact_names {
+            let literals = &by_fact[name];
+            if self.is_free(name) {
+                if let Some(reason) = symbolic_contradiction(name, literals) {
+                    return Some(reason);
+                }
+            } else if let Some(literal) = literals
+                .iter()
+                .find(|literal| literal.comparison.evaluate(self.facts) != literal.holds)
+            {
+                let value = match self.facts.get(name) {
+                    Some(fact) => format!("'{}' is {}", name, fact_value(fact)),
+                    None => format!("'{}' is never set", name),
+                };
+                return Some(format!("{} so {} doesn't hold", value, literal));
+            }
+        }
+        None
+    }
+
+    // The facts after the effects, effects on free facts are left out as those can be anything
+    fn apply(&self, effects: &[Effect]) -> HashMap<String, Fact> {
+        let mut facts = FactsOfTheWorld {
+            facts: self.facts.clone(),
+            updated_facts: HashSet::new(),
+            removed_facts: HashSet::new(),
+        };
+        for effect in effects.iter().filter(|effect| !self.is_free(effect.fact_name())) {
+            // Effects on facts of the wrong type are reported by the validation
+            let _ = effect.apply(&mut facts);
+        }
+        facts.facts
+    }
+}
+
+fn fact_value(fact: &Fact) -> String {
+    match fact {
+        Fact::Int(_, value) => value.to_string(),
+        Fact::Float(_, value) => value.0.to_string(),
+        Fact::Bool(_, value) => value.to_string(),
+        Fact::String(_, value) => format!("\"{}\"", value),
+        Fact::StringList(_, values) => {
+            let mut values: Vec<&String> = values.0.iter().collect();
+            values.sort();
+            format!("{:?}", values)
+        }
+    }
+}
+
+// A comparison that must hold, or must not hold
+#[derive(Debug, Clone, Copy)]
+struct Literal<'a> {
+    comparison: &'a Condition,
+    fact_name: &'a str,
+    fact_type: FactType,
+    holds: bool,
+}
+
+impl Display for Literal<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let comparison = match self.comparison {
+            Condition::IntEquals { fact_name, expected_value }
+            | Condition::IntMoreThan { fact_name, expected_value }
+            | Condition::IntLessThan { fact_name, expected_value } => {
+                format!("{} {} {}", fact_name, operator(self.comparison), expected_value)
+            }
+            Condition::FloatMoreThan { fact_name, expected_value }
+            | Condition::FloatLessThan { fact_name, expected_value }
+            | Condition::FloatEquals { fact_name, expected_value, .. } => {
+                format!("{} {} {}", fact_name, operator(self.comparison), expected_value.0)
+            }
+            Condition::BoolEquals { fact_name, expected_value } => format!("{} == {}", fact_name, expected_value),
+            Condition::StringEquals { fact_name, expected_value } => format!("{} == \"{}\"", fact_name, expected_value),
+            Condition::ListContains { fact_name, expected_value } => {
+                format!("{} contains \"{}\"", fact_name, expected_value)
+            }
+            Condition::All(_) | Condition::Any(_) | Condition::Not(_) | Condition::AtLeast(_, _) => String::new(),
+        };
+        if self.holds {
+            write!(f, "`{}`", comparison)
+        } else {
+            write!(f, "`!({})`", comparison)
+        }
+    }
+}
+
+fn operator(comparison: &Condition) -> &'static str {
+    match comparison {
+        Condition::IntMoreThan { .. } | Condition::FloatMoreThan { .. } => ">",
+        Condition::IntLessThan { .. } | Condition::FloatLessThan { .. } => "<",
+        _ => "==",
+    }
+}
+
+// The alternatives in which all the conditions hold, or none of them do. Each alternative is a
+// list of comparisons that must all hold or not hold. None when there are more than
+// MAX_ALTERNATIVES, the conditions are then assumed to be possible.
+fn all_of<'a>(conditions: impl IntoIterator<Item = &'a Condition>, holds: bool) -> Option<Vec<Vec<Literal<'a>>>> {
+    let mut combined: Vec<Vec<Literal>> = vec![Vec::new()];
+    for condition in conditions {
+        let mut next = Vec::new();
+        for alternative in alternatives(condition, holds)? {
+            for previous in combined.iter() {
+                let mut literals = previous.clone();
+                literals.extend(alternative.iter().copied());
+                next.push(literals);
+            }
+        }
+        if next.len() > MAX_ALTERNATIVES {
+            return None;
+        }
+        combined = next;
+    }
+    Some(combined)
+}
+
+// The alternatives in which a condition holds, or doesn't
+fn alternatives(condition: &Condition, holds: bool) -> Option<Vec<Vec<Literal<'_>>>> {
+    match (condition, holds) {
+        (Condition::All(conditions), true) | (Condition::Any(conditions), false) => all_of(conditions, holds),
+        (Condition::Any(conditions), true) | (Condition::All(conditions), false) => {
+            let mut combined = Vec::new();
+            for condition in conditions {
+                combined.extend(alternatives(condition, holds)?);
+                if combined.len() > MAX_ALTERNATIVES {
+                    return None;
+                }
+            }
+            Some(combined)
+        }
+        (Condition::Not(condition), holds) => alternatives(condition, !holds),
+        // At least `count` of the conditions hold when all of some `count` of them do
+        (Condition::AtLeast(count, conditions), true) => {
+            let mut combined = Vec::new();
+            for chosen in combinations(conditions.len(), *count)? {
+                combined.extend(all_of(chosen.into_iter().map(|index| &conditions[index]), true)?);
+                if combined.len() > MAX_ALTERNATIVES {
+                    return None;
+                }
+            }
+            Some(combined)
+        }
+        // Fewer than `count` of them hold, this isn't taken apart and assumed to be possible
+        (Condition::AtLeast(_, _), false) => Some(vec![Vec::new()]),
+        (comparison, holds) => match FactType::checked_by(comparison) {
+            Some((fact_name, fact_type)) => Some(vec![vec![Literal {
+                comparison,
+                fact_name,
+                fact_type,
+                holds,
+            }]]),
+            None => Some(vec![Vec::new()]),
+        },
+    }
+}
+
+// The ways to choose `count` of `total` indices, None when there are more than MAX_ALTERNATIVES
+fn combinations(total: usize, count: usize) -> Option<Vec<Vec<usize>>> {
+    fn choose(start: usize, total: usize, count: usize, chosen: &mut Vec<usize>, found: &mut Vec<Vec<usize>>) -> bool {
+        if chosen.len() == count {
+            found.push(chosen.clone());
+            return found.len() <= MAX_ALTERNATIVES;
+        }
+        for index in start..total {
+            chosen.push(index);
+            let within_limit = choose(index + 1, total, count, chosen, found);
+            chosen.pop();
+            if !within_limit {
+                return false;
+            }
+        }
+        true
+    }
+    let mut found = Vec::new();
+    choose(0, total, count, &mut Vec::new(), &mut found).then_some(found)
+}
+
+// Why no value of a free fact satisfies the comparisons on it, None when some value does. A fact
+// with only comparisons that must not hold can be absent, which satisfies all of them.
+fn symbolic_contradiction(name: &str, literals: &[Literal]) -> Option<String> {
+    let mut types: Vec<FactType> = Vec::new();
+    for literal in literals.iter().filter(|literal| literal.holds) {
+        if !types.contains(&literal.fact_type) {
+            types.push(literal.fact_type);
+        }
+    }
+    if let [first, second, ..] = types.as_slice() {
+        return Some(format!("'{}' would have to be both {} and {}", name, first, second));
+    }
+    let fact_type = *types.first()?;
+    // Comparisons on another type never hold, which is what those that must not hold want
+    let literals: Vec<&Literal> = literals.iter().filter(|literal| literal.fact_type == fact_type).collect();
+    let possible = match fact_type {
+        FactType::Int => int_possible(&literals),
+        FactType::Float => float_possible(&literals),
+        FactType::Bool => bool_possible(&literals),
+        FactType::String => string_possible(&literals),
+        FactType::StringList => list_possible(&literals),
+    };
+    if possible {
+        return None;
+    }
+    let comparisons: Vec<String> = literals.iter().map(|literal| literal.to_string()).collect();
+    Some(format!("no value of '{}' satisfies {}", name, comparisons.join(" and ")))
+}
+
+fn int_possible(literals: &[&Literal]) -> bool {
+    let (mut low, mut high) = (i32::MIN as i64, i32::MAX as i64);
+    let mut excluded: HashSet<i64> = HashSet::new();
+    for literal in literals {
+        match (literal.comparison, literal.holds) {
+            (Condition::IntEquals { expected_value, .. }, true) => {
+                low = low.max(*expected_value as i64);
+                high = high.min(*expected_value as i64);
+            }
+            (Condition::IntEquals { expected_value, .. }, false) => {
+                excluded.insert(*expected_value as i64);
+            }
+            (Condition::IntMoreThan { expected_value, .. }, true) => low = low.max(*expected_value as i64 + 1),
+            (Condition::IntMoreThan { expected_value, .. }, false) => high = high.min(*expected_value as i64),
+            (Condition::IntLessThan { expected_value, .. }, true) => high = high.min(*expected_value as i64 - 1),
+            (Condition::IntLessThan { expected_value, .. }, false) => low = low.max(*expected_value as i64),
+            _ => {}
+        }
+    }
+    let excluded_in_range = excluded.iter().filter(|value| (low..=high).contains(*value)).count() as i64;
+    low <= high && high - low + 1 > excluded_in_range
+}
+
+// A bound of a range of floats, and whether it is included
+type FloatBound = (f32, bool);
+
+fn raise_low(low: &mut FloatBound, bound: FloatBound) {
+    if bound.0 > low.0 || (bound.0 == low.0 && !bound.1) {
+        *low = bound;
+    }
+}
+
+fn lower_high(high: &mut FloatBound, bound: FloatBound) {
+    if bound.0 < high.0 || (bound.0 == high.0 && !bound.1) {
+        *high = bound;
+    }
+}
+
+fn float_possible(literals: &[&Literal]) -> bool {
+    let (mut low, mut high) = ((f32::NEG_INFINITY, false), (f32::INFINITY, false));
+    let mut excluded: Vec<(f32, f32)> = Vec::new();
+    for literal in literals {
+        match (literal.comparison, literal.holds) {
+            (Condition::FloatEquals { expected_value, epsilon, .. }, true) => {
+                raise_low(&mut low, (expected_value.0 - epsilon.0, true));
+                lower_high(&mut high, (expected_value.0 + epsilon.0, true));
+            }
+            (Condition::FloatEquals { expected_value, epsilon, .. }, false) => {
+                excluded.push((expected_value.0 - epsilon.0, expected_value.0 + epsilon.0));
+            }
+            (Condition::FloatMoreThan { expected_value, .. }, true) => raise_low(&mut low, (expected_value.0, false)),
+            (Condition::FloatMoreThan { expected_value, .. }, false) => lower_high(&mut high, (expected_value.0, true)),
+            (Condition::FloatLessThan { expected_value, .. }, true) => lower_high(&mut high, (expected_value.0, false)),
+            (Condition::FloatLessThan { expected_value, .. }, false) => raise_low(&mut low, (expected_value.0, true)),
+            _ => {}
+        }
+    }
+    let non_empty = low.0 < high.0 || (low.0 == high.0 && low.1 && high.1);
+    // Only a single excluded window covering the whole range is found, not several together
+    non_empty && !excluded.iter().any(|(from, to)| *from <= low.0 && high.0 <= *to)
+}
+
+fn bool_possible(literals: &[&Literal]) -> bool {
+    let mut required: Option<bool> = None;
+    for literal in literals {
+        if let Condition::BoolEquals { expected_value, .. } = literal.comparison {
+            let value = *expected_value == literal.holds;
+            if required.is_some_and(|required| required != value) {
+                return false;
+            }
+            required = Some(value);
+        }
+    }
+    true
+}
+
+fn string_possible(literals: &[&Literal]) -> bool {
+    let mut equal: Option<&str> = None;
+    let mut not_equal: HashSet<&str> = HashSet::new();
+    for literal in literals {
+        if let Condition::StringEquals { expected_value, .. } = literal.comparison {
+            let expected_value = expected_value.as_str();
+            if !literal.holds {
+                not_equal.insert(expected_value);
+            } else if equal.is_some_and(|equal| equal != expected_value) {
+                return false;
+            } else {
+                equal = Some(expected_value);
+            }
+        }
+    }
+    equal.is_none_or(|equal| !not_equal.contains(equal))
+}
+
+fn list_possible(literals: &[&Literal]) -> bool {
+    let mut contained: HashSet<&str> = HashSet::new();
+    let mut missing: HashSet<&str> = HashSet::new();
+    for literal in literals {
+        if let Condition::ListContains { expected_value, .. } = literal.comparison {
+            if literal.holds {
+                contained.insert(expected_value.as_str());
+            } else {
+                missing.insert(expected_value.as_str());
+            }
+        }
+    }
+    contained.is_disjoint(&missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beats::expression::parse_condition;
+    use crate::beats::parser::parse_stories;
+
+    fn rule(conditions: &[&str]) -> Rule {
+        let conditions = conditions.iter().map(|condition| parse_condition(condition).unwrap()).collect();
+        Rule::new("rule".to_string(), conditions)
+    }
+
+    // Why the conditions can never hold when every fact can have any value
+    fn contradiction(conditions: &[&str]) -> Option<String> {
+        let no_facts = HashMap::new();
+        let known = Known {
+            facts: &no_facts,
+            free: None,
+        };
+        known.unsatisfiable([&rule(conditions)])
+    }
+
+    fn analyze_source(source: &str, facts: &FactsOfTheWorld, schema: &FactSchema) -> AnalysisReport {
+        let mut story_engine = StoryEngine::new();
+        for story in parse_stories(source).unwrap() {
+            if story.is_template() {
+                story_engine.add_template(story);
+            } else {
+                story_engine.add_story(story);
+            }
+        }
+        analyze(&story_engine, facts, schema)
+    }
+
+    fn dead_beats(report: &AnalysisReport) -> Vec<(&str, &str)> {
+        report
+            .dead_beats
+            .iter()
+            .map(|dead_beat| (dead_beat.beat.as_str(), dead_beat.reason.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn finds_contradicting_int_ranges() {
+        assert_eq!(
+            contradiction(&["x > 5 && x < 3"]).unwrap(),
+            "no value of 'x' satisfies `x > 5` and `x < 3`"
+        );
+        assert_eq!(contradiction(&["x > 5", "x < 3"]), contradiction(&["x > 5 && x < 3"]));
+        assert!(contradiction(&["x >= 3 && x <= 3"]).is_none());
+        assert!(contradiction(&["x > 2 && x < 4 && x != 3"]).is_some());
+        assert!(contradiction(&["x > 2 && x < 5 && x != 3"]).is_none());
+        assert!(contradiction(&["x > 2147483647"]).is_some());
+        assert!(contradiction(&["x > 5 || x < 3"]).is_none());
+    }
+
+    #[test]
+    fn finds_contradicting_float_ranges() {
+        assert!(contradiction(&["speed > 1.5 && speed < 1.5"]).is_some());
+        assert!(contradiction(&["speed >= 1.5 && speed <= 1.5"]).is_none());
+        assert!(contradiction(&["speed == 0.5 && speed != 0.5"]).is_some());
+        assert!(contradiction(&["speed == 0.5 within 0.1 && speed > 0.7"]).is_some());
+        assert!(contradiction(&["speed == 0.5 within 0.1 && speed > 0.55"]).is_none());
+    }
+
+    #[test]
+    fn finds_contradicting_bools_strings_and_lists() {
+        assert_eq!(
+            contradiction(&["flag && !flag"]).unwrap(),
+            "no value of 'flag' satisfies `flag == true` and `!(flag == true)`"
+        );
+        assert!(contradiction(&["!flag && !flag"]).is_none());
+        assert!(contradiction(&["name == \"a\" && name == \"b\""]).is_some());
+        assert!(contradiction(&["name == \"a\" && name != \"b\""]).is_none());
+        assert!(contradiction(&["\"torch\" in bag && !(\"torch\" in bag)"]).is_some());
+        assert!(contradiction(&["\"torch\" in bag && !(\"rope\" in bag)"]).is_none());
+        assert_eq!(
+            contradiction(&["gold > 3 && gold"]).unwrap(),
+            "'gold' would have to be both integer and boolean"
+        );
+    }
+
+    #[test]
+    fn expands_at_least() {
+        assert!(contradiction(&["at_least(2, x > 5, x < 3, ready)"]).is_none());
+        assert!(contradiction(&["at_least(2, x > 5, x < 3)"]).is_some());
+        assert!(contradiction(&["at_least(3, x > 5, x < 3, ready)"]).is_some());
+        // Fewer than two holding is not taken apart
+        assert!(contradiction(&["!at_least(2, x > 5, ready) && x > 5 && ready"]).is_none());
+        assert_eq!(combinations(4, 2).unwrap().len(), 6);
+        assert_eq!(combinations(3, 0), Some(vec![Vec::new()]));
+        assert!(combinations(20, 10).is_none());
+    }
+
+    #[test]
+    fn assumes_too_many_alternatives_hold() {
+        // Every `||` doubles the alternatives, nine of them give 512
+        let choices: Vec<String> = (0..9).map(|index| format!("(a{} || b{})", index, index)).collect();
+        let choices = choices.join(" && ");
+        assert!(all_of([&parse_condition(&choices).unwrap()], true).is_none());
+        assert!(contradiction(&[&choices, "x > 5 && x < 3"]).is_none());
+        let condition = parse_condition("(a || b) && (c || d)").unwrap();
+        assert_eq!(all_of([&condition], true).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn reports_contradicting_rules_of_beats() {
+        let report = analyze_source(
+            "# Forge\n## Light\nHot:\n    heat > 5\n    heat < 3\n",
+            &FactsOfTheWorld::new(),
+            &FactSchema::new().with_fact("heat", FactType::Int),
+        );
+        assert_eq!(report.contradictions.len(), 1);
+        assert_eq!(report.contradictions[0].beat.as_deref(), Some("Light"));
+        assert_eq!(report.contradictions[0].rule, "Hot");
+        assert!(report.dead_beats.is_empty());
+    }
+
+    #[test]
+    fn finds_unreachable_branch_targets() {
+        let report = analyze_source(
+            "# Forge\n## Light\nEffects:\n    heat = 2\nHot -> Hammer:\n    heat > 5\nCold -> END:\n    heat < 5\n## Hammer\n## Quench\n",
+            &FactsOfTheWorld::new(),
+            &FactSchema::new(),
+        );
+        assert!(report.contradictions.is_empty());
+        assert_eq!(
+            dead_beats(&report),
+            vec![
+                ("Hammer", "from 'Light', transition 'Hot' never holds, 'heat' is never set so `heat > 5` doesn't hold"),
+                ("Quench", "'Hammer' is never reached"),
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_effects_before_later_beats() {
+        let source = "# Forge\n## Light\nEffects:\n    heat = 7\n## Hammer\nHot:\n    heat > 5\n## Quench\n";
+        let report = analyze_source(source, &FactsOfTheWorld::new(), &FactSchema::new());
+        assert!(report.is_empty(), "{}", report);
+
+        let source = "# Forge\n## Light\nEffects:\n    heat = 2\n## Hammer\nHot:\n    heat > 5\n## Quench\n";
+        let report = analyze_source(source, &FactsOfTheWorld::new(), &FactSchema::new());
+        assert_eq!(
+            dead_beats(&report),
+            vec![("Quench", "'Hammer' never finishes, 'heat' is 2 so `heat > 5` doesn't hold")]
+        );
+        // Unless the fact is set in code
+        let schema = FactSchema::new().with_fact("heat", FactType::Int);
+        assert!(analyze_source(source, &FactsOfTheWorld::new(), &schema).is_empty());
+    }
+
+    #[test]
+    fn completes_objectives_after_the_effects_of_others() {
+        let source = "# Forge\n## Smith\n- Second:\n    heat > 5\n- First:\n    ready\n    => heat = 7\n## Sell\n";
+        let schema = FactSchema::new().with_fact("ready", FactType::Bool);
+        let report = analyze_source(source, &FactsOfTheWorld::new(), &schema);
+        assert!(report.is_empty(), "{}", report);
+    }
+
+    #[test]
+    fn checks_failures_after_objectives() {
+        let source = "# Forge\n## Smith\n- Heat:\n    ready\n    => heat = 7\n! Burnt -> Heal:\n    heat > 5\nNever:\n    ready && !ready\n## Heal\n";
+        let schema = FactSchema::new().with_fact("ready", FactType::Bool);
+        let report = analyze_source(source, &FactsOfTheWorld::new(), &schema);
+        assert_eq!(report.contradictions.len(), 1);
+        assert!(report.dead_beats.is_empty(), "{}", report);
+    }
+
+    #[test]
+    fn reports_stories_that_never_start() {
+        let mut facts = FactsOfTheWorld::new();
+        facts.store_int("level".to_string(), 1);
+        let report = analyze_source("# Forge\nStart:\n    level > 3\n## Light\n", &facts, &FactSchema::new());
+        assert_eq!(
+            dead_beats(&report),
+            vec![("Light", "the story can never start, 'level' is 1 so `level > 3` doesn't hold")]
+        );
+    }
+
+    #[test]
+    fn treats_template_parameters_as_free() {
+        let report = analyze_source(
+            "# Greet {npc} (npc)\nMet:\n    {npc}_met\n## Wave\n",
+            &FactsOfTheWorld::new(),
+            &FactSchema::new(),
+        );
+        assert!(report.is_empty(), "{}", report);
+    }
+
+    #[test]
+    fn stops_at_max_states() {
+        let report = analyze_source(
+            "# Counter\n## Count\nEffects:\n    count += 1\nAgain -> Count:\nDone -> Done:\n    count < 0\n## Done\n",
+            &FactsOfTheWorld::new(),
+            &FactSchema::new(),
+        );
+        assert!(report.dead_beats.is_empty());
+        assert_eq!(report.undecided_beats.len(), 1);
+        assert_eq!(report.undecided_beats[0].beat, "Done");
+    }
+}
@@ -0,0 +1,262 @@
+use crate::beats::conversations::{parse_conversations, Conversation};
+use crate::beats::data::Story;
+use crate::beats::dialogue::{parse_responses, Response};
+use crate::beats::parser::{parse_stories, StoryParseError};
+use crate::beats::storylets::Storylet;
+use bevy::asset::io::Reader;
+use bevy::asset::{Asset, AssetLoader, AsyncReadExt, LoadContext};
+use bevy::reflect::TypePath;
+use bevy::utils::BoxedFuture;
+use std::fmt::{Display, Formatter};
+
+// All stories declared in a single story file
+#[derive(Asset, TypePath, Debug, Clone)]
+pub struct StoryAsset {
+    pub stories: Vec<Story>,
+}
+
+// All dialogue responses declared in a single responses file
+#[derive(Asset, TypePath, Debug, Clone)]
+pub struct ResponseAsset {
+    pub responses: Vec<Response>,
+}
+
+// All conversations declared in a single dialogue file
+#[derive(Asset, TypePath, Debug, Clone)]
+pub struct ConversationAsset {
+    pub conversations: Vec<Conversation>,
+}
+
+// All storylets declared in a single storylets file
+#[derive(Asset, TypePath, Debug, Clone)]
+pub struct StoryletAsset {
+    pub storylets: Vec<Storylet>,
+}
+
+#[derive(Debug)]
+pub enum StoryLoaderError {
+    Io(std::io::Error),
+    Utf8(std::string::FromUtf8Error),
+    Parse(StoryParseError),
+    Ron(ron::error::SpannedError),
+    // A transition or failure of a RON story leads to a beat the story doesn't have
+    UnknownBeat { story: String, from: String, target: String },
+}
+
+impl Display for StoryLoaderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoryLoaderError::Io(error) => write!(f, "could not read story file: {}", error),
+            StoryLoaderError::Utf8(error) => write!(f, "story file is not valid UTF-8: {}", error),
+            StoryLoaderError::Parse(error) => write!(f, "could not parse story file: {}", error),
+            StoryLoaderError::Ron(error) => write!(f, "could not parse story file: {}", error),
+            StoryLoaderError::UnknownBeat { story, from, target } => write!(
+                f,
+                "story '{}': '{}' leads to '{}', which is not a beat of this story",
+                story, from, target
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StoryLoaderError {}
+
+impl From<std::io::Error> for StoryLoaderError {
+    fn from(error: std::io::Error) -> Self {
+        StoryLoaderError::Io(error)
+    }
+}
+
+impl From<std::string::FromUtf8Error> for StoryLoaderError {
+    fn from(error: std::string::FromUtf8Error) -> Self {
+        StoryLoaderError::Utf8(error)
+    }
+}
+
+impl From<StoryParseError> for StoryLoaderError {
+    fn from(error: StoryParseError) -> Self {
+        StoryLoaderError::Parse(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for StoryLoaderError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        StoryLoaderError::Ron(error)
+    }
+}
+
+// Loads the markdown-like `.story` files, see crate::beats::parser for the format
+#[derive(Default)]
+pub struct StoryLoader;
+
+impl AssetLoader for StoryLoader {
+    type Asset = StoryAsset;
+    type Settings = ();
+    type Error = StoryLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let stories = parse_stories(&String::from_utf8(bytes)?)?;
+            Ok(StoryAsset { stories })
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["story"]
+    }
+}
+
+// Loads `.stories.ron` files, a serialized list of Story values for tools and designers
+#[derive(Default)]
+pub struct RonStoryLoader;
+
+impl AssetLoader for RonStoryLoader {
+    type Asset = StoryAsset;
+    type Settings = ();
+    type Error = StoryLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let stories = ron::de::from_bytes::<Vec<Story>>(&bytes)?;
+            check_targets(&stories)?;
+            Ok(StoryAsset { stories })
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["stories.ron"]
+    }
+}
+
+// The `.story` parser rejects transitions and recoveries to beats that don't exist, RON stories are
+// checked after reading them
+fn check_targets(stories: &[Story]) -> Result<(), StoryLoaderError> {
+    for story in stories {
+        if let Some((from, target)) = story.unknown_targets().first() {
+            return Err(StoryLoaderError::UnknownBeat {
+                story: story.name.clone(),
+                from: from.to_string(),
+                target: target.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+// Loads `.responses` files, see crate::beats::dialogue for the format
+#[derive(Default)]
+pub struct ResponseLoader;
+
+impl AssetLoader for ResponseLoader {
+    type Asset = ResponseAsset;
+    type Settings = ();
+    type Error = StoryLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let responses = parse_responses(&String::from_utf8(bytes)?)?;
+            Ok(ResponseAsset { responses })
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["responses"]
+    }
+}
+
+// Loads `.dialogue` files, see crate::beats::conversations for the format
+#[derive(Default)]
+pub struct ConversationLoader;
+
+impl AssetLoader for ConversationLoader {
+    type Asset = ConversationAsset;
+    type Settings = ();
+    type Error = StoryLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let conversations = parse_conversations(&String::from_utf8(bytes)?)?;
+            Ok(ConversationAsset { conversations })
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["dialogue"]
+    }
+}
+
+// Loads `.storylets.ron` files, a serialized list of Storylet values
+#[derive(Default)]
+pub struct RonStoryletLoader;
+
+impl AssetLoader for RonStoryletLoader {
+    type Asset = StoryletAsset;
+    type Settings = ();
+    type Error = StoryLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            let storylets = ron::de::from_bytes::<Vec<Storylet>>(&bytes)?;
+            Ok(StoryletAsset { storylets })
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["storylets.ron"]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beats::data::Failure;
+
+    #[test]
+    fn rejects_ron_stories_leading_to_unknown_beats() {
+        let mut stories: Vec<Story> = ron::from_str(include_str!("../../assets/side_quests.stories.ron")).unwrap();
+        assert!(check_targets(&stories).is_ok());
+
+        let lost = Failure::new("Lost".to_string(), Vec::new(), Vec::new(), Some("Sea".to_string()));
+        stories[0].beats[0].failures.push(lost);
+        let error = check_targets(&stories).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "story 'The Lost Barnacle': 'Lost' leads to 'Sea', which is not a beat of this story"
+        );
+    }
+}
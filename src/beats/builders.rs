@@ -1,5 +1,7 @@
 use bevy::utils::HashSet;
-use crate::beats::data::{Condition, Effect, Fact, Rule, Story, StoryBeat, StringHashSet};
+use crate::beats::data::{CompletionPolicy, Condition, Effect, Fact, Failure, HashableFloat, Objective, Rule, Story, StoryBeat, StringHashSet, Transition};
+use crate::beats::expression::parse_condition;
+use crate::beats::storylets::Storylet;
 
 #[derive(Debug, Default)]
 pub struct EffectBuilder {
@@ -28,11 +30,80 @@ impl EffectBuilder {
         self
     }
 
+    pub fn set_fact_float(mut self, name: impl Into<String>, value: f32) -> Self {
+        self.effects.push(Effect::SetFact(Fact::Float(name.into(), HashableFloat(value))));
+        self
+    }
+
     pub fn set_fact_string_list(mut self, name: impl Into<String>, values: HashSet<String>) -> Self {
         self.effects.push(Effect::SetFact(Fact::StringList(name.into(), StringHashSet(values))));
         self
     }
 
+    pub fn add_to_int(mut self, name: impl Into<String>, value: i32) -> Self {
+        self.effects.push(Effect::AddInt { fact_name: name.into(), value });
+        self
+    }
+
+    pub fn subtract_from_int(mut self, name: impl Into<String>, value: i32) -> Self {
+        self.effects.push(Effect::SubtractInt { fact_name: name.into(), value });
+        self
+    }
+
+    pub fn multiply_int(mut self, name: impl Into<String>, value: i32) -> Self {
+        self.effects.push(Effect::MultiplyInt { fact_name: name.into(), value });
+        self
+    }
+
+    pub fn clamp_int(mut self, name: impl Into<String>, min: i32, max: i32) -> Self {
+        self.effects.push(Effect::ClampInt { fact_name: name.into(), min, max });
+        self
+    }
+
+    pub fn toggle_bool(mut self, name: impl Into<String>) -> Self {
+        self.effects.push(Effect::ToggleBool { fact_name: name.into() });
+        self
+    }
+
+    pub fn remove_from_list(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.effects.push(Effect::RemoveFromList { fact_name: name.into(), value: value.into() });
+        self
+    }
+
+    pub fn clear_list(mut self, name: impl Into<String>) -> Self {
+        self.effects.push(Effect::ClearList { fact_name: name.into() });
+        self
+    }
+
+    pub fn remove_fact(mut self, name: impl Into<String>) -> Self {
+        self.effects.push(Effect::RemoveFact { fact_name: name.into() });
+        self
+    }
+
+    pub fn add_to_float(mut self, name: impl Into<String>, value: f32) -> Self {
+        self.effects.push(Effect::AddFloat { fact_name: name.into(), value: HashableFloat(value) });
+        self
+    }
+
+    pub fn subtract_from_float(mut self, name: impl Into<String>, value: f32) -> Self {
+        self.effects.push(Effect::SubtractFloat { fact_name: name.into(), value: HashableFloat(value) });
+        self
+    }
+
+    pub fn multiply_float(mut self, name: impl Into<String>, value: f32) -> Self {
+        self.effects.push(Effect::MultiplyFloat { fact_name: name.into(), value: HashableFloat(value) });
+        self
+    }
+
+    pub fn clamp_float(mut self, name: impl Into<String>, min: f32, max: f32) -> Self {
+        self.effects.push(Effect::ClampFloat {
+            fact_name: name.into(),
+            min: HashableFloat(min),
+            max: HashableFloat(max),
+        });
+        self
+    }
+
     pub fn build(self) -> Vec<Effect> {
         self.effects
     }
@@ -43,6 +114,11 @@ pub struct StoryBeatBuilder {
     name: String,
     rules: Vec<Rule>,
     effects: Vec<Effect>,
+    objectives: Vec<Objective>,
+    completion: CompletionPolicy,
+    failures: Vec<Failure>,
+    transitions: Vec<Transition>,
+    conversation: Option<String>,
 }
 
 impl StoryBeatBuilder {
@@ -51,6 +127,11 @@ impl StoryBeatBuilder {
             name: name.into(),
             rules: Vec::new(),
             effects: Vec::new(),
+            objectives: Vec::new(),
+            completion: CompletionPolicy::All,
+            failures: Vec::new(),
+            transitions: Vec::new(),
+            conversation: None,
         }
     }
     pub fn with_rule<F>(mut self, name: impl Into<String>, build_fn: F) -> Self
@@ -73,14 +154,224 @@ impl StoryBeatBuilder {
         self
     }
 
+    pub fn with_objective<F>(mut self, name: impl Into<String>, build_fn: F) -> Self
+        where
+            F: FnOnce(ObjectiveBuilder) -> ObjectiveBuilder,
+    {
+        let builder = ObjectiveBuilder::new(name.into());
+        self.objectives.push(build_fn(builder).build());
+        self
+    }
+
+    // How many objectives must be completed before the beat can finish, all of them by default
+    pub fn with_completion(mut self, completion: CompletionPolicy) -> Self {
+        self.completion = completion;
+        self
+    }
+
+    // Fails the story while this beat is active, unless the failure recovers with another beat
+    pub fn with_failure<F>(mut self, name: impl Into<String>, build_fn: F) -> Self
+        where
+            F: FnOnce(FailureBuilder) -> FailureBuilder,
+    {
+        let builder = FailureBuilder::new(name.into());
+        self.failures.push(build_fn(builder).build());
+        self
+    }
+
+    // Continues with the target beat once the beat is finished and the rule built by the closure holds,
+    // the first transition that holds is taken
+    pub fn with_transition<F>(mut self, name: impl Into<String>, target: impl Into<String>, build_fn: F) -> Self
+        where
+            F: FnOnce(RuleBuilder) -> RuleBuilder,
+    {
+        let name = name.into();
+        let rule = build_fn(RuleBuilder::new(name.clone())).build();
+        self.transitions.push(Transition::new(name, vec![rule], Some(target.into())));
+        self
+    }
+
+    // Like with_transition, but ends the story instead of continuing with another beat
+    pub fn with_ending<F>(mut self, name: impl Into<String>, build_fn: F) -> Self
+        where
+            F: FnOnce(RuleBuilder) -> RuleBuilder,
+    {
+        let name = name.into();
+        let rule = build_fn(RuleBuilder::new(name.clone())).build();
+        self.transitions.push(Transition::new(name, vec![rule], None));
+        self
+    }
+
+    // The conversation starts when the beat is activated
+    pub fn with_conversation(mut self, name: impl Into<String>) -> Self {
+        self.conversation = Some(name.into());
+        self
+    }
+
     pub fn build(self) -> StoryBeat {
         StoryBeat {
             name: self.name,
             rules: self.rules,
             effects: self.effects,
+            objectives: self.objectives,
+            completion: self.completion,
+            failures: self.failures,
+            transitions: self.transitions,
             finished: false,
+            conversation: self.conversation,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ObjectiveBuilder {
+    name: String,
+    rules: Vec<Rule>,
+    effects: Vec<Effect>,
+}
+
+impl ObjectiveBuilder {
+    pub fn new(name: impl Into<String>) -> Self {
+        ObjectiveBuilder {
+            name: name.into(),
+            rules: Vec::new(),
+            effects: Vec::new(),
+        }
+    }
+
+    pub fn with_rule<F>(mut self, name: impl Into<String>, build_fn: F) -> Self
+        where
+            F: FnOnce(RuleBuilder) -> RuleBuilder,
+    {
+        let builder = RuleBuilder::new(name.into());
+        self.rules.push(build_fn(builder).build());
+        self
+    }
+
+    pub fn with_effects<F>(mut self, build_fn: F) -> Self
+        where
+            F: FnOnce(EffectBuilder) -> EffectBuilder,
+    {
+        let builder = EffectBuilder::new();
+        self.effects.extend(build_fn(builder).build());
+        self
+    }
+
+    pub fn build(self) -> Objective {
+        Objective::new(self.name, self.rules, self.effects)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct StoryletBuilder {
+    name: String,
+    rules: Vec<Rule>,
+    effects: Vec<Effect>,
+    weight: u32,
+    cooldown: u64,
+    one_shot: bool,
+}
+
+impl StoryletBuilder {
+    pub fn new(name: impl Into<String>) -> Self {
+        StoryletBuilder {
+            name: name.into(),
+            rules: Vec::new(),
+            effects: Vec::new(),
+            weight: 1,
+            cooldown: 0,
+            one_shot: false,
+        }
+    }
+
+    pub fn with_rule<F>(mut self, name: impl Into<String>, build_fn: F) -> Self
+        where
+            F: FnOnce(RuleBuilder) -> RuleBuilder,
+    {
+        let builder = RuleBuilder::new(name.into());
+        self.rules.push(build_fn(builder).build());
+        self
+    }
+
+    pub fn with_effects<F>(mut self, build_fn: F) -> Self
+        where
+            F: FnOnce(EffectBuilder) -> EffectBuilder,
+    {
+        let builder = EffectBuilder::new();
+        self.effects.extend(build_fn(builder).build());
+        self
+    }
+
+    pub fn with_weight(mut self, weight: u32) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    // The number of picks from the pool before the storylet is available again
+    pub fn with_cooldown(mut self, cooldown: u64) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    pub fn one_shot(mut self) -> Self {
+        self.one_shot = true;
+        self
+    }
+
+    pub fn build(self) -> Storylet {
+        let mut storylet = Storylet::new(self.name, self.rules, self.effects);
+        storylet.weight = self.weight;
+        storylet.cooldown = self.cooldown;
+        storylet.one_shot = self.one_shot;
+        storylet
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct FailureBuilder {
+    name: String,
+    rules: Vec<Rule>,
+    effects: Vec<Effect>,
+    recovery: Option<String>,
+}
+
+impl FailureBuilder {
+    pub fn new(name: impl Into<String>) -> Self {
+        FailureBuilder {
+            name: name.into(),
+            rules: Vec::new(),
+            effects: Vec::new(),
+            recovery: None,
         }
     }
+
+    pub fn with_rule<F>(mut self, name: impl Into<String>, build_fn: F) -> Self
+        where
+            F: FnOnce(RuleBuilder) -> RuleBuilder,
+    {
+        let builder = RuleBuilder::new(name.into());
+        self.rules.push(build_fn(builder).build());
+        self
+    }
+
+    pub fn with_effects<F>(mut self, build_fn: F) -> Self
+        where
+            F: FnOnce(EffectBuilder) -> EffectBuilder,
+    {
+        let builder = EffectBuilder::new();
+        self.effects.extend(build_fn(builder).build());
+        self
+    }
+
+    // Continues with this beat instead of failing the story
+    pub fn with_recovery(mut self, beat: impl Into<String>) -> Self {
+        self.recovery = Some(beat.into());
+        self
+    }
+
+    pub fn build(self) -> Failure {
+        Failure::new(self.name, self.rules, self.effects, self.recovery)
+    }
 }
 
 #[derive(Debug, Default)]
@@ -102,6 +393,47 @@ impl RuleBuilder {
         self
     }
 
+    // Panics when the expression is invalid, use parse_condition to handle the error instead
+    pub fn with_expr(mut self, expression: &str) -> Self {
+        match parse_condition(expression) {
+            Ok(condition) => self.conditions.push(condition),
+            Err(error) => panic!("Invalid condition expression, {}", error),
+        }
+        self
+    }
+
+    pub fn with_all<F>(mut self, build_fn: F) -> Self
+        where
+            F: FnOnce(ConditionBuilder) -> ConditionBuilder,
+    {
+        self.conditions.push(ConditionBuilder::new().with_all(build_fn).build_one());
+        self
+    }
+
+    pub fn with_any<F>(mut self, build_fn: F) -> Self
+        where
+            F: FnOnce(ConditionBuilder) -> ConditionBuilder,
+    {
+        self.conditions.push(ConditionBuilder::new().with_any(build_fn).build_one());
+        self
+    }
+
+    pub fn with_not<F>(mut self, build_fn: F) -> Self
+        where
+            F: FnOnce(ConditionBuilder) -> ConditionBuilder,
+    {
+        self.conditions.push(ConditionBuilder::new().with_not(build_fn).build_one());
+        self
+    }
+
+    pub fn with_at_least<F>(mut self, count: usize, build_fn: F) -> Self
+        where
+            F: FnOnce(ConditionBuilder) -> ConditionBuilder,
+    {
+        self.conditions.push(ConditionBuilder::new().with_at_least(count, build_fn).build_one());
+        self
+    }
+
     pub fn build(self) -> Rule {
         Rule {
             name: self.name,
@@ -110,11 +442,82 @@ impl RuleBuilder {
     }
 }
 
+// Builds the conditions of a group, groups can be nested to any depth
+#[derive(Debug, Default)]
+pub struct ConditionBuilder {
+    conditions: Vec<Condition>,
+}
+
+impl ConditionBuilder {
+    pub fn new() -> Self {
+        ConditionBuilder {
+            conditions: Vec::new(),
+        }
+    }
+
+    pub fn with_condition(mut self, condition: Condition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    pub fn with_all<F>(mut self, build_fn: F) -> Self
+        where
+            F: FnOnce(ConditionBuilder) -> ConditionBuilder,
+    {
+        let conditions = build_fn(ConditionBuilder::new()).build();
+        self.conditions.push(Condition::All(conditions));
+        self
+    }
+
+    pub fn with_any<F>(mut self, build_fn: F) -> Self
+        where
+            F: FnOnce(ConditionBuilder) -> ConditionBuilder,
+    {
+        let conditions = build_fn(ConditionBuilder::new()).build();
+        self.conditions.push(Condition::Any(conditions));
+        self
+    }
+
+    // Negates the conditions built by the closure, several of them are negated as a whole
+    pub fn with_not<F>(mut self, build_fn: F) -> Self
+        where
+            F: FnOnce(ConditionBuilder) -> ConditionBuilder,
+    {
+        let condition = build_fn(ConditionBuilder::new()).build_one();
+        self.conditions.push(Condition::Not(Box::new(condition)));
+        self
+    }
+
+    pub fn with_at_least<F>(mut self, count: usize, build_fn: F) -> Self
+        where
+            F: FnOnce(ConditionBuilder) -> ConditionBuilder,
+    {
+        let conditions = build_fn(ConditionBuilder::new()).build();
+        self.conditions.push(Condition::AtLeast(count, conditions));
+        self
+    }
+
+    pub fn build(self) -> Vec<Condition> {
+        self.conditions
+    }
+
+    // A single condition stays as it is, several are combined with All
+    pub fn build_one(mut self) -> Condition {
+        if self.conditions.len() == 1 {
+            self.conditions.remove(0)
+        } else {
+            Condition::All(self.conditions)
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct StoryBuilder {
     name: String,
     pre_requisites: Vec<Rule>,
     beats: Vec<StoryBeat>,
+    failures: Vec<Failure>,
+    parameters: Vec<String>,
 }
 
 impl StoryBuilder {
@@ -123,9 +526,17 @@ impl StoryBuilder {
             name: name.into(),
             beats: Vec::new(),
             pre_requisites: Vec::new(),
+            failures: Vec::new(),
+            parameters: Vec::new(),
         }
     }
 
+    // Makes the story a template, `{name}` is filled in by StoryEngine::instantiate
+    pub fn add_parameter(mut self, name: impl Into<String>) -> Self {
+        self.parameters.push(name.into());
+        self
+    }
+
     pub fn add_story_beat<F>(mut self, name: impl Into<String>, build_fn: F) -> Self
         where
             F: FnOnce(StoryBeatBuilder) -> StoryBeatBuilder,
@@ -146,7 +557,82 @@ impl StoryBuilder {
         self
     }
 
+    // Fails the story whatever beat is active, unless the failure recovers with a beat
+    pub fn add_failure<F>(mut self, name: impl Into<String>, build_fn: F) -> Self
+        where
+            F: FnOnce(FailureBuilder) -> FailureBuilder,
+    {
+        let builder = FailureBuilder::new(name.into());
+        self.failures.push(build_fn(builder).build());
+        self
+    }
+
+    // Adds a transition to a beat that was added before, so the edges of the graph can be declared
+    // after all of its beats. Panics when there is no beat named `from`.
+    pub fn add_transition<F>(
+        mut self,
+        from: &str,
+        name: impl Into<String>,
+        target: impl Into<String>,
+        build_fn: F,
+    ) -> Self
+        where
+            F: FnOnce(RuleBuilder) -> RuleBuilder,
+    {
+        let name = name.into();
+        let rule = build_fn(RuleBuilder::new(name.clone())).build();
+        self.beat_mut(from)
+            .transitions
+            .push(Transition::new(name, vec![rule], Some(target.into())));
+        self
+    }
+
+    // Adds a transition that ends the story to a beat that was added before
+    pub fn add_ending<F>(mut self, from: &str, name: impl Into<String>, build_fn: F) -> Self
+        where
+            F: FnOnce(RuleBuilder) -> RuleBuilder,
+    {
+        let name = name.into();
+        let rule = build_fn(RuleBuilder::new(name.clone())).build();
+        self.beat_mut(from).transitions.push(Transition::new(name, vec![rule], None));
+        self
+    }
+
+    fn beat_mut(&mut self, name: &str) -> &mut StoryBeat {
+        let story_name = &self.name;
+        self.beats
+            .iter_mut()
+            .find(|beat| beat.name == name)
+            .unwrap_or_else(|| panic!("Story '{}' has no beat named '{}'", story_name, name))
+    }
+
+    // Panics when a transition or a failure leads to a beat that doesn't exist
     pub fn build(self) -> Story {
-        Story::new(self.name, self.pre_requisites, self.beats)
+        for beat in &self.beats {
+            let targets = beat
+                .transitions
+                .iter()
+                .filter_map(|transition| transition.target.as_ref())
+                .chain(beat.failures.iter().filter_map(|failure| failure.recovery.as_ref()));
+            for target in targets {
+                self.check_beat_exists(&beat.name, target);
+            }
+        }
+        for recovery in self.failures.iter().filter_map(|failure| failure.recovery.as_ref()) {
+            self.check_beat_exists(&self.name, recovery);
+        }
+        let mut story = Story::new(self.name, self.pre_requisites, self.beats);
+        story.failures = self.failures;
+        story.parameters = self.parameters;
+        story
+    }
+
+    fn check_beat_exists(&self, from: &str, target: &str) {
+        if !self.beats.iter().any(|beat| beat.name == target) {
+            panic!(
+                "Story '{}': '{}' leads to '{}', which is not a beat of the story",
+                self.name, from, target
+            );
+        }
     }
 }
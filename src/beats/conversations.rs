@@ -0,0 +1,445 @@
+use crate::beats::assets::ConversationAsset;
+use crate::beats::data::{BeatActivated, Effect, FactError, FactErrorOccurred, FactErrorSource, FactSource, FactsOfTheWorld, Rule, StoryEngine};
+use crate::beats::parser::{
+    condition_or_effect_line, effect_line, heading, parse_line, rest_of_line, transition_heading, ConditionOrEffect, ParseResult,
+    StoryParseError, END_TARGET,
+};
+use crate::loading::StoryAssets;
+use bevy::log::{info, warn};
+use bevy::prelude::{Assets, Event, EventReader, EventWriter, Res, ResMut, Resource};
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_till1};
+use nom::character::complete::{char, space0, space1};
+use nom::combinator::{all_consuming, cut, map};
+use nom::sequence::{preceded, separated_pair, terminated, tuple};
+use serde::{Deserialize, Serialize};
+
+/*
+Conversations are trees of dialogue nodes with choices for the player, written in `.dialogue`
+files:
+
+# Smith Greeting                  <- starts a new conversation
+## Welcome                        <- a node, the conversation starts at the first one
+Smith: Welcome, traveller.        <- a line and who says it
+Smith: What brings you here?
+- I need a sword -> Swords:       <- a choice leading to another node, END ends the conversation
+    gold >= 10                    <- conditions, the choice is only offered when they hold
+    => gold -= 10                 <- effects applied when the choice is made
+- Just looking -> END:
+## Swords
+Smith: A fine choice.
+=> has_sword = true               <- effects applied when the node is reached
+
+A node without choices continues with the next node in the file, and the last one ends the
+conversation. A beat starts a conversation with a `Conversation: Smith Greeting` line.
+ */
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct DialogueLine {
+    pub speaker: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct DialogueChoice {
+    pub text: String,
+    // The choice is only offered when the rules hold
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    #[serde(default)]
+    pub effects: Vec<Effect>,
+    // The node the choice leads to, None ends the conversation
+    #[serde(default)]
+    pub target: Option<String>,
+}
+
+impl DialogueChoice {
+    pub fn new(text: String, target: Option<String>) -> Self {
+        DialogueChoice {
+            text: text.clone(),
+            rules: vec![Rule::new(text, Vec::new())],
+            effects: Vec::new(),
+            target,
+        }
+    }
+
+    pub fn is_available(&self, facts: &dyn FactSource) -> bool {
+        self.rules.iter().all(|rule| rule.evaluate(facts))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct DialogueNode {
+    pub name: String,
+    pub lines: Vec<DialogueLine>,
+    // Applied when the conversation reaches the node
+    #[serde(default)]
+    pub effects: Vec<Effect>,
+    #[serde(default)]
+    pub choices: Vec<DialogueChoice>,
+}
+
+impl DialogueNode {
+    pub fn new(name: String) -> Self {
+        DialogueNode {
+            name,
+            lines: Vec::new(),
+            effects: Vec::new(),
+            choices: Vec::new(),
+        }
+    }
+
+    // The indices of the choices the player can make right now
+    pub fn available_choices(&self, facts: &dyn FactSource) -> Vec<usize> {
+        self.choices
+            .iter()
+            .enumerate()
+            .filter(|(_, choice)| choice.is_available(facts))
+            .map(|(index, _)| index)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct Conversation {
+    pub name: String,
+    pub nodes: Vec<DialogueNode>,
+}
+
+impl Conversation {
+    pub fn new(name: String, nodes: Vec<DialogueNode>) -> Self {
+        Conversation { name, nodes }
+    }
+
+    pub fn node(&self, name: &str) -> Option<&DialogueNode> {
+        self.nodes.iter().find(|node| node.name == name)
+    }
+
+    fn node_index(&self, name: &str) -> Option<usize> {
+        self.nodes.iter().position(|node| node.name == name)
+    }
+}
+
+// All conversations that can be started
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Conversations {
+    pub conversations: Vec<Conversation>,
+}
+
+impl Conversations {
+    pub fn add_conversation(&mut self, conversation: Conversation) {
+        self.conversations.push(conversation);
+    }
+
+    pub fn conversation(&self, name: &str) -> Option<&Conversation> {
+        self.conversations.iter().find(|conversation| conversation.name == name)
+    }
+}
+
+// The conversation the player is in and the node it is at, if any
+#[derive(Resource, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ActiveConversation {
+    pub conversation: Option<(usize, usize)>,
+}
+
+impl ActiveConversation {
+    pub fn current<'a>(&self, conversations: &'a Conversations) -> Option<(&'a Conversation, &'a DialogueNode)> {
+        let (conversation, node) = self.conversation?;
+        let conversation = conversations.conversations.get(conversation)?;
+        Some((conversation, conversation.nodes.get(node)?))
+    }
+
+    // Moves to the node and applies its effects
+    fn enter(&mut self, conversations: &Conversations, conversation: usize, node: usize, facts: &mut FactsOfTheWorld) -> Vec<FactError> {
+        self.conversation = Some((conversation, node));
+        conversations.conversations[conversation].nodes[node]
+            .effects
+            .iter()
+            .filter_map(|effect| effect.apply(facts).err())
+            .collect()
+    }
+}
+
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct StartConversation {
+    pub name: String,
+}
+
+// The index of the choice in the node the conversation is at, nodes without choices continue
+// with any choice
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DialogueChoiceMade {
+    pub choice: usize,
+}
+
+#[derive(Event, Debug, Clone, PartialEq, Eq)]
+pub struct ConversationEnded {
+    pub name: String,
+}
+
+enum DialogueFileLine<'a> {
+    Blank,
+    Conversation(&'a str),
+    Node(&'a str),
+    Line(&'a str, &'a str),
+    Choice(&'a str, &'a str),
+    NodeEffect(Effect),
+    Item(ConditionOrEffect),
+}
+
+// Parses the contents of a `.dialogue` file into the conversations it declares
+pub fn parse_conversations(input: &str) -> Result<Vec<Conversation>, StoryParseError> {
+    let mut conversations: Vec<Conversation> = Vec::new();
+    // Targets are checked once all nodes of a conversation are known
+    let mut target_lines: Vec<(usize, usize, String)> = Vec::new();
+
+    for (index, raw_line) in input.lines().enumerate() {
+        let line_number = index + 1;
+        let line = raw_line.trim_end();
+        let conversation_index = conversations.len().saturating_sub(1);
+        let node = conversations.last_mut().and_then(|conversation| conversation.nodes.last_mut());
+        match parse_line(line_number, line, dialogue_file_line)? {
+            DialogueFileLine::Blank => {}
+            DialogueFileLine::Conversation(name) => {
+                conversations.push(Conversation::new(name.to_string(), Vec::new()));
+            }
+            DialogueFileLine::Node(name) => {
+                let Some(conversation) = conversations.last_mut() else {
+                    return Err(StoryParseError::new(line_number, 1, "a node must belong to a conversation, add a `# Conversation` heading first"));
+                };
+                conversation.nodes.push(DialogueNode::new(name.to_string()));
+            }
+            DialogueFileLine::Line(speaker, text) => {
+                let Some(node) = node else {
+                    return Err(StoryParseError::new(line_number, 1, "a line belongs to a node, add a `## Node` heading first"));
+                };
+                node.lines.push(DialogueLine {
+                    speaker: speaker.trim().to_string(),
+                    text: text.trim().to_string(),
+                });
+            }
+            DialogueFileLine::Choice(text, target) => {
+                let Some(node) = node else {
+                    return Err(StoryParseError::new(line_number, 1, "a choice belongs to a node, add a `## Node` heading first"));
+                };
+                let target = (target != END_TARGET).then(|| target.to_string());
+                if let Some(target) = &target {
+                    target_lines.push((line_number, conversation_index, target.clone()));
+                }
+                node.choices.push(DialogueChoice::new(text.to_string(), target));
+            }
+            DialogueFileLine::NodeEffect(effect) => {
+                let Some(node) = node else {
+                    return Err(StoryParseError::new(line_number, 1, "effects belong to a node, add a `## Node` heading first"));
+                };
+                node.effects.push(effect);
+            }
+            DialogueFileLine::Item(item) => {
+                let Some(choice) = node.and_then(|node| node.choices.last_mut()) else {
+                    return Err(StoryParseError::new(line_number, 1, "indented line outside of a choice"));
+                };
+                match item {
+                    ConditionOrEffect::Condition(condition) => {
+                        if let Some(rule) = choice.rules.last_mut() {
+                            rule.conditions.push(condition);
+                        }
+                    }
+                    ConditionOrEffect::Effect(effect) => choice.effects.push(effect),
+                }
+            }
+        }
+    }
+
+    for (line_number, conversation_index, target) in target_lines {
+        if conversations[conversation_index].node(&target).is_none() {
+            return Err(StoryParseError::new(
+                line_number,
+                1,
+                format!("'{}' is not a node of this conversation", target),
+            ));
+        }
+    }
+    Ok(conversations)
+}
+
+fn dialogue_file_line(input: &str) -> ParseResult<'_, DialogueFileLine<'_>> {
+    alt((
+        map(all_consuming(space0), |_| DialogueFileLine::Blank),
+        map(preceded(tuple((space0, tag("//"))), rest_of_line), |_| DialogueFileLine::Blank),
+        map(condition_or_effect_line, DialogueFileLine::Item),
+        map(heading("##"), DialogueFileLine::Node),
+        map(heading("#"), DialogueFileLine::Conversation),
+        map(preceded(terminated(char('-'), space1), transition_heading), |(text, target)| {
+            DialogueFileLine::Choice(text, target)
+        }),
+        // Node effects are written like the effects of choices, without the indentation
+        map(preceded(tag("=>"), cut(effect_line)), DialogueFileLine::NodeEffect),
+        map(
+            separated_pair(take_till1(|c| c == ':'), char(':'), preceded(space1, rest_of_line)),
+            |(speaker, text)| DialogueFileLine::Line(speaker, text),
+        ),
+    ))(input)
+}
+
+pub fn load_conversations_from_assets(
+    mut conversations: ResMut<Conversations>,
+    story_assets: Res<StoryAssets>,
+    conversation_assets: Res<Assets<ConversationAsset>>,
+) {
+    for conversation_asset in story_assets.conversations.iter().filter_map(|handle| conversation_assets.get(handle)) {
+        conversations.conversations.extend(conversation_asset.conversations.iter().cloned());
+    }
+}
+
+// Starts the conversations of beats that were activated
+pub fn beat_conversation_starter(
+    mut beat_activated: EventReader<BeatActivated>,
+    story_engine: Res<StoryEngine>,
+    mut start_writer: EventWriter<StartConversation>,
+) {
+    for event in beat_activated.read() {
+        if let Some(name) = story_engine.beat(event.beat).and_then(|beat| beat.conversation.clone()) {
+            start_writer.send(StartConversation { name });
+        }
+    }
+}
+
+pub fn conversation_runner(
+    mut start_requests: EventReader<StartConversation>,
+    mut choices: EventReader<DialogueChoiceMade>,
+    conversations: Res<Conversations>,
+    mut active_conversation: ResMut<ActiveConversation>,
+    mut cool_fact_store: ResMut<FactsOfTheWorld>,
+    mut ended_writer: EventWriter<ConversationEnded>,
+    mut fact_error_writer: EventWriter<FactErrorOccurred>,
+) {
+    let mut fact_errors = Vec::new();
+    for request in start_requests.read() {
+        let Some(conversation) = conversations.conversations.iter().position(|conversation| conversation.name == request.name) else {
+            warn!("There is no conversation named '{}'", request.name);
+            continue;
+        };
+        if conversations.conversations[conversation].nodes.is_empty() {
+            continue;
+        }
+        info!("Starting conversation '{}'", request.name);
+        let errors = active_conversation.enter(&conversations, conversation, 0, &mut cool_fact_store);
+        fact_errors.extend(errors.into_iter().map(|error| (request.name.clone(), error)));
+    }
+
+    for made in choices.read() {
+        let Some((conversation, node)) = active_conversation.current(&conversations) else {
+            continue;
+        };
+        let (conversation_index, node_index) = active_conversation.conversation.unwrap_or_default();
+        let next = if node.choices.is_empty() {
+            Some(node_index + 1).filter(|next| *next < conversation.nodes.len())
+        } else {
+            if !node.available_choices(&cool_fact_store.facts).contains(&made.choice) {
+                warn!("Choice {} can't be made in '{}'", made.choice, node.name);
+                continue;
+            }
+            let choice = &node.choices[made.choice];
+            fact_errors.extend(
+                choice
+                    .effects
+                    .iter()
+                    .filter_map(|effect| effect.apply(&mut cool_fact_store).err())
+                    .map(|error| (conversation.name.clone(), error)),
+            );
+            choice.target.as_ref().and_then(|target| conversation.node_index(target))
+        };
+        match next {
+            Some(next) => {
+                let errors = active_conversation.enter(&conversations, conversation_index, next, &mut cool_fact_store);
+                fact_errors.extend(errors.into_iter().map(|error| (conversation.name.clone(), error)));
+            }
+            None => {
+                ended_writer.send(ConversationEnded {
+                    name: conversation.name.clone(),
+                });
+                active_conversation.conversation = None;
+            }
+        }
+    }
+
+    for (name, error) in fact_errors {
+        warn!("Conversation '{}': {}", name, error);
+        fact_error_writer.send(FactErrorOccurred {
+            source: FactErrorSource::Conversation(name),
+            error,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beats::data::{Condition, Fact};
+
+    const SMITH: &str = include_str!("../../assets/smith.dialogue");
+
+    #[test]
+    fn parses_the_smith_conversation() {
+        let conversations = parse_conversations(SMITH).unwrap();
+        assert_eq!(conversations.len(), 1);
+        let smith = &conversations[0];
+        assert_eq!(smith.name, "Smith Greeting");
+        let node_names: Vec<&str> = smith.nodes.iter().map(|node| node.name.as_str()).collect();
+        assert_eq!(node_names, vec!["Welcome", "Swords", "Dungeon"]);
+
+        let welcome = &smith.nodes[0];
+        assert_eq!(
+            welcome.lines[0],
+            DialogueLine {
+                speaker: "Smith".to_string(),
+                text: "Welcome, traveller.".to_string(),
+            }
+        );
+        let targets: Vec<Option<&str>> = welcome.choices.iter().map(|choice| choice.target.as_deref()).collect();
+        assert_eq!(targets, vec![Some("Swords"), Some("Dungeon"), None]);
+        let sword = &welcome.choices[0];
+        assert_eq!(
+            sword.rules[0].conditions,
+            vec![Condition::IntMoreThan {
+                fact_name: "gold".to_string(),
+                expected_value: 9,
+            }]
+        );
+        assert_eq!(
+            sword.effects,
+            vec![Effect::SubtractInt {
+                fact_name: "gold".to_string(),
+                value: 10,
+            }]
+        );
+
+        let swords = &smith.nodes[1];
+        assert_eq!(swords.lines[1].text, "That leaves you {gold} gold{if gold < 5}, spend it wisely{end}.");
+        assert_eq!(swords.effects, vec![Effect::SetFact(Fact::Bool("has_sword".to_string(), true))]);
+        assert_eq!(smith.nodes[2].effects.len(), 1);
+    }
+
+    #[test]
+    fn parsed_conversations_survive_a_round_trip_through_ron() {
+        let conversations = parse_conversations(SMITH).unwrap();
+        let serialized = ron::to_string(&conversations).unwrap();
+        let deserialized: Vec<Conversation> = ron::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, conversations);
+    }
+
+    #[test]
+    fn reports_the_line_and_column_of_errors() {
+        let error = parse_conversations("# Smith\n## Welcome\n=> gold = 3 junk\n").unwrap_err();
+        assert_eq!((error.line, error.column), (3, 13));
+
+        let error = parse_conversations("# Smith\n## Welcome\n- Bye -> END:\n    gold >\n").unwrap_err();
+        assert_eq!((error.line, error.column), (4, 11));
+
+        let error = parse_conversations("# Smith\n## Welcome\n- Leave -> Nowhere:\n").unwrap_err();
+        assert_eq!(error.line, 3);
+
+        let error = parse_conversations("Smith: Hello\n").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 1));
+    }
+}
@@ -10,8 +10,26 @@ pub struct FactUpdated {
 }
 
 #[derive(Event)]
-pub struct RuleUpdated {
-    pub rule: String,
+pub struct FactRemoved {
+    pub name: String,
+}
+
+// The index of a story in the StoryEngine, stories keep their index when they are hot reloaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct StoryId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct BeatId {
+    pub story: StoryId,
+    pub beat: usize,
+}
+
+// A rule of a beat, or a pre-requisite of the story when there is no beat
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct RuleId {
+    pub story: StoryId,
+    pub beat: Option<usize>,
+    pub rule: usize,
 }
 
 // Fact enum
@@ -21,6 +39,41 @@ pub enum Fact {
     String(String, String),
     Bool(String, bool),
     StringList(String, StringHashSet),
+    Float(String, HashableFloat),
+}
+
+pub const FLOAT_EPSILON: f32 = 0.0001;
+
+// Floats compare by their bits so facts can stay Eq and Hash, NaN equals NaN and 0.0 equals -0.0.
+// Use the conditions with an epsilon to compare values that come out of arithmetic.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct HashableFloat(pub f32);
+
+impl HashableFloat {
+    fn canonical_bits(&self) -> u32 {
+        if self.0.is_nan() {
+            f32::NAN.to_bits()
+        } else if self.0 == 0.0 {
+            0.0f32.to_bits()
+        } else {
+            self.0.to_bits()
+        }
+    }
+}
+
+impl PartialEq for HashableFloat {
+    fn eq(&self, other: &Self) -> bool {
+        self.canonical_bits() == other.canonical_bits()
+    }
+}
+
+impl Eq for HashableFloat {}
+
+impl Hash for HashableFloat {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.canonical_bits().hash(state);
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
@@ -50,110 +103,370 @@ impl Hash for StringHashSet {
     }
 }
 
-#[derive(Resource, Deserialize, Serialize)]
+impl Fact {
+    fn fill_parameters(&mut self, parameters: &[(&str, &str)]) {
+        match self {
+            Fact::Int(name, _) | Fact::Bool(name, _) | Fact::Float(name, _) => fill_parameters(name, parameters),
+            Fact::String(name, value) => {
+                fill_parameters(name, parameters);
+                fill_parameters(value, parameters);
+            }
+            Fact::StringList(name, values) => {
+                fill_parameters(name, parameters);
+                values.0 = values
+                    .0
+                    .drain()
+                    .map(|mut value| {
+                        fill_parameters(&mut value, parameters);
+                        value
+                    })
+                    .collect();
+            }
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Fact::Int(name, _)
+            | Fact::String(name, _)
+            | Fact::Bool(name, _)
+            | Fact::StringList(name, _)
+            | Fact::Float(name, _) => name,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Fact::Int(_, _) => "integer",
+            Fact::String(_, _) => "string",
+            Fact::Bool(_, _) => "boolean",
+            Fact::StringList(_, _) => "string list",
+            Fact::Float(_, _) => "float",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FactError {
+    TypeMismatch {
+        key: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+    MissingKey(String),
+    Overflow {
+        key: String,
+    },
+}
+
+impl FactError {
+    fn type_mismatch(key: String, expected: &'static str, found: &Fact) -> Self {
+        FactError::TypeMismatch {
+            key,
+            expected,
+            found: found.type_name(),
+        }
+    }
+}
+
+impl std::fmt::Display for FactError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FactError::TypeMismatch { key, expected, found } => write!(
+                f,
+                "Fact with key {} is not a {}, it is a {}",
+                key, expected, found
+            ),
+            FactError::MissingKey(key) => write!(f, "There is no fact with key {}", key),
+            FactError::Overflow { key } => write!(f, "Fact with key {} overflowed", key),
+        }
+    }
+}
+
+impl std::error::Error for FactError {}
+
+#[derive(Resource, Debug, Clone, Deserialize, Serialize)]
 pub struct FactsOfTheWorld {
     pub facts: HashMap<String, Fact>,
     pub updated_facts: HashSet<Fact>,
+    #[serde(default)]
+    pub removed_facts: HashSet<String>,
 }
 
 impl FactsOfTheWorld {
+    // Hashes the facts in a stable order, so equal facts always hash the same
+    pub fn hash_facts<H: Hasher>(&self, state: &mut H) {
+        let mut sorted_facts: Vec<&Fact> = self.facts.values().collect();
+        sorted_facts.sort_unstable_by_key(|fact| fact.name());
+        sorted_facts.hash(state);
+    }
+
     pub fn new() -> Self {
         FactsOfTheWorld {
             facts: HashMap::new(),
             updated_facts: HashSet::new(),
+            removed_facts: HashSet::new(),
         }
     }
 
     pub fn store_int(&mut self, key: String, value: i32) {
-        if let Some(fact) = self.facts.get_mut(&key) {
-            if let Fact::Int(_, current_value) = fact {
-                if current_value != &value {
-                    *fact = Fact::Int(key.clone(), value);
-                    self.updated_facts.insert(fact.clone());
+        if let Err(error) = self.try_store_int(key, value) {
+            panic!("{}", error)
+        }
+    }
+
+    pub fn try_store_int(&mut self, key: String, value: i32) -> Result<(), FactError> {
+        match self.facts.get_mut(&key) {
+            Some(Fact::Int(_, current_value)) => {
+                if *current_value != value {
+                    *current_value = value;
+                    self.mark_updated(Fact::Int(key, value));
                 }
-            } else {
-                panic!("Fact with key {} is not an integer", key)
             }
-        } else {
-            self.facts
-                .insert(key.clone(), Fact::Int(key.clone(), value));
-            self.updated_facts.insert(Fact::Int(key.clone(), value));
+            Some(fact) => return Err(FactError::type_mismatch(key, "integer", fact)),
+            None => {
+                self.facts.insert(key.clone(), Fact::Int(key.clone(), value));
+                self.mark_updated(Fact::Int(key, value));
+            }
         }
+        Ok(())
     }
 
+    // Saturates at the bounds of i32, try_add_to_int reports the overflow instead
     pub fn add_to_int(&mut self, key: String, value: i32) {
         let current = self.get_int(&key).unwrap_or(&0);
-        self.store_int(key, current + value);
+        self.store_int(key, current.saturating_add(value));
+    }
+
+    pub fn try_add_to_int(&mut self, key: String, value: i32) -> Result<(), FactError> {
+        let current = self.int_or_zero(&key)?;
+        let sum = current
+            .checked_add(value)
+            .ok_or_else(|| FactError::Overflow { key: key.clone() })?;
+        self.try_store_int(key, sum)
     }
 
-    fn subtract_from_int(&mut self, key: String, value: i32) {
+    // Saturates at the bounds of i32, try_subtract_from_int reports the overflow instead
+    pub fn subtract_from_int(&mut self, key: String, value: i32) {
         let current = self.get_int(&key).unwrap_or(&0);
-        self.store_int(key, current + value);
+        self.store_int(key, current.saturating_sub(value));
+    }
+
+    pub fn try_subtract_from_int(&mut self, key: String, value: i32) -> Result<(), FactError> {
+        let current = self.int_or_zero(&key)?;
+        let difference = current
+            .checked_sub(value)
+            .ok_or_else(|| FactError::Overflow { key: key.clone() })?;
+        self.try_store_int(key, difference)
+    }
+
+    pub fn try_multiply_int(&mut self, key: String, value: i32) -> Result<(), FactError> {
+        let current = self.int_or_zero(&key)?;
+        let product = current
+            .checked_mul(value)
+            .ok_or_else(|| FactError::Overflow { key: key.clone() })?;
+        self.try_store_int(key, product)
+    }
+
+    pub fn try_clamp_int(&mut self, key: String, min: i32, max: i32) -> Result<(), FactError> {
+        let current = self.int_or_zero(&key)?;
+        self.try_store_int(key, current.max(min).min(max))
+    }
+
+    fn int_or_zero(&self, key: &str) -> Result<i32, FactError> {
+        match self.try_get_int(key) {
+            Ok(current) => Ok(*current),
+            Err(FactError::MissingKey(_)) => Ok(0),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub fn store_float(&mut self, key: String, value: f32) {
+        if let Err(error) = self.try_store_float(key, value) {
+            panic!("{}", error)
+        }
+    }
+
+    pub fn try_store_float(&mut self, key: String, value: f32) -> Result<(), FactError> {
+        let value = HashableFloat(value);
+        match self.facts.get_mut(&key) {
+            Some(Fact::Float(_, current_value)) => {
+                if *current_value != value {
+                    *current_value = value;
+                    self.mark_updated(Fact::Float(key, value));
+                }
+            }
+            Some(fact) => return Err(FactError::type_mismatch(key, "float", fact)),
+            None => {
+                self.facts.insert(key.clone(), Fact::Float(key.clone(), value));
+                self.mark_updated(Fact::Float(key, value));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn add_to_float(&mut self, key: String, value: f32) {
+        let current = self.get_float(&key).unwrap_or(0.0);
+        self.store_float(key, current + value);
+    }
+
+    pub fn try_add_to_float(&mut self, key: String, value: f32) -> Result<(), FactError> {
+        let current = self.float_or_zero(&key)?;
+        self.try_store_float(key, current + value)
+    }
+
+    pub fn try_subtract_from_float(&mut self, key: String, value: f32) -> Result<(), FactError> {
+        let current = self.float_or_zero(&key)?;
+        self.try_store_float(key, current - value)
+    }
+
+    pub fn try_multiply_float(&mut self, key: String, value: f32) -> Result<(), FactError> {
+        let current = self.float_or_zero(&key)?;
+        self.try_store_float(key, current * value)
+    }
+
+    pub fn try_clamp_float(&mut self, key: String, min: f32, max: f32) -> Result<(), FactError> {
+        let current = self.float_or_zero(&key)?;
+        self.try_store_float(key, current.max(min).min(max))
+    }
+
+    fn float_or_zero(&self, key: &str) -> Result<f32, FactError> {
+        match self.try_get_float(key) {
+            Ok(current) => Ok(current),
+            Err(FactError::MissingKey(_)) => Ok(0.0),
+            Err(error) => Err(error),
+        }
     }
 
     pub fn store_string(&mut self, key: String, value: String) {
-        if let Some(fact) = self.facts.get_mut(&key) {
-            if let Fact::String(_, current_value) = fact {
-                if current_value != &value {
-                    *fact = Fact::String(key.clone(), value.clone());
-                    self.updated_facts.insert(fact.clone());
+        if let Err(error) = self.try_store_string(key, value) {
+            panic!("{}", error)
+        }
+    }
+
+    pub fn try_store_string(&mut self, key: String, value: String) -> Result<(), FactError> {
+        match self.facts.get_mut(&key) {
+            Some(Fact::String(_, current_value)) => {
+                if *current_value != value {
+                    *current_value = value.clone();
+                    self.mark_updated(Fact::String(key, value));
                 }
-            } else {
-                panic!("Fact with key {} is not a string", key)
             }
-        } else {
-            self.facts
-                .insert(key.clone(), Fact::String(key.clone(), value.clone()));
-            self.updated_facts
-                .insert(Fact::String(key.clone(), value.clone()));
+            Some(fact) => return Err(FactError::type_mismatch(key, "string", fact)),
+            None => {
+                self.facts
+                    .insert(key.clone(), Fact::String(key.clone(), value.clone()));
+                self.mark_updated(Fact::String(key, value));
+            }
         }
+        Ok(())
     }
 
     pub fn store_bool(&mut self, key: String, value: bool) {
-        if let Some(fact) = self.facts.get_mut(&key) {
-            if let Fact::Bool(_, current_value) = fact {
-                if current_value != &value {
-                    *fact = Fact::Bool(key.clone(), value);
-                    self.updated_facts.insert(fact.clone());
+        if let Err(error) = self.try_store_bool(key, value) {
+            panic!("{}", error)
+        }
+    }
+
+    pub fn try_store_bool(&mut self, key: String, value: bool) -> Result<(), FactError> {
+        match self.facts.get_mut(&key) {
+            Some(Fact::Bool(_, current_value)) => {
+                if *current_value != value {
+                    *current_value = value;
+                    self.mark_updated(Fact::Bool(key, value));
                 }
-            } else {
-                panic!("Fact with key {} is not a boolean", key)
             }
-        } else {
-            self.facts
-                .insert(key.clone(), Fact::Bool(key.clone(), value.clone()));
-            self.updated_facts
-                .insert(Fact::Bool(key.clone(), value.clone()));
+            Some(fact) => return Err(FactError::type_mismatch(key, "boolean", fact)),
+            None => {
+                self.facts.insert(key.clone(), Fact::Bool(key.clone(), value));
+                self.mark_updated(Fact::Bool(key, value));
+            }
         }
+        Ok(())
+    }
+
+    // A missing fact counts as false, so toggling it stores true
+    pub fn try_toggle_bool(&mut self, key: String) -> Result<(), FactError> {
+        let current = match self.try_get_bool(&key) {
+            Ok(current) => *current,
+            Err(FactError::MissingKey(_)) => false,
+            Err(error) => return Err(error),
+        };
+        self.try_store_bool(key, !current)
     }
 
     pub fn add_to_list(&mut self, key: String, value: String) {
-        if let Some(list_fact) = self.facts.get_mut(&key) {
-            if let Fact::StringList(_, list) = list_fact {
+        let _ = self.try_add_to_list(key, value);
+    }
+
+    pub fn try_add_to_list(&mut self, key: String, value: String) -> Result<(), FactError> {
+        match self.facts.get_mut(&key) {
+            Some(Fact::StringList(_, list)) => {
                 if list.insert(value) {
-                    self.updated_facts.insert(list_fact.clone());
+                    let updated = Fact::StringList(key, list.clone());
+                    self.mark_updated(updated);
                 }
             }
-        } else {
-            let mut new_list = StringHashSet::new();
-            new_list.insert(value);
-            self.facts
-                .insert(key.clone(), Fact::StringList(key.clone(), new_list.clone()));
-            self.updated_facts
-                .insert(Fact::StringList(key.clone(), new_list.clone()));
+            Some(fact) => return Err(FactError::type_mismatch(key, "string list", fact)),
+            None => {
+                let mut new_list = StringHashSet::new();
+                new_list.insert(value);
+                self.facts
+                    .insert(key.clone(), Fact::StringList(key.clone(), new_list.clone()));
+                self.mark_updated(Fact::StringList(key, new_list));
+            }
         }
+        Ok(())
     }
 
     pub fn remove_from_list(&mut self, key: String, value: String) {
-        if let Some(list_fact) = self.facts.get_mut(&key) {
-            if let Fact::StringList(_, list) = list_fact {
+        let _ = self.try_remove_from_list(key, value);
+    }
+
+    pub fn try_remove_from_list(&mut self, key: String, value: String) -> Result<(), FactError> {
+        match self.facts.get_mut(&key) {
+            Some(Fact::StringList(_, list)) => {
                 if list.remove(&value) {
-                    self.updated_facts.insert(list_fact.clone());
+                    let updated = Fact::StringList(key, list.clone());
+                    self.mark_updated(updated);
                 }
+                Ok(())
             }
+            Some(fact) => Err(FactError::type_mismatch(key, "string list", fact)),
+            // A missing list has nothing to remove, like clearing it has nothing to clear
+            None => Ok(()),
         }
     }
 
+    pub fn try_clear_list(&mut self, key: String) -> Result<(), FactError> {
+        match self.facts.get_mut(&key) {
+            Some(Fact::StringList(_, list)) => {
+                if !list.0.is_empty() {
+                    list.0.clear();
+                    let updated = Fact::StringList(key, list.clone());
+                    self.mark_updated(updated);
+                }
+                Ok(())
+            }
+            Some(fact) => Err(FactError::type_mismatch(key, "string list", fact)),
+            None => Ok(()),
+        }
+    }
+
+    // Changes to the fact earlier in the frame are dropped, it is only reported as removed
+    pub fn remove_fact(&mut self, key: &str) {
+        if self.facts.remove(key).is_some() {
+            self.updated_facts.retain(|fact| fact.name() != key);
+            self.removed_facts.insert(key.to_string());
+        }
+    }
+
+    // A fact that is set again after it was removed in the same frame is only reported as updated
+    fn mark_updated(&mut self, fact: Fact) {
+        self.removed_facts.remove(fact.name());
+        self.updated_facts.insert(fact);
+    }
+
     pub fn get_int(&self, key: &str) -> Option<&i32> {
         return if let Some(Fact::Int(_, value)) = self.facts.get(key) {
             Some(&value)
@@ -185,6 +498,87 @@ impl FactsOfTheWorld {
             None
         };
     }
+
+    pub fn get_float(&self, key: &str) -> Option<f32> {
+        if let Some(Fact::Float(_, value)) = self.facts.get(key) {
+            Some(value.0)
+        } else {
+            None
+        }
+    }
+
+    pub fn try_get_int(&self, key: &str) -> Result<&i32, FactError> {
+        match self.facts.get(key) {
+            Some(Fact::Int(_, value)) => Ok(value),
+            Some(fact) => Err(FactError::type_mismatch(key.to_string(), "integer", fact)),
+            None => Err(FactError::MissingKey(key.to_string())),
+        }
+    }
+
+    pub fn try_get_string(&self, key: &str) -> Result<&String, FactError> {
+        match self.facts.get(key) {
+            Some(Fact::String(_, value)) => Ok(value),
+            Some(fact) => Err(FactError::type_mismatch(key.to_string(), "string", fact)),
+            None => Err(FactError::MissingKey(key.to_string())),
+        }
+    }
+
+    pub fn try_get_bool(&self, key: &str) -> Result<&bool, FactError> {
+        match self.facts.get(key) {
+            Some(Fact::Bool(_, value)) => Ok(value),
+            Some(fact) => Err(FactError::type_mismatch(key.to_string(), "boolean", fact)),
+            None => Err(FactError::MissingKey(key.to_string())),
+        }
+    }
+
+    pub fn try_get_float(&self, key: &str) -> Result<f32, FactError> {
+        match self.facts.get(key) {
+            Some(Fact::Float(_, value)) => Ok(value.0),
+            Some(fact) => Err(FactError::type_mismatch(key.to_string(), "float", fact)),
+            None => Err(FactError::MissingKey(key.to_string())),
+        }
+    }
+
+    pub fn try_get_list(&self, key: &str) -> Result<&StringHashSet, FactError> {
+        match self.facts.get(key) {
+            Some(Fact::StringList(_, value)) => Ok(value),
+            Some(fact) => Err(FactError::type_mismatch(key.to_string(), "string list", fact)),
+            None => Err(FactError::MissingKey(key.to_string())),
+        }
+    }
+}
+
+// Where conditions look their facts up, the FactsOfTheWorld or a view over entity facts
+pub trait FactSource {
+    fn fact(&self, name: &str) -> Option<&Fact>;
+}
+
+impl FactSource for HashMap<String, Fact> {
+    fn fact(&self, name: &str) -> Option<&Fact> {
+        self.get(name)
+    }
+}
+
+pub const SELF_SCOPE: &str = "self";
+
+// Where a fact lives: `self.alerted` is on the subject of the story, `guard_3.alerted` on the
+// entity with the Name guard_3 and `alerted` in the FactsOfTheWorld
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FactScope<'a> {
+    Global,
+    Subject,
+    Entity(&'a str),
+}
+
+impl<'a> FactScope<'a> {
+    // Splits a fact name into its scope and the name of the fact within that scope
+    pub fn of(fact_name: &'a str) -> (FactScope<'a>, &'a str) {
+        match fact_name.split_once('.') {
+            Some((SELF_SCOPE, name)) => (FactScope::Subject, name),
+            Some((entity, name)) => (FactScope::Entity(entity), name),
+            None => (FactScope::Global, fact_name),
+        }
+    }
 }
 
 // Condition enum
@@ -214,16 +608,51 @@ pub enum Condition {
         fact_name: String,
         expected_value: String,
     },
+    FloatEquals {
+        fact_name: String,
+        expected_value: HashableFloat,
+        #[serde(default = "default_epsilon")]
+        epsilon: HashableFloat,
+    },
+    FloatMoreThan {
+        fact_name: String,
+        expected_value: HashableFloat,
+    },
+    FloatLessThan {
+        fact_name: String,
+        expected_value: HashableFloat,
+    },
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+    Not(Box<Condition>),
+    AtLeast(usize, Vec<Condition>),
 }
 
 impl Condition {
-    pub fn evaluate(&self, facts: &HashMap<String, Fact>) -> bool {
+    pub fn evaluate(&self, facts: &dyn FactSource) -> bool {
         match self {
+            Condition::All(conditions) => {
+                return conditions.iter().all(|condition| condition.evaluate(facts));
+            }
+            Condition::Any(conditions) => {
+                return conditions.iter().any(|condition| condition.evaluate(facts));
+            }
+            Condition::Not(condition) => {
+                return !condition.evaluate(facts);
+            }
+            Condition::AtLeast(count, conditions) => {
+                return conditions
+                    .iter()
+                    .filter(|condition| condition.evaluate(facts))
+                    .take(*count)
+                    .count()
+                    >= *count;
+            }
             Condition::IntEquals {
                 fact_name,
                 expected_value,
             } => {
-                if let Some(Fact::Int(_, value)) = facts.get(fact_name) {
+                if let Some(Fact::Int(_, value)) = facts.fact(fact_name) {
                     return *value == *expected_value;
                 }
             }
@@ -231,7 +660,7 @@ impl Condition {
                 fact_name,
                 expected_value,
             } => {
-                if let Some(Fact::String(_, value)) = facts.get(fact_name) {
+                if let Some(Fact::String(_, value)) = facts.fact(fact_name) {
                     return value == expected_value;
                 }
             }
@@ -239,7 +668,7 @@ impl Condition {
                 fact_name,
                 expected_value,
             } => {
-                if let Some(Fact::Bool(_, value)) = facts.get(fact_name) {
+                if let Some(Fact::Bool(_, value)) = facts.fact(fact_name) {
                     return *value == *expected_value;
                 }
             }
@@ -247,7 +676,7 @@ impl Condition {
                 fact_name,
                 expected_value,
             } => {
-                if let Some(Fact::Int(_, value)) = facts.get(fact_name) {
+                if let Some(Fact::Int(_, value)) = facts.fact(fact_name) {
                     return *value > *expected_value;
                 }
             }
@@ -255,7 +684,7 @@ impl Condition {
                 fact_name,
                 expected_value,
             } => {
-                if let Some(Fact::Int(_, value)) = facts.get(fact_name) {
+                if let Some(Fact::Int(_, value)) = facts.fact(fact_name) {
                     return *value < *expected_value;
                 }
             }
@@ -263,13 +692,121 @@ impl Condition {
                 fact_name,
                 expected_value,
             } => {
-                if let Some(Fact::StringList(_, value)) = facts.get(fact_name) {
+                if let Some(Fact::StringList(_, value)) = facts.fact(fact_name) {
                     return value.0.contains(expected_value);
                 }
             }
+            Condition::FloatEquals {
+                fact_name,
+                expected_value,
+                epsilon,
+            } => {
+                if let Some(Fact::Float(_, value)) = facts.fact(fact_name) {
+                    return (value.0 - expected_value.0).abs() <= epsilon.0;
+                }
+            }
+            Condition::FloatMoreThan {
+                fact_name,
+                expected_value,
+            } => {
+                if let Some(Fact::Float(_, value)) = facts.fact(fact_name) {
+                    return value.0 > expected_value.0;
+                }
+            }
+            Condition::FloatLessThan {
+                fact_name,
+                expected_value,
+            } => {
+                if let Some(Fact::Float(_, value)) = facts.fact(fact_name) {
+                    return value.0 < expected_value.0;
+                }
+            }
         }
         false
     }
+
+    // Collects the names of all facts this condition, and the conditions nested in it, look at
+    pub fn collect_fact_names<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Condition::IntEquals { fact_name, .. }
+            | Condition::IntMoreThan { fact_name, .. }
+            | Condition::IntLessThan { fact_name, .. }
+            | Condition::StringEquals { fact_name, .. }
+            | Condition::BoolEquals { fact_name, .. }
+            | Condition::ListContains { fact_name, .. }
+            | Condition::FloatEquals { fact_name, .. }
+            | Condition::FloatMoreThan { fact_name, .. }
+            | Condition::FloatLessThan { fact_name, .. } => names.push(fact_name),
+            Condition::All(conditions) | Condition::Any(conditions) | Condition::AtLeast(_, conditions) => {
+                for condition in conditions {
+                    condition.collect_fact_names(names);
+                }
+            }
+            Condition::Not(condition) => condition.collect_fact_names(names),
+        }
+    }
+
+    // The number of conditions joined with `&&` that all have to hold, `a && b` counts two and
+    // `a || b` one, however many facts its branches look at
+    pub fn conjunct_count(&self) -> usize {
+        match self {
+            Condition::All(conditions) => conditions.iter().map(Condition::conjunct_count).sum(),
+            _ => 1,
+        }
+    }
+
+    fn fill_parameters(&mut self, parameters: &[(&str, &str)]) {
+        match self {
+            Condition::StringEquals { fact_name, expected_value }
+            | Condition::ListContains { fact_name, expected_value } => {
+                fill_parameters(fact_name, parameters);
+                fill_parameters(expected_value, parameters);
+            }
+            Condition::IntEquals { fact_name, .. }
+            | Condition::IntMoreThan { fact_name, .. }
+            | Condition::IntLessThan { fact_name, .. }
+            | Condition::BoolEquals { fact_name, .. }
+            | Condition::FloatEquals { fact_name, .. }
+            | Condition::FloatMoreThan { fact_name, .. }
+            | Condition::FloatLessThan { fact_name, .. } => fill_parameters(fact_name, parameters),
+            Condition::All(conditions) | Condition::Any(conditions) | Condition::AtLeast(_, conditions) => {
+                for condition in conditions {
+                    condition.fill_parameters(parameters);
+                }
+            }
+            Condition::Not(condition) => condition.fill_parameters(parameters),
+        }
+    }
+
+    pub fn float_equals(fact_name: impl Into<String>, expected_value: f32) -> Self {
+        Condition::float_equals_within(fact_name, expected_value, FLOAT_EPSILON)
+    }
+
+    pub fn float_equals_within(fact_name: impl Into<String>, expected_value: f32, epsilon: f32) -> Self {
+        Condition::FloatEquals {
+            fact_name: fact_name.into(),
+            expected_value: HashableFloat(expected_value),
+            epsilon: HashableFloat(epsilon),
+        }
+    }
+
+    pub fn float_more_than(fact_name: impl Into<String>, expected_value: f32) -> Self {
+        Condition::FloatMoreThan {
+            fact_name: fact_name.into(),
+            expected_value: HashableFloat(expected_value),
+        }
+    }
+
+    pub fn float_less_than(fact_name: impl Into<String>, expected_value: f32) -> Self {
+        Condition::FloatLessThan {
+            fact_name: fact_name.into(),
+            expected_value: HashableFloat(expected_value),
+        }
+    }
+}
+
+fn default_epsilon() -> HashableFloat {
+    HashableFloat(FLOAT_EPSILON)
 }
 
 // Rule struct
@@ -284,140 +821,1819 @@ impl Rule {
         Rule { name, conditions }
     }
 
-    pub fn evaluate(&self, facts: &HashMap<String, Fact>) -> bool {
+    pub fn evaluate(&self, facts: &dyn FactSource) -> bool {
         self.conditions
             .iter()
             .all(|condition| condition.evaluate(facts))
     }
+
+    fn fill_parameters(&mut self, parameters: &[(&str, &str)]) {
+        fill_parameters(&mut self.name, parameters);
+        for condition in self.conditions.iter_mut() {
+            condition.fill_parameters(parameters);
+        }
+    }
+
+    pub fn collect_fact_names<'a>(&'a self, names: &mut Vec<&'a str>) {
+        for condition in &self.conditions {
+            condition.collect_fact_names(names);
+        }
+    }
 }
 
-// StoryBeat struct
+// An outgoing edge of a beat, taken when the beat is finished and all of its rules hold
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
-pub struct StoryBeat {
+pub struct Transition {
+    pub name: String,
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    // The beat to continue with, None ends the story
+    #[serde(default)]
+    pub target: Option<String>,
+}
+
+impl Transition {
+    pub fn new(name: String, rules: Vec<Rule>, target: Option<String>) -> Self {
+        Transition { name, rules, target }
+    }
+
+    pub fn evaluate(&self, facts: &dyn FactSource) -> bool {
+        self.rules.iter().all(|rule| rule.evaluate(facts))
+    }
+
+    fn fill_parameters(&mut self, parameters: &[(&str, &str)]) {
+        fill_parameters(&mut self.name, parameters);
+        for rule in self.rules.iter_mut() {
+            rule.fill_parameters(parameters);
+        }
+        if let Some(target) = self.target.as_mut() {
+            fill_parameters(target, parameters);
+        }
+    }
+}
+
+// A part of a beat that is completed on its own, in any order with the other objectives
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct Objective {
     pub name: String,
     pub rules: Vec<Rule>,
+    // Applied when the objective is completed
+    #[serde(default)]
     pub effects: Vec<Effect>,
-    pub finished: bool,
+    #[serde(default)]
+    pub completed: bool,
 }
 
-impl StoryBeat {
-    // Constructor for StoryBeat
+impl Objective {
     pub fn new(name: String, rules: Vec<Rule>, effects: Vec<Effect>) -> Self {
-        StoryBeat {
+        Objective {
             name,
             rules,
             effects,
-            finished: false,
+            completed: false,
         }
     }
 
-    // Evaluate all rules for the story beat based on the provided facts
-    pub fn evaluate(&mut self, facts: &HashMap<String, Fact>) {
-        self.finished = self.rules.iter().all(|rule| rule.evaluate(facts));
+    pub fn evaluate(&self, facts: &dyn FactSource) -> bool {
+        self.rules.iter().all(|rule| rule.evaluate(facts))
+    }
+
+    fn fill_parameters(&mut self, parameters: &[(&str, &str)]) {
+        fill_parameters(&mut self.name, parameters);
+        for rule in self.rules.iter_mut() {
+            rule.fill_parameters(parameters);
+        }
+        for effect in self.effects.iter_mut() {
+            effect.fill_parameters(parameters);
+        }
     }
 }
 
-// Story struct
+// Fails a story or a beat when all of its rules hold, like an escort dying or a timer running out
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
-pub struct Story {
+pub struct Failure {
     pub name: String,
-    pub pre_requisites: Vec<Rule>,
-    pub beats: Vec<StoryBeat>,
-    pub is_started: bool,
-    pub active_beat_index: usize,
+    pub rules: Vec<Rule>,
+    // Applied when the failure triggers
+    #[serde(default)]
+    pub effects: Vec<Effect>,
+    // The beat to continue with instead of failing the whole story
+    #[serde(default)]
+    pub recovery: Option<String>,
+    // Set while the rules keep holding after the failure triggered, it only triggers again once
+    // they stopped holding
+    #[serde(default)]
+    pub triggered: bool,
 }
 
-impl Story {
-    pub fn new(name: String, pre_requisites: Vec<Rule>, beats: Vec<StoryBeat>) -> Self {
-        Story {
+impl Failure {
+    pub fn new(name: String, rules: Vec<Rule>, effects: Vec<Effect>, recovery: Option<String>) -> Self {
+        Failure {
             name,
-            pre_requisites,
-            beats,
-            is_started: false,
-            active_beat_index: 0,
+            rules,
+            effects,
+            recovery,
+            triggered: false,
         }
     }
 
-    pub fn evaluate_active_beat(&mut self, facts: &HashMap<String, Fact>) -> Option<StoryBeat> {
-        if self.active_beat_index < self.beats.len() {
-            let active_beat = &mut self.beats[self.active_beat_index];
-            active_beat.evaluate(facts);
-            if active_beat.finished {
-                self.active_beat_index += 1;
-                Some(active_beat.clone())
-            } else {
-                None
+    pub fn evaluate(&self, facts: &dyn FactSource) -> bool {
+        self.rules.iter().all(|rule| rule.evaluate(facts))
+    }
+
+    fn fill_parameters(&mut self, parameters: &[(&str, &str)]) {
+        fill_parameters(&mut self.name, parameters);
+        for rule in self.rules.iter_mut() {
+            rule.fill_parameters(parameters);
+        }
+        for effect in self.effects.iter_mut() {
+            effect.fill_parameters(parameters);
+        }
+        if let Some(recovery) = self.recovery.as_mut() {
+            fill_parameters(recovery, parameters);
+        }
+    }
+}
+
+// The first of the failures whose rules started to hold, a failure whose rules keep holding doesn't
+// trigger again and a recovery beat isn't reactivated on every evaluation
+fn trigger_failure(failures: &mut [Failure], facts: &dyn FactSource) -> Option<usize> {
+    let mut triggered = None;
+    for (index, failure) in failures.iter_mut().enumerate() {
+        if !failure.evaluate(facts) {
+            failure.triggered = false;
+        } else if !failure.triggered && triggered.is_none() {
+            failure.triggered = true;
+            triggered = Some(index);
+        }
+    }
+    triggered
+}
+
+// How many objectives of a beat must be completed before it can finish
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum CompletionPolicy {
+    #[default]
+    All,
+    Any,
+    AtLeast(usize),
+}
+
+impl CompletionPolicy {
+    pub fn is_met(&self, completed: usize, total: usize) -> bool {
+        match self {
+            CompletionPolicy::All => completed == total,
+            CompletionPolicy::Any => total == 0 || completed > 0,
+            CompletionPolicy::AtLeast(count) => completed >= (*count).min(total),
+        }
+    }
+}
+
+// StoryBeat struct
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct StoryBeat {
+    pub name: String,
+    pub rules: Vec<Rule>,
+    pub effects: Vec<Effect>,
+    #[serde(default)]
+    pub objectives: Vec<Objective>,
+    #[serde(default)]
+    pub completion: CompletionPolicy,
+    // Checked while the beat is active, before its rules
+    #[serde(default)]
+    pub failures: Vec<Failure>,
+    // Without transitions the story continues with the next beat in the list
+    #[serde(default)]
+    pub transitions: Vec<Transition>,
+    // Set once the beat has been finished, a beat in a loop can be finished again
+    #[serde(default)]
+    pub finished: bool,
+    // The conversation that starts when the beat is activated
+    #[serde(default)]
+    pub conversation: Option<String>,
+}
+
+impl StoryBeat {
+    // Constructor for StoryBeat
+    pub fn new(name: String, rules: Vec<Rule>, effects: Vec<Effect>) -> Self {
+        StoryBeat {
+            name,
+            rules,
+            effects,
+            objectives: Vec::new(),
+            completion: CompletionPolicy::All,
+            failures: Vec::new(),
+            transitions: Vec::new(),
+            finished: false,
+            conversation: None,
+        }
+    }
+
+    // Evaluate all rules for the story beat based on the provided facts, and whether enough of its
+    // objectives are completed
+    pub fn evaluate(&self, facts: &dyn FactSource) -> bool {
+        let (completed, total) = self.objective_progress();
+        self.completion.is_met(completed, total) && self.rules.iter().all(|rule| rule.evaluate(facts))
+    }
+
+    fn fill_parameters(&mut self, parameters: &[(&str, &str)]) {
+        fill_parameters(&mut self.name, parameters);
+        for rule in self.rules.iter_mut() {
+            rule.fill_parameters(parameters);
+        }
+        for effect in self.effects.iter_mut() {
+            effect.fill_parameters(parameters);
+        }
+        for objective in self.objectives.iter_mut() {
+            objective.fill_parameters(parameters);
+        }
+        for failure in self.failures.iter_mut() {
+            failure.fill_parameters(parameters);
+        }
+        for transition in self.transitions.iter_mut() {
+            transition.fill_parameters(parameters);
+        }
+        if let Some(conversation) = self.conversation.as_mut() {
+            fill_parameters(conversation, parameters);
+        }
+    }
+
+    // The number of completed objectives and the number of all objectives, as in 2/3
+    pub fn objective_progress(&self) -> (usize, usize) {
+        let completed = self.objectives.iter().filter(|objective| objective.completed).count();
+        (completed, self.objectives.len())
+    }
+
+    // Completes the objectives that hold and returns their indices
+    fn complete_objectives(&mut self, facts: &dyn FactSource) -> Vec<usize> {
+        let mut completed_objectives = Vec::new();
+        for (index, objective) in self.objectives.iter_mut().enumerate() {
+            if !objective.completed && objective.evaluate(facts) {
+                objective.completed = true;
+                completed_objectives.push(index);
             }
-        } else {
-            None
         }
+        completed_objectives
+    }
+
+    // Makes the beat ready to be played again when a story loops back to it
+    fn reset_objectives(&mut self) {
+        for objective in self.objectives.iter_mut() {
+            objective.completed = false;
+        }
+    }
+}
+
+// What happened to a story during one evaluation, in the order it happened. Beats, objectives,
+// failures and rules are referred to by their index in the story
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StoryStep {
+    Started,
+    BeatActivated(usize),
+    ObjectiveCompleted {
+        beat: usize,
+        objective: usize,
+        completed: usize,
+        total: usize,
+    },
+    BeatFinished(usize),
+    // A failure of the story, or of the beat, triggered
+    Failed {
+        beat: Option<usize>,
+        failure: usize,
+    },
+    Finished,
+    // A pre-requisite, or a rule of an active beat, started or stopped to hold
+    RuleChanged {
+        beat: Option<usize>,
+        rule: usize,
+        holds: bool,
+    },
+}
+
+impl StoryStep {
+    // Whether the story moved on, so its next beats should be evaluated as well
+    pub fn advances(&self) -> bool {
+        matches!(self, StoryStep::Started | StoryStep::BeatFinished(_) | StoryStep::Failed { .. })
     }
+}
+
+// Story struct
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct Story {
+    pub name: String,
+    pub pre_requisites: Vec<Rule>,
+    // The first beat is where the story starts, transitions can lead to any of the others
+    pub beats: Vec<StoryBeat>,
+    // Checked while the story is running, whatever beats are active
+    #[serde(default)]
+    pub failures: Vec<Failure>,
+    #[serde(default)]
+    pub is_started: bool,
+    #[serde(default)]
+    pub is_failed: bool,
+    // The names of the beats the story is currently at, empty before it starts and once it ends
+    #[serde(default)]
+    pub active_beats: Vec<String>,
+    // A story with parameters is a template, StoryEngine::instantiate fills them in
+    #[serde(default)]
+    pub parameters: Vec<String>,
+}
 
-    pub fn start_if_possible(&mut self, facts: &HashMap<String, Fact>) -> bool {
+impl Story {
+    pub fn new(name: String, pre_requisites: Vec<Rule>, beats: Vec<StoryBeat>) -> Self {
+        Story {
+            name,
+            pre_requisites,
+            beats,
+            failures: Vec::new(),
+            is_started: false,
+            is_failed: false,
+            active_beats: Vec::new(),
+            parameters: Vec::new(),
+        }
+    }
+
+    pub fn is_template(&self) -> bool {
+        !self.parameters.is_empty()
+    }
+
+    // Whether the text has a `{parameter}` of the template in it, like the fact name `{npc}_met`
+    pub fn mentions_parameter(&self, text: &str) -> bool {
+        text.contains('{') && self.parameters.iter().any(|parameter| text.contains(&format!("{{{}}}", parameter)))
+    }
+
+    // A copy of the template with `{parameter}` replaced by its value in every name, fact name
+    // and string, all parameters of the template must be given
+    pub fn instantiate(&self, parameters: &[(&str, &str)]) -> Result<Story, TemplateError> {
+        if let Some((unknown, _)) = parameters.iter().find(|(name, _)| !self.parameters.iter().any(|parameter| parameter == name)) {
+            return Err(TemplateError::UnknownParameter {
+                template: self.name.clone(),
+                parameter: unknown.to_string(),
+            });
+        }
+        if let Some(missing) = self.parameters.iter().find(|parameter| !parameters.iter().any(|(name, _)| name == parameter)) {
+            return Err(TemplateError::MissingParameter {
+                template: self.name.clone(),
+                parameter: missing.clone(),
+            });
+        }
+
+        let mut story = self.clone();
+        story.parameters = Vec::new();
+        fill_parameters(&mut story.name, parameters);
+        for rule in story.pre_requisites.iter_mut() {
+            rule.fill_parameters(parameters);
+        }
+        for failure in story.failures.iter_mut() {
+            failure.fill_parameters(parameters);
+        }
+        for beat in story.beats.iter_mut() {
+            beat.fill_parameters(parameters);
+        }
+        for active_beat in story.active_beats.iter_mut() {
+            fill_parameters(active_beat, parameters);
+        }
+        Ok(story)
+    }
+
+    pub fn beat(&self, name: &str) -> Option<&StoryBeat> {
+        self.beats.iter().find(|beat| beat.name == name)
+    }
+
+    // The transition targets and recovery beats that are not beats of the story, with the name of
+    // the transition or failure that leads there
+    pub fn unknown_targets(&self) -> Vec<(&str, &str)> {
+        let transitions = self.beats.iter().flat_map(|beat| beat.transitions.iter());
+        let transition_targets = transitions.filter_map(|transition| {
+            transition.target.as_deref().map(|target| (transition.name.as_str(), target))
+        });
+        let failures = self.failures.iter().chain(self.beats.iter().flat_map(|beat| beat.failures.iter()));
+        let recoveries = failures.filter_map(|failure| {
+            failure.recovery.as_deref().map(|recovery| (failure.name.as_str(), recovery))
+        });
+        transition_targets.chain(recoveries).filter(|(_, target)| self.beat(target).is_none()).collect()
+    }
+
+    fn beat_index(&self, name: &str) -> Option<usize> {
+        self.beats.iter().position(|beat| beat.name == name)
+    }
+
+    // Starts the story if possible and evaluates its active beats
+    pub fn evaluate(&mut self, facts: &dyn FactSource) -> Vec<StoryStep> {
+        let mut steps = Vec::new();
         if !self.is_started {
-            self.is_started = self.pre_requisites.iter().all(|rule| rule.evaluate(facts));
+            if !self.start_if_possible(facts) {
+                return steps;
+            }
+            steps.push(StoryStep::Started);
+            steps.extend(self.active_beat_indices().map(StoryStep::BeatActivated));
+        } else if self.is_running() {
+            steps.extend(self.evaluate_active_beats(facts));
+        } else {
+            return steps;
+        }
+        if self.is_finished() {
+            steps.push(StoryStep::Finished);
+        }
+        steps
+    }
+
+    // Checks the failures of the story and of the active beats, completes the objectives of the
+    // active beats, finishes the beats whose rules, objectives and one of whose transitions hold and
+    // moves on to the targets of those transitions
+    pub fn evaluate_active_beats(&mut self, facts: &dyn FactSource) -> Vec<StoryStep> {
+        let mut steps = Vec::new();
+        if let Some(failure) = trigger_failure(&mut self.failures, facts) {
+            let recovery = self.failures[failure].recovery.clone();
+            self.fail(None, failure, recovery.as_deref(), &mut steps);
+            return steps;
+        }
+
+        let mut next_beats: Vec<String> = Vec::new();
+        for beat_name in std::mem::take(&mut self.active_beats) {
+            let Some(index) = self.beat_index(&beat_name) else {
+                continue;
+            };
+            let beat = &mut self.beats[index];
+            if let Some(failure) = trigger_failure(&mut beat.failures, facts) {
+                let recovery = beat.failures[failure].recovery.clone();
+                match self.fail(Some(index), failure, recovery.as_deref(), &mut steps) {
+                    Some(recovery) if !next_beats.contains(&recovery) => next_beats.push(recovery),
+                    Some(_) => {}
+                    None => return steps,
+                }
+                continue;
+            }
+
+            let beat = &mut self.beats[index];
+            let (mut completed, total) = beat.objective_progress();
+            for objective in beat.complete_objectives(facts) {
+                completed += 1;
+                steps.push(StoryStep::ObjectiveCompleted {
+                    beat: index,
+                    objective,
+                    completed,
+                    total,
+                });
+            }
+
+            let beat = &self.beats[index];
+            let target = if !beat.evaluate(facts) {
+                None
+            } else if beat.transitions.is_empty() {
+                Some(self.beats.get(index + 1).map(|next_beat| next_beat.name.clone()))
+            } else {
+                beat.transitions
+                    .iter()
+                    .find(|transition| transition.evaluate(facts))
+                    .map(|transition| transition.target.clone())
+            };
+
+            let next_beat = match target {
+                // Not finished yet, or waiting for one of its transitions
+                None => Some(beat_name),
+                // Only stories changed in code get here, the loaders reject them
+                Some(Some(target)) if self.beat_index(&target).is_none() => {
+                    warn!(
+                        "Story '{}': beat '{}' leads to '{}', which is not a beat of this story",
+                        self.name, beat_name, target
+                    );
+                    Some(beat_name)
+                }
+                Some(target) => {
+                    self.beats[index].finished = true;
+                    steps.push(StoryStep::BeatFinished(index));
+                    if let Some(next_index) = target.as_deref().and_then(|target| self.beat_index(target)) {
+                        self.beats[next_index].reset_objectives();
+                        steps.push(StoryStep::BeatActivated(next_index));
+                    }
+                    target
+                }
+            };
+            if let Some(next_beat) = next_beat {
+                if !next_beats.contains(&next_beat) {
+                    next_beats.push(next_beat);
+                }
+            }
+        }
+
+        self.active_beats = next_beats;
+        steps
+    }
+
+    // Fails the story, or activates the recovery beat of the failure, and returns the recovery beat
+    fn fail(
+        &mut self,
+        beat: Option<usize>,
+        failure: usize,
+        recovery: Option<&str>,
+        steps: &mut Vec<StoryStep>,
+    ) -> Option<String> {
+        steps.push(StoryStep::Failed { beat, failure });
+        let Some(index) = recovery.and_then(|recovery| self.beat_index(recovery)) else {
+            self.is_failed = true;
+            self.active_beats.clear();
+            return None;
+        };
+        self.beats[index].reset_objectives();
+        steps.push(StoryStep::BeatActivated(index));
+        if beat.is_none() {
+            self.active_beats = vec![self.beats[index].name.clone()];
+        }
+        Some(self.beats[index].name.clone())
+    }
+
+    // The effects applied for a step, with the name of the beat they belong to
+    pub fn step_effects(&self, step: StoryStep) -> Option<(&str, &[Effect])> {
+        let beat_name = |beat: Option<usize>| {
+            beat.and_then(|beat| self.beats.get(beat))
+                .map(|beat| beat.name.as_str())
+                .unwrap_or_default()
+        };
+        match step {
+            StoryStep::BeatFinished(beat) => self.beats.get(beat).map(|beat| (beat.name.as_str(), beat.effects.as_slice())),
+            StoryStep::ObjectiveCompleted { beat, objective, .. } => self
+                .beats
+                .get(beat)
+                .and_then(|story_beat| story_beat.objectives.get(objective))
+                .map(|objective| (beat_name(Some(beat)), objective.effects.as_slice())),
+            StoryStep::Failed { beat, failure } => {
+                let failures = match beat {
+                    Some(beat) => &self.beats.get(beat)?.failures,
+                    None => &self.failures,
+                };
+                failures.get(failure).map(|failure| (beat_name(beat), failure.effects.as_slice()))
+            }
+            _ => None,
+        }
+    }
+
+    // Applies the effects of a step, collecting the names of the facts whose value changed and the
+    // errors
+    pub fn apply_step_effects(
+        &self,
+        step: StoryStep,
+        facts: &mut FactsOfTheWorld,
+        changed_facts: &mut HashSet<String>,
+        fact_errors: &mut Vec<FactErrorOccurred>,
+    ) {
+        let Some((beat, effects)) = self.step_effects(step) else {
+            return;
+        };
+        for effect in effects {
+            let before = facts.facts.get(effect.fact_name()).cloned();
+            match effect.apply(facts) {
+                Ok(()) => {
+                    // Setting a fact to the value it has doesn't need another round of evaluation
+                    if facts.facts.get(effect.fact_name()) != before.as_ref() {
+                        changed_facts.insert(effect.fact_name().to_string());
+                    }
+                }
+                Err(error) => fact_errors.push(FactErrorOccurred {
+                    source: FactErrorSource::Beat {
+                        story: self.name.clone(),
+                        beat: beat.to_string(),
+                    },
+                    error,
+                }),
+            }
+        }
+    }
+
+    pub fn active_beat_indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.active_beats.iter().filter_map(|name| self.beat_index(name))
+    }
+
+    pub fn start_if_possible(&mut self, facts: &dyn FactSource) -> bool {
+        if !self.is_started && self.pre_requisites.iter().all(|rule| rule.evaluate(facts)) {
+            self.is_started = true;
+            self.active_beats = self.beats.first().map(|beat| beat.name.clone()).into_iter().collect();
         }
         self.is_started
     }
 
+    // Whether the story reached its end, a failed story never finishes
     pub fn is_finished(&self) -> bool {
-        self.active_beat_index >= self.beats.len()
+        self.is_started && !self.is_failed && self.active_beats.is_empty()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.is_started && !self.is_failed && !self.active_beats.is_empty()
+    }
+
+    // The facts any pre-requisite, failure, beat, objective or transition of the story looks at
+    pub fn fact_names(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        let beat_rules = self.beats.iter().flat_map(|beat| {
+            beat.rules
+                .iter()
+                .chain(beat.objectives.iter().flat_map(|objective| objective.rules.iter()))
+                .chain(beat.failures.iter().flat_map(|failure| failure.rules.iter()))
+                .chain(beat.transitions.iter().flat_map(|transition| transition.rules.iter()))
+        });
+        let failure_rules = self.failures.iter().flat_map(|failure| failure.rules.iter());
+        for rule in self.pre_requisites.iter().chain(failure_rules).chain(beat_rules) {
+            rule.collect_fact_names(&mut names);
+        }
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+
+    // Carries the progress of a previous version of this story over, matching beats, objectives and
+    // failures by name
+    pub fn take_progress_from(&mut self, previous: &Story) -> Vec<StoryReloadWarning> {
+        let mut warnings = Vec::new();
+        self.is_started = previous.is_started;
+        self.is_failed = previous.is_failed;
+        take_triggered_failures(&mut self.failures, &previous.failures);
+
+        for previous_beat in previous.beats.iter() {
+            if let Some(beat) = self.beats.iter_mut().find(|beat| beat.name == previous_beat.name) {
+                for objective in beat.objectives.iter_mut() {
+                    objective.completed = previous_beat
+                        .objectives
+                        .iter()
+                        .any(|previous| previous.name == objective.name && previous.completed);
+                }
+                take_triggered_failures(&mut beat.failures, &previous_beat.failures);
+            }
+        }
+
+        for finished_beat in previous.beats.iter().filter(|beat| beat.finished) {
+            match self.beats.iter_mut().find(|beat| beat.name == finished_beat.name) {
+                Some(beat) => beat.finished = true,
+                None => warnings.push(StoryReloadWarning::FinishedBeatRemoved {
+                    story: self.name.clone(),
+                    beat: finished_beat.name.clone(),
+                }),
+            }
+        }
+
+        self.active_beats = Vec::new();
+        for active_beat in &previous.active_beats {
+            let next_beat = match self.beat(active_beat) {
+                Some(beat) => Some(beat.name.clone()),
+                None => {
+                    let continuing_with = self.first_unfinished_beat().map(|beat| beat.name.clone());
+                    warnings.push(StoryReloadWarning::ActiveBeatRemoved {
+                        story: self.name.clone(),
+                        beat: active_beat.clone(),
+                        continuing_with: continuing_with.clone(),
+                    });
+                    continuing_with
+                }
+            };
+            if let Some(next_beat) = next_beat {
+                if !self.active_beats.contains(&next_beat) {
+                    self.active_beats.push(next_beat);
+                }
+            }
+        }
+
+        // The parser rejects unknown targets, but a reloaded `.stories.ron` or a rename in
+        // another beat can still leave a transition pointing nowhere
+        for beat in self.beats.iter() {
+            for transition in beat.transitions.iter() {
+                let Some(target) = &transition.target else {
+                    continue;
+                };
+                if self.beat(target).is_none() {
+                    warnings.push(StoryReloadWarning::TransitionTargetRemoved {
+                        story: self.name.clone(),
+                        beat: beat.name.clone(),
+                        transition: transition.name.clone(),
+                        target: target.clone(),
+                    });
+                }
+            }
+        }
+        warnings
+    }
+
+    fn first_unfinished_beat(&self) -> Option<&StoryBeat> {
+        self.beats.iter().find(|beat| !beat.finished)
+    }
+}
+
+fn take_triggered_failures(failures: &mut [Failure], previous_failures: &[Failure]) {
+    for failure in failures.iter_mut() {
+        failure.triggered = previous_failures
+            .iter()
+            .any(|previous| previous.name == failure.name && previous.triggered);
+    }
+}
+
+// Replaces every `{name}` in the text with the value of the parameter
+fn fill_parameters(text: &mut String, parameters: &[(&str, &str)]) {
+    if !text.contains('{') {
+        return;
+    }
+    for (name, value) in parameters {
+        *text = text.replace(&format!("{{{}}}", name), value);
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    UnknownTemplate(String),
+    MissingParameter { template: String, parameter: String },
+    UnknownParameter { template: String, parameter: String },
+}
+
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TemplateError::UnknownTemplate(template) => write!(f, "There is no story template named '{}'", template),
+            TemplateError::MissingParameter { template, parameter } => {
+                write!(f, "Story template '{}' needs a value for parameter '{}'", template, parameter)
+            }
+            TemplateError::UnknownParameter { template, parameter } => {
+                write!(f, "Story template '{}' has no parameter '{}'", template, parameter)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoryReloadWarning {
+    ActiveBeatRemoved {
+        story: String,
+        beat: String,
+        continuing_with: Option<String>,
+    },
+    FinishedBeatRemoved {
+        story: String,
+        beat: String,
+    },
+    TransitionTargetRemoved {
+        story: String,
+        beat: String,
+        transition: String,
+        target: String,
+    },
+}
+
+impl std::fmt::Display for StoryReloadWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoryReloadWarning::ActiveBeatRemoved {
+                story,
+                beat,
+                continuing_with: Some(next_beat),
+            } => write!(
+                f,
+                "Story '{}': the active beat '{}' was removed or renamed, continuing with '{}'",
+                story, beat, next_beat
+            ),
+            StoryReloadWarning::ActiveBeatRemoved {
+                story,
+                beat,
+                continuing_with: None,
+            } => write!(
+                f,
+                "Story '{}': the active beat '{}' was removed or renamed, there is no unfinished beat to continue with",
+                story, beat
+            ),
+            StoryReloadWarning::FinishedBeatRemoved { story, beat } => write!(
+                f,
+                "Story '{}': the finished beat '{}' was removed or renamed",
+                story, beat
+            ),
+            StoryReloadWarning::TransitionTargetRemoved {
+                story,
+                beat,
+                transition,
+                target,
+            } => write!(
+                f,
+                "Story '{}': the transition '{}' of beat '{}' leads to '{}', which was removed or renamed",
+                story, transition, beat, target
+            ),
+        }
+    }
+}
+
+// Maps fact names to the stories that have conditions on them, so a changed fact only
+// re-evaluates those stories. It is derived from the stories and rebuilt whenever it is stale.
+#[derive(Debug, Clone, Default)]
+pub struct StoryIndex {
+    stories_by_fact: HashMap<String, Vec<usize>>,
+    indexed_stories: usize,
+    // Stories that started or advanced, their next beat is checked on the next evaluation
+    pending: HashSet<usize>,
+    // The watched rules that held when they were last evaluated
+    holding_rules: HashSet<RuleId>,
+    // Stories an evaluation stopped at with the hash of the facts they depend on at that moment,
+    // they are evaluated again once one of those facts has another value
+    stalled: HashMap<usize, u64>,
+}
+
+impl StoryIndex {
+    fn add(&mut self, story_index: usize, story: &Story) {
+        for name in story.fact_names() {
+            self.stories_by_fact
+                .entry(name.to_string())
+                .or_default()
+                .push(story_index);
+        }
+        self.indexed_stories = self.indexed_stories.max(story_index + 1);
+    }
+
+    // Evaluates the pre-requisites of a story that hasn't started, or the rules of its active beats,
+    // and returns the ones that started or stopped to hold since they were last evaluated
+    fn rule_changes(&mut self, story_index: usize, story: &Story, facts: &dyn FactSource) -> Vec<StoryStep> {
+        let story_id = StoryId(story_index);
+        let watched_rules: Vec<(Option<usize>, usize, &Rule)> = if !story.is_started {
+            story.pre_requisites.iter().enumerate().map(|(rule, rule_data)| (None, rule, rule_data)).collect()
+        } else if story.is_running() {
+            story
+                .active_beat_indices()
+                .flat_map(|beat| {
+                    story.beats[beat].rules.iter().enumerate().map(move |(rule, rule_data)| (Some(beat), rule, rule_data))
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        // Rules that are no longer watched start out as not holding when they are watched again
+        self.holding_rules.retain(|rule_id| {
+            rule_id.story != story_id
+                || watched_rules.iter().any(|(beat, rule, _)| rule_id.beat == *beat && rule_id.rule == *rule)
+        });
+
+        let mut steps = Vec::new();
+        for (beat, rule, rule_data) in watched_rules {
+            let rule_id = RuleId { story: story_id, beat, rule };
+            let holds = rule_data.evaluate(facts);
+            let changed = if holds {
+                self.holding_rules.insert(rule_id)
+            } else {
+                self.holding_rules.remove(&rule_id)
+            };
+            if changed {
+                steps.push(StoryStep::RuleChanged { beat, rule, holds });
+            }
+        }
+        steps
+    }
+
+    // Whether the story is stalled and none of the facts it depends on changed since
+    fn is_stalled(&mut self, story_index: usize, story: &Story, facts: &dyn FactSource) -> bool {
+        match self.stalled.get(&story_index) {
+            None => false,
+            Some(&stalled_facts) if stalled_facts == depended_facts_hash(story, facts) => true,
+            Some(_) => {
+                self.stalled.remove(&story_index);
+                false
+            }
+        }
+    }
+
+    fn rebuild(&mut self, stories: &[Story]) {
+        self.stories_by_fact.clear();
+        self.indexed_stories = 0;
+        for (story_index, story) in stories.iter().enumerate() {
+            self.add(story_index, story);
+        }
+    }
+}
+
+pub(super) fn depended_facts_hash(story: &Story, facts: &dyn FactSource) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    for name in story.fact_names() {
+        facts.fact(name).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+// The index is derived data and never makes two engines different
+impl PartialEq for StoryIndex {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for StoryIndex {}
+
+impl Hash for StoryIndex {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
 // StoryEngine struct
 #[derive(Resource, Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub struct StoryEngine {
     pub stories: Vec<Story>,
+    // Stories with parameters, they are only played through their instances
+    #[serde(default)]
+    pub templates: Vec<Story>,
+    #[serde(skip)]
+    index: StoryIndex,
 }
 
 impl StoryEngine {
     pub fn new() -> Self {
         StoryEngine {
             stories: Vec::new(),
+            templates: Vec::new(),
+            index: StoryIndex::default(),
         }
     }
 
     pub fn add_story(&mut self, story: Story) {
+        if self.index.indexed_stories == self.stories.len() {
+            self.index.add(self.stories.len(), &story);
+        }
+        self.index.pending.insert(self.stories.len());
         self.stories.push(story);
     }
 
+    // Replaces the template with the same name, instances already made keep their definition
+    pub fn add_template(&mut self, template: Story) {
+        match self.templates.iter_mut().find(|existing| existing.name == template.name) {
+            Some(existing) => *existing = template,
+            None => self.templates.push(template),
+        }
+    }
+
+    // Adds a new story made from the template, any number of instances can run at the same time
+    pub fn instantiate(&mut self, template: &str, parameters: &[(&str, &str)]) -> Result<StoryId, TemplateError> {
+        let story = self
+            .templates
+            .iter()
+            .find(|existing| existing.name == template)
+            .ok_or_else(|| TemplateError::UnknownTemplate(template.to_string()))?
+            .instantiate(parameters)?;
+        let story_id = StoryId(self.stories.len());
+        self.add_story(story);
+        Ok(story_id)
+    }
+
+    // Call this after changing the conditions of stories directly in `stories`
+    pub fn invalidate_index(&mut self) {
+        self.index.indexed_stories = usize::MAX;
+    }
+
+    // Call this after deserializing, the index isn't saved. Every story is evaluated again and the
+    // watched rules that already hold don't report that they became true.
+    pub fn rebuild_index(&mut self, facts: &FactsOfTheWorld) {
+        self.index = StoryIndex::default();
+        self.index.rebuild(&self.stories);
+        for (story_index, story) in self.stories.iter().enumerate() {
+            self.index.rule_changes(story_index, story, &facts.facts);
+            self.index.pending.insert(story_index);
+        }
+    }
+
+    // The stories with conditions on any of the facts, in the order they were added
+    pub fn stories_depending_on<'a>(&mut self, fact_names: impl IntoIterator<Item = &'a str>) -> Vec<usize> {
+        if self.index.indexed_stories != self.stories.len() {
+            self.index.rebuild(&self.stories);
+        }
+        let mut story_indices: Vec<usize> = fact_names
+            .into_iter()
+            .filter_map(|name| self.index.stories_by_fact.get(name))
+            .flatten()
+            .copied()
+            .collect();
+        story_indices.sort_unstable();
+        story_indices.dedup();
+        story_indices
+    }
+
+    // Starts and advances the stories that depend on the changed facts, or that started or
+    // advanced during the last evaluation, and returns what happened to them by story index
+    pub fn evaluate_changed<'a>(
+        &mut self,
+        changed_facts: impl IntoIterator<Item = &'a str>,
+        facts: &dyn FactSource,
+    ) -> Vec<(usize, StoryStep)> {
+        let mut story_indices = self.stories_depending_on(changed_facts);
+        story_indices.extend(self.index.pending.drain());
+        story_indices.sort_unstable();
+        story_indices.dedup();
+
+        let mut story_steps = Vec::new();
+        for story_index in story_indices {
+            let Some(story) = self.stories.get_mut(story_index) else {
+                continue;
+            };
+            if self.index.is_stalled(story_index, story, facts) {
+                continue;
+            }
+            let mut steps = self.index.rule_changes(story_index, story, facts);
+            steps.extend(story.evaluate(facts));
+            if steps.iter().any(StoryStep::advances) {
+                self.index.pending.insert(story_index);
+            }
+            story_steps.extend(steps.into_iter().map(|step| (story_index, step)));
+        }
+        story_steps
+    }
+
+    // Swaps in a new definition of a story, keeping the progress made on the old one. The story is
+    // evaluated again, its new rules may already hold.
+    pub fn reload_story(&mut self, mut story: Story) -> Vec<StoryReloadWarning> {
+        match self.stories.iter().position(|existing| existing.name == story.name) {
+            Some(story_index) => {
+                let warnings = story.take_progress_from(&self.stories[story_index]);
+                self.stories[story_index] = story;
+                self.invalidate_index();
+                self.index.stalled.remove(&story_index);
+                self.index.pending.insert(story_index);
+                warnings
+            }
+            None => {
+                self.add_story(story);
+                Vec::new()
+            }
+        }
+    }
+
+    // Evaluates the stories, applies the effects of everything that happened and evaluates again
+    // until nothing changes anymore, so chained beats all advance at once. Stops with an error when
+    // the stories and facts get back to a state they were in before, or after `max_iterations`.
+    // The stories it stopped at stall until a fact they depend on gets another value, otherwise
+    // their own effects would start them over on the next evaluation.
+    pub fn evaluate_until_settled(
+        &mut self,
+        changed_facts: HashSet<String>,
+        facts: &mut FactsOfTheWorld,
+        max_iterations: usize,
+    ) -> StoryEvaluation {
+        let mut evaluation = StoryEvaluation::default();
+        let mut changed_facts = changed_facts;
+        let mut seen_states = HashSet::new();
+
+        for iteration in 0..max_iterations {
+            if changed_facts.is_empty() && self.index.pending.is_empty() {
+                return evaluation;
+            }
+            if !seen_states.insert(self.state_hash(facts)) {
+                evaluation.error = Some(StoryEvaluationError::Cycle { iteration });
+                self.stall(&changed_facts, facts);
+                return evaluation;
+            }
+
+            let steps = self.evaluate_changed(changed_facts.iter().map(String::as_str), &facts.facts);
+            changed_facts = HashSet::new();
+            for &(story_index, step) in &steps {
+                self.stories[story_index].apply_step_effects(step, facts, &mut changed_facts, &mut evaluation.fact_errors);
+            }
+            evaluation.steps.extend(steps);
+            evaluation.iterations = iteration + 1;
+        }
+
+        if !changed_facts.is_empty() || !self.index.pending.is_empty() {
+            evaluation.error = Some(StoryEvaluationError::IterationLimit { max_iterations });
+            self.stall(&changed_facts, facts);
+        }
+        evaluation
+    }
+
+    // Stalls the stories the next iteration would have evaluated
+    fn stall(&mut self, changed_facts: &HashSet<String>, facts: &FactsOfTheWorld) {
+        let mut story_indices = self.stories_depending_on(changed_facts.iter().map(String::as_str));
+        story_indices.extend(self.index.pending.drain());
+        for story_index in story_indices {
+            let stalled_facts = depended_facts_hash(&self.stories[story_index], &facts.facts);
+            self.index.stalled.insert(story_index, stalled_facts);
+        }
+    }
+
+    fn state_hash(&self, facts: &FactsOfTheWorld) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        self.stories.hash(&mut hasher);
+        facts.hash_facts(&mut hasher);
+        hasher.finish()
+    }
+
+    pub fn story(&self, story: StoryId) -> Option<&Story> {
+        self.stories.get(story.0)
+    }
+
+    pub fn beat(&self, beat: BeatId) -> Option<&StoryBeat> {
+        self.story(beat.story).and_then(|story| story.beats.get(beat.beat))
+    }
+
+    pub fn objective(&self, beat: BeatId, objective: usize) -> Option<&Objective> {
+        self.beat(beat).and_then(|story_beat| story_beat.objectives.get(objective))
+    }
+
+    // A failure of the story itself when `beat` is None
+    pub fn failure(&self, story: StoryId, beat: Option<BeatId>, failure: usize) -> Option<&Failure> {
+        match beat {
+            Some(beat) => self.beat(beat).and_then(|story_beat| story_beat.failures.get(failure)),
+            None => self.story(story).and_then(|story| story.failures.get(failure)),
+        }
+    }
+
+    // A pre-requisite of the story when the rule has no beat
+    pub fn rule(&self, rule: RuleId) -> Option<&Rule> {
+        match rule.beat {
+            Some(beat) => self
+                .beat(BeatId { story: rule.story, beat })
+                .and_then(|story_beat| story_beat.rules.get(rule.rule)),
+            None => self.story(rule.story).and_then(|story| story.pre_requisites.get(rule.rule)),
+        }
+    }
+
     // Check if all stories are finished
     pub fn all_stories_finished(&self) -> bool {
         self.stories.iter().all(|story| story.is_finished())
     }
 }
 
-#[derive(Event)]
-pub struct StoryBeatFinished {
-    pub story: Story,
-    pub beat: StoryBeat,
+// Where the effect that failed to apply comes from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FactErrorSource {
+    Beat { story: String, beat: String },
+    Storylet(String),
+    Response(String),
+    Conversation(String),
+}
+
+impl std::fmt::Display for FactErrorSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FactErrorSource::Beat { story, beat } => write!(f, "story '{}', beat '{}'", story, beat),
+            FactErrorSource::Storylet(storylet) => write!(f, "storylet '{}'", storylet),
+            FactErrorSource::Response(response) => write!(f, "response '{}'", response),
+            FactErrorSource::Conversation(conversation) => write!(f, "conversation '{}'", conversation),
+        }
+    }
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct FactErrorOccurred {
+    pub source: FactErrorSource,
+    pub error: FactError,
+}
+
+// Everything that happened while evaluating the stories until they settled
+#[derive(Debug, Default)]
+pub struct StoryEvaluation {
+    pub steps: Vec<(usize, StoryStep)>,
+    pub fact_errors: Vec<FactErrorOccurred>,
+    pub iterations: usize,
+    pub error: Option<StoryEvaluationError>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoryEvaluationError {
+    // The stories and facts got back to a state they were in earlier in the same evaluation
+    Cycle { iteration: usize },
+    IterationLimit { max_iterations: usize },
+}
+
+impl std::fmt::Display for StoryEvaluationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoryEvaluationError::Cycle { iteration } => write!(
+                f,
+                "the stories went around in a cycle, iteration {} repeated an earlier state",
+                iteration
+            ),
+            StoryEvaluationError::IterationLimit { max_iterations } => write!(
+                f,
+                "the stories did not settle within {} iterations",
+                max_iterations
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StoryEvaluationError {}
+
+// How the story evaluator runs each frame
+#[derive(Resource, Debug, Clone)]
+pub struct StoryEvaluationSettings {
+    // Evaluations of the stories per frame before giving up, each applies the effects of the last one
+    pub max_iterations: usize,
+}
+
+impl Default for StoryEvaluationSettings {
+    fn default() -> Self {
+        StoryEvaluationSettings { max_iterations: 100 }
+    }
+}
+
+// The lifecycle events only carry ids, look the stories, beats and rules up in the StoryEngine
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoryStarted {
+    pub story: StoryId,
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BeatActivated {
+    pub beat: BeatId,
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectiveCompleted {
+    pub beat: BeatId,
+    pub objective: usize,
+    // Progress of the beat including this objective, as in 2/3
+    pub completed: usize,
+    pub total: usize,
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BeatFinished {
+    pub beat: BeatId,
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoryFinished {
+    pub story: StoryId,
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoryFailed {
+    pub story: StoryId,
+    // None when a failure of the story itself triggered
+    pub beat: Option<BeatId>,
+    pub failure: usize,
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RuleBecameTrue {
+    pub rule: RuleId,
+}
+
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RuleBecameFalse {
+    pub rule: RuleId,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub enum Effect {
     SetFact(Fact),
+    AddInt {
+        fact_name: String,
+        value: i32,
+    },
+    SubtractInt {
+        fact_name: String,
+        value: i32,
+    },
+    MultiplyInt {
+        fact_name: String,
+        value: i32,
+    },
+    ClampInt {
+        fact_name: String,
+        min: i32,
+        max: i32,
+    },
+    ToggleBool {
+        fact_name: String,
+    },
+    RemoveFromList {
+        fact_name: String,
+        value: String,
+    },
+    ClearList {
+        fact_name: String,
+    },
+    RemoveFact {
+        fact_name: String,
+    },
+    AddFloat {
+        fact_name: String,
+        value: HashableFloat,
+    },
+    SubtractFloat {
+        fact_name: String,
+        value: HashableFloat,
+    },
+    MultiplyFloat {
+        fact_name: String,
+        value: HashableFloat,
+    },
+    ClampFloat {
+        fact_name: String,
+        min: HashableFloat,
+        max: HashableFloat,
+    },
 }
 
 impl Effect {
-    pub fn apply(&self, fact_store: &mut FactsOfTheWorld) {
+    fn fill_parameters(&mut self, parameters: &[(&str, &str)]) {
+        match self {
+            Effect::SetFact(fact) => fact.fill_parameters(parameters),
+            Effect::RemoveFromList { fact_name, value } => {
+                fill_parameters(fact_name, parameters);
+                fill_parameters(value, parameters);
+            }
+            Effect::AddInt { fact_name, .. }
+            | Effect::SubtractInt { fact_name, .. }
+            | Effect::MultiplyInt { fact_name, .. }
+            | Effect::ClampInt { fact_name, .. }
+            | Effect::ToggleBool { fact_name }
+            | Effect::ClearList { fact_name }
+            | Effect::RemoveFact { fact_name }
+            | Effect::AddFloat { fact_name, .. }
+            | Effect::SubtractFloat { fact_name, .. }
+            | Effect::MultiplyFloat { fact_name, .. }
+            | Effect::ClampFloat { fact_name, .. } => fill_parameters(fact_name, parameters),
+        }
+    }
+
+    // The fact the effect changes
+    pub fn fact_name(&self) -> &str {
+        match self {
+            Effect::SetFact(fact) => fact.name(),
+            Effect::AddInt { fact_name, .. }
+            | Effect::SubtractInt { fact_name, .. }
+            | Effect::MultiplyInt { fact_name, .. }
+            | Effect::ClampInt { fact_name, .. }
+            | Effect::ToggleBool { fact_name }
+            | Effect::RemoveFromList { fact_name, .. }
+            | Effect::ClearList { fact_name }
+            | Effect::RemoveFact { fact_name }
+            | Effect::AddFloat { fact_name, .. }
+            | Effect::SubtractFloat { fact_name, .. }
+            | Effect::MultiplyFloat { fact_name, .. }
+            | Effect::ClampFloat { fact_name, .. } => fact_name,
+        }
+    }
+
+    pub fn apply(&self, fact_store: &mut FactsOfTheWorld) -> Result<(), FactError> {
+        self.apply_as(self.fact_name(), fact_store)
+    }
+
+    // Applies the effect to the fact with the given name instead of its own, used for scoped facts
+    pub fn apply_as(&self, fact_name: &str, fact_store: &mut FactsOfTheWorld) -> Result<(), FactError> {
+        let name = fact_name.to_string();
         match self {
             Effect::SetFact(fact) => {
                 match fact {
-                    Fact::Int(name, value) => fact_store.store_int(name.clone(), *value),
-                    Fact::String(name, value) => fact_store.store_string(name.clone(), value.clone()),
-                    Fact::Bool(name, value) => fact_store.store_bool(name.clone(), *value),
-                    Fact::StringList(name, values) => {
+                    Fact::Int(_, value) => fact_store.try_store_int(name, *value),
+                    Fact::String(_, value) => fact_store.try_store_string(name, value.clone()),
+                    Fact::Bool(_, value) => fact_store.try_store_bool(name, *value),
+                    Fact::Float(_, value) => fact_store.try_store_float(name, value.0),
+                    Fact::StringList(_, values) => {
                         for value in &values.0 {
-                            fact_store.add_to_list(name.clone(), value.clone());
+                            fact_store.try_add_to_list(name.clone(), value.clone())?;
                         }
+                        Ok(())
                     },
                 }
             }
+            Effect::AddInt { value, .. } => fact_store.try_add_to_int(name, *value),
+            Effect::SubtractInt { value, .. } => fact_store.try_subtract_from_int(name, *value),
+            Effect::MultiplyInt { value, .. } => fact_store.try_multiply_int(name, *value),
+            Effect::ClampInt { min, max, .. } => fact_store.try_clamp_int(name, *min, *max),
+            Effect::ToggleBool { .. } => fact_store.try_toggle_bool(name),
+            Effect::RemoveFromList { value, .. } => fact_store.try_remove_from_list(name, value.clone()),
+            Effect::ClearList { .. } => fact_store.try_clear_list(name),
+            Effect::RemoveFact { .. } => {
+                fact_store.remove_fact(fact_name);
+                Ok(())
+            }
+            Effect::AddFloat { value, .. } => fact_store.try_add_to_float(name, value.0),
+            Effect::SubtractFloat { value, .. } => fact_store.try_subtract_from_float(name, value.0),
+            Effect::MultiplyFloat { value, .. } => fact_store.try_multiply_float(name, value.0),
+            Effect::ClampFloat { min, max, .. } => fact_store.try_clamp_float(name, min.0, max.0),
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn story_engine(source: &str) -> StoryEngine {
+        let mut story_engine = StoryEngine::new();
+        for story in crate::beats::parser::parse_stories(source).unwrap() {
+            story_engine.add_story(story);
+        }
+        story_engine
+    }
+
+    #[test]
+    fn only_reports_facts_whose_value_changed() {
+        let story_engine = story_engine("# Forge\n## Light\nEffects:\n    lit = true\n    heat += 1\n");
+        let mut facts = FactsOfTheWorld::new();
+        facts.store_bool("lit".to_string(), true);
+        let mut changed_facts = HashSet::new();
+        let mut fact_errors = Vec::new();
+        let step = StoryStep::BeatFinished(0);
+        story_engine.stories[0].apply_step_effects(step, &mut facts, &mut changed_facts, &mut fact_errors);
+        assert!(fact_errors.is_empty());
+        assert_eq!(changed_facts, HashSet::from_iter(["heat".to_string()]));
+    }
+
+    #[test]
+    fn detects_cycles_back_to_the_first_state() {
+        let mut story_engine = story_engine(
+            "# Blink\n## On\nDark:\n    !lit\nEffects:\n    toggle lit\nNext -> Off:\n## Off\nBright:\n    lit\nEffects:\n    toggle lit\nNext -> On:\n",
+        );
+        let story = &mut story_engine.stories[0];
+        story.is_started = true;
+        story.active_beats = vec!["On".to_string()];
+        for beat in story.beats.iter_mut() {
+            beat.finished = true;
+        }
+        let mut facts = FactsOfTheWorld::new();
+        facts.store_bool("lit".to_string(), false);
+        let evaluation = story_engine.evaluate_until_settled(HashSet::new(), &mut facts, 10);
+        assert_eq!(evaluation.error, Some(StoryEvaluationError::Cycle { iteration: 2 }));
+
+        // Neither the next evaluation nor the updates of its own effects start the cycle over
+        assert!(story_engine.evaluate_until_settled(HashSet::new(), &mut facts, 10).steps.is_empty());
+        let lit = HashSet::from_iter(["lit".to_string()]);
+        let evaluation = story_engine.evaluate_until_settled(lit.clone(), &mut facts, 10);
+        assert!(evaluation.steps.is_empty());
+        assert_eq!(evaluation.error, None);
+
+        // Another value of a fact it depends on does
+        facts.store_bool("lit".to_string(), true);
+        story_engine.evaluate_until_settled(lit.clone(), &mut facts, 10);
+        facts.store_bool("lit".to_string(), false);
+        let evaluation = story_engine.evaluate_until_settled(lit, &mut facts, 10);
+        assert_eq!(evaluation.error, Some(StoryEvaluationError::Cycle { iteration: 2 }));
+    }
 
+    #[test]
+    fn evaluates_reloaded_stories_again() {
+        let mut story_engine = story_engine("# Forge\n## Light\nHot:\n    heat > 3\n");
+        let mut facts = FactsOfTheWorld::new();
+        facts.store_int("heat".to_string(), 2);
+        story_engine.evaluate_until_settled(HashSet::new(), &mut facts, 10);
+        assert_eq!(story_engine.stories[0].active_beats, vec!["Light".to_string()]);
 
+        let reloaded =
+            crate::beats::parser::parse_stories("# Forge\n## Light\nWarm:\n    heat > 1\n").unwrap();
+        assert!(story_engine.reload_story(reloaded[0].clone()).is_empty());
+        let evaluation = story_engine.evaluate_until_settled(HashSet::new(), &mut facts, 10);
+        assert!(evaluation.steps.contains(&(0, StoryStep::BeatFinished(0))));
+    }
+
+    #[test]
+    fn warns_about_transitions_to_removed_beats() {
+        let mut story_engine =
+            story_engine("# Forge\n## Light\nDone -> Quench:\n    heat > 3\n## Quench\n");
+        let mut reloaded = crate::beats::parser::parse_stories(
+            "# Forge\n## Light\nDone -> Cool:\n    heat > 3\n## Cool\n",
+        )
+        .unwrap();
+        reloaded[0].beats[0].transitions[0].target = Some("Quench".to_string());
+        reloaded[0].beats.pop();
+        let warnings = story_engine.reload_story(reloaded[0].clone());
+        assert_eq!(
+            warnings,
+            vec![StoryReloadWarning::TransitionTargetRemoved {
+                story: "Forge".to_string(),
+                beat: "Light".to_string(),
+                transition: "Done".to_string(),
+                target: "Quench".to_string(),
+            }]
+        );
+    }
 
+    #[test]
+    fn only_evaluates_stories_depending_on_changed_facts() {
+        let mut story_engine = story_engine("# Forge\n## Light\nHot:\n    heat > 3\n# Market\n## Buy\nRich:\n    gold > 3\n");
+        let mut facts = FactsOfTheWorld::new();
+        story_engine.evaluate_until_settled(HashSet::new(), &mut facts, 10);
+        assert_eq!(story_engine.stories_depending_on(["gold"]), vec![1]);
+
+        facts.store_int("heat".to_string(), 5);
+        facts.store_int("gold".to_string(), 5);
+        let steps = story_engine.evaluate_changed(["gold"], &facts.facts);
+        assert!(steps.iter().all(|(story_index, _)| *story_index == 1));
+        assert!(steps.contains(&(1, StoryStep::BeatFinished(0))));
+        assert!(story_engine.stories[0].active_beats.contains(&"Light".to_string()));
+    }
+
+    #[test]
+    fn rebuilds_the_index_after_loading() {
+        // Saved while the forge was already hot but the tongs were still missing
+        let mut story_engine = story_engine("# Forge\n## Light\nHot:\n    heat > 3\n- Tongs:\n    has_tongs\n## Quench\n");
+        let mut facts = FactsOfTheWorld::new();
+        facts.store_int("heat".to_string(), 5);
+        facts.store_bool("has_tongs".to_string(), false);
+        let evaluation = story_engine.evaluate_until_settled(HashSet::new(), &mut facts, 10);
+        let hot = StoryStep::RuleChanged { beat: Some(0), rule: 0, holds: true };
+        assert!(evaluation.steps.contains(&(0, hot)));
+
+        let mut loaded: StoryEngine = ron::from_str(&ron::to_string(&story_engine).unwrap()).unwrap();
+        loaded.rebuild_index(&facts);
+        facts.store_bool("has_tongs".to_string(), true);
+        let evaluation = loaded.evaluate_until_settled(HashSet::new(), &mut facts, 10);
+        assert!(evaluation.steps.contains(&(0, StoryStep::BeatFinished(0))));
+        assert!(!evaluation.steps.contains(&(0, hot)));
+    }
+
+    #[test]
+    fn counts_conjuncts_but_not_alternatives() {
+        let count = |condition: &str| crate::beats::expression::parse_condition(condition).unwrap().conjunct_count();
+        assert_eq!(count("a && b && c"), 3);
+        assert_eq!(count("a || b || c"), 1);
+        assert_eq!(count("(a && b) || c"), 1);
+        assert_eq!(count("!(a && b)"), 1);
+        assert_eq!(count("(a && b) && (c || d)"), 3);
+    }
+
+    #[test]
+    fn stays_at_beats_whose_transition_leads_nowhere() {
+        let mut story_engine = story_engine("# Forge\n## Light\nHot -> Quench:\n    heat > 3\n## Quench\n");
+        story_engine.stories[0].beats[0].transitions[0].target = Some("Temper".to_string());
+        story_engine.invalidate_index();
+        assert_eq!(story_engine.stories[0].unknown_targets(), vec![("Hot", "Temper")]);
+
+        let mut facts = FactsOfTheWorld::new();
+        facts.store_int("heat".to_string(), 5);
+        let evaluation = story_engine.evaluate_until_settled(HashSet::new(), &mut facts, 10);
+        let finished = |step: &StoryStep| matches!(step, StoryStep::BeatFinished(_) | StoryStep::Finished);
+        assert!(!evaluation.steps.iter().any(|(_, step)| finished(step)));
+        assert_eq!(story_engine.stories[0].active_beats, vec!["Light".to_string()]);
+        assert!(!story_engine.stories[0].beats[0].finished);
+    }
+
+    fn objectives_completed(evaluation: &StoryEvaluation) -> Vec<(usize, usize, usize)> {
+        evaluation
+            .steps
+            .iter()
+            .filter_map(|(_, step)| match step {
+                StoryStep::ObjectiveCompleted {
+                    objective,
+                    completed,
+                    total,
+                    ..
+                } => Some((*objective, *completed, *total)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn gather(completion: &str) -> StoryEngine {
+        story_engine(&format!(
+            "# Supplies\n## Gather\nComplete: {}\n- Rope:\n    rope\n- Food:\n    food\n- Torch:\n    torch\n\
+             ## Leave\nGone:\n    gone\n",
+            completion
+        ))
+    }
+
+    fn supplies(found: &[&str]) -> FactsOfTheWorld {
+        let mut facts = FactsOfTheWorld::new();
+        for name in ["rope", "food", "torch", "gone"] {
+            facts.store_bool(name.to_string(), found.contains(&name));
+        }
+        facts
+    }
+
+    #[test]
+    fn reports_the_progress_of_objectives() {
+        let mut story_engine = gather("all");
+        let mut facts = supplies(&["rope", "torch"]);
+        let evaluation = story_engine.evaluate_until_settled(HashSet::new(), &mut facts, 10);
+        assert_eq!(objectives_completed(&evaluation), vec![(0, 1, 3), (2, 2, 3)]);
+        assert_eq!(story_engine.stories[0].beats[0].objective_progress(), (2, 3));
+        assert_eq!(story_engine.stories[0].active_beats, vec!["Gather".to_string()]);
+
+        facts.store_bool("food".to_string(), true);
+        let evaluation = story_engine.evaluate_until_settled(HashSet::from_iter(["food".to_string()]), &mut facts, 10);
+        assert_eq!(objectives_completed(&evaluation), vec![(1, 3, 3)]);
+        assert!(evaluation.steps.contains(&(0, StoryStep::BeatFinished(0))));
+    }
+
+    #[test]
+    fn finishes_beats_by_their_completion_policy() {
+        let finishes = |completion: &str, found: &[&str]| {
+            let mut story_engine = gather(completion);
+            let evaluation = story_engine.evaluate_until_settled(HashSet::new(), &mut supplies(found), 10);
+            evaluation.steps.contains(&(0, StoryStep::BeatFinished(0)))
+        };
+        assert!(!finishes("all", &["rope", "food"]));
+        assert!(finishes("all", &["rope", "food", "torch"]));
+        assert!(!finishes("any", &[]));
+        assert!(finishes("any", &["torch"]));
+        assert!(!finishes("2", &["food"]));
+        assert!(finishes("2", &["rope", "torch"]));
+
+        // A beat can't need more objectives than it has
+        assert!(CompletionPolicy::AtLeast(5).is_met(3, 3));
+        assert!(CompletionPolicy::Any.is_met(0, 0));
+    }
+
+    #[test]
+    fn resets_objectives_when_a_beat_is_entered_again() {
+        let mut story_engine = story_engine(
+            "# Camp\n## Gather\n- Rope:\n    rope\n- Food:\n    food\nHome -> Rest:\n\
+             ## Rest\nRested:\n    gone\nOut -> Gather:\n",
+        );
+        let mut facts = supplies(&["rope", "food"]);
+        story_engine.evaluate_until_settled(HashSet::new(), &mut facts, 10);
+        assert_eq!(story_engine.stories[0].active_beats, vec!["Rest".to_string()]);
+
+        let mut changed = HashSet::new();
+        for (name, value) in [("rope", false), ("food", false), ("gone", true)] {
+            facts.store_bool(name.to_string(), value);
+            changed.insert(name.to_string());
+        }
+        let evaluation = story_engine.evaluate_until_settled(changed, &mut facts, 10);
+        assert_eq!(evaluation.error, None);
+        assert_eq!(story_engine.stories[0].active_beats, vec!["Gather".to_string()]);
+        assert_eq!(story_engine.stories[0].beats[0].objective_progress(), (0, 2));
+    }
+
+    const FETCH_TEMPLATE: &str = "# Fetch {item} for {npc} (npc, item)\n\
+        ## Ask {npc}\nConversation: {npc} Greeting\nAsked:\n    {npc}_asked\nGo -> Find {item}:\n\
+        ## Find {item}\nFound:\n    \"{item}\" in inventory\n! Lost -> Ask {npc}:\n    {item}_lost\n\
+        Back -> Thank {npc}:\n\
+        ## Thank {npc}\n";
+
+    fn templates() -> StoryEngine {
+        let mut story_engine = StoryEngine::new();
+        for template in crate::beats::parser::parse_stories(FETCH_TEMPLATE).unwrap() {
+            story_engine.add_template(template);
+        }
+        story_engine
+    }
+
+    #[test]
+    fn rejects_unknown_templates_and_parameters() {
+        let mut story_engine = templates();
+        let template = "Fetch {item} for {npc}";
+        assert_eq!(
+            story_engine.instantiate("Fetch", &[]),
+            Err(TemplateError::UnknownTemplate("Fetch".to_string()))
+        );
+        assert_eq!(
+            story_engine.instantiate(template, &[("npc", "smith")]),
+            Err(TemplateError::MissingParameter {
+                template: template.to_string(),
+                parameter: "item".to_string(),
+            })
+        );
+        assert_eq!(
+            story_engine.instantiate(template, &[("npc", "smith"), ("item", "hammer"), ("reward", "gold")]),
+            Err(TemplateError::UnknownParameter {
+                template: template.to_string(),
+                parameter: "reward".to_string(),
+            })
+        );
+        assert!(story_engine.stories.is_empty());
+    }
+
+    #[test]
+    fn fills_parameters_into_targets_recoveries_and_conversations() {
+        let mut story_engine = templates();
+        let story_id = story_engine
+            .instantiate("Fetch {item} for {npc}", &[("npc", "smith"), ("item", "hammer")])
+            .unwrap();
+        let story = story_engine.story(story_id).unwrap();
+        assert_eq!(story.name, "Fetch hammer for smith");
+        assert!(!story.is_template());
+        assert!(story.unknown_targets().is_empty());
+
+        let [ask, find, thank] = &story.beats[..] else {
+            panic!("expected three beats");
+        };
+        let names = [ask, find, thank].map(|beat| beat.name.as_str());
+        assert_eq!(names, ["Ask smith", "Find hammer", "Thank smith"]);
+        assert_eq!(ask.conversation.as_deref(), Some("smith Greeting"));
+        assert_eq!(ask.transitions[0].target.as_deref(), Some("Find hammer"));
+        assert_eq!(find.transitions[0].target.as_deref(), Some("Thank smith"));
+        assert_eq!(find.failures[0].recovery.as_deref(), Some("Ask smith"));
+        assert_eq!(story.fact_names(), vec!["hammer_lost", "inventory", "smith_asked"]);
+        assert_eq!(
+            find.rules[0].conditions,
+            vec![Condition::ListContains {
+                fact_name: "inventory".to_string(),
+                expected_value: "hammer".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn plays_instances_of_a_template_apart() {
+        let mut story_engine = templates();
+        let template = "Fetch {item} for {npc}";
+        let smith = story_engine.instantiate(template, &[("npc", "smith"), ("item", "hammer")]).unwrap();
+        let tailor = story_engine.instantiate(template, &[("npc", "tailor"), ("item", "needle")]).unwrap();
+        assert_eq!((smith, tailor), (StoryId(0), StoryId(1)));
+        assert_eq!(story_engine.templates.len(), 1);
+
+        let mut facts = FactsOfTheWorld::new();
+        facts.store_bool("smith_asked".to_string(), true);
+        story_engine.evaluate_until_settled(HashSet::new(), &mut facts, 10);
+        assert_eq!(story_engine.story(smith).unwrap().active_beats, vec!["Find hammer".to_string()]);
+        assert_eq!(story_engine.story(tailor).unwrap().active_beats, vec!["Ask tailor".to_string()]);
+    }
+
+    #[test]
+    fn saturates_int_arithmetic() {
+        let mut facts = FactsOfTheWorld::new();
+        facts.store_int("gold".to_string(), i32::MAX - 1);
+        facts.add_to_int("gold".to_string(), 5);
+        assert_eq!(facts.get_int("gold"), Some(&i32::MAX));
+        assert!(matches!(facts.try_add_to_int("gold".to_string(), 1), Err(FactError::Overflow { .. })));
+
+        facts.store_int("debt".to_string(), i32::MIN + 1);
+        facts.subtract_from_int("debt".to_string(), 5);
+        assert_eq!(facts.get_int("debt"), Some(&i32::MIN));
+    }
+
+    #[test]
+    fn treats_missing_lists_alike() {
+        let mut facts = FactsOfTheWorld::new();
+        assert_eq!(facts.try_remove_from_list("inventory".to_string(), "torch".to_string()), Ok(()));
+        assert_eq!(facts.try_clear_list("inventory".to_string()), Ok(()));
+        assert!(facts.updated_facts.is_empty());
+
+        facts.store_int("gold".to_string(), 3);
+        assert!(facts.try_remove_from_list("gold".to_string(), "torch".to_string()).is_err());
+        assert!(facts.try_clear_list("gold".to_string()).is_err());
+    }
+
+    #[test]
+    fn reports_a_fact_either_as_updated_or_as_removed() {
+        let mut facts = FactsOfTheWorld::new();
+        facts.store_int("gold".to_string(), 3);
+        facts.remove_fact("gold");
+        assert!(facts.updated_facts.is_empty());
+        assert!(facts.removed_facts.contains("gold"));
+
+        facts.store_int("gold".to_string(), 4);
+        assert!(facts.removed_facts.is_empty());
+        assert!(facts.updated_facts.contains(&Fact::Int("gold".to_string(), 4)));
+    }
+
+    fn failures(evaluation: &StoryEvaluation) -> usize {
+        evaluation.steps.iter().filter(|(_, step)| matches!(step, StoryStep::Failed { .. })).count()
+    }
+
+    fn ambush_facts(bandits: i32) -> FactsOfTheWorld {
+        let mut facts = FactsOfTheWorld::new();
+        facts.store_int("bandits".to_string(), bandits);
+        facts.store_int("gold".to_string(), 100);
+        facts.store_bool("arrived".to_string(), false);
+        facts
+    }
+
+    #[test]
+    fn triggers_story_failures_with_recovery_once_while_they_hold() {
+        let mut story_engine = story_engine(
+            "# Escort\n! Ambush -> Hide:\n    bandits > 0\n    => gold -= 10\n\
+             ## Travel\nArrived:\n    arrived\n## Hide\nSafe:\n    arrived\n",
+        );
+        let mut facts = ambush_facts(1);
+        let evaluation = story_engine.evaluate_until_settled(HashSet::new(), &mut facts, 10);
+        assert_eq!(evaluation.error, None);
+        assert_eq!(failures(&evaluation), 1);
+        assert_eq!(facts.get_int("gold"), Some(&90));
+        assert_eq!(story_engine.stories[0].active_beats, vec!["Hide".to_string()]);
+
+        let bandits = HashSet::from_iter(["bandits".to_string()]);
+        let evaluation = story_engine.evaluate_until_settled(bandits, &mut facts, 10);
+        assert!(evaluation.steps.is_empty());
+
+        // Only a new ambush triggers the failure again
+        for bandits in [0, 2] {
+            facts.store_int("bandits".to_string(), bandits);
+            story_engine.evaluate_until_settled(HashSet::from_iter(["bandits".to_string()]), &mut facts, 10);
+        }
+        assert_eq!(facts.get_int("gold"), Some(&80));
+    }
+
+    #[test]
+    fn fails_stories_without_recovery() {
+        let mut story_engine = story_engine("# Escort\n! Ambush:\n    bandits > 0\n## Travel\nArrived:\n    arrived\n");
+        let evaluation = story_engine.evaluate_until_settled(HashSet::new(), &mut ambush_facts(1), 10);
+        assert_eq!(failures(&evaluation), 1);
+        assert!(story_engine.stories[0].is_failed);
+        assert!(story_engine.stories[0].active_beats.is_empty());
+    }
+
+    #[test]
+    fn triggers_beat_failures_with_recovery_once_while_they_hold() {
+        let mut story_engine = story_engine(
+            "# Escort\n## Travel\nArrived:\n    arrived\n! Ambush -> Travel:\n    bandits > 0\n    => gold -= 10\n",
+        );
+        let mut facts = ambush_facts(1);
+        let evaluation = story_engine.evaluate_until_settled(HashSet::new(), &mut facts, 10);
+        assert_eq!(evaluation.error, None);
+        assert_eq!(failures(&evaluation), 1);
+        assert_eq!(facts.get_int("gold"), Some(&90));
+        assert_eq!(story_engine.stories[0].active_beats, vec!["Travel".to_string()]);
+        assert!(!story_engine.stories[0].is_failed);
+    }
+
+    #[test]
+    fn fails_beats_without_recovery() {
+        let mut story_engine = story_engine("# Escort\n## Travel\nArrived:\n    arrived\n! Ambush:\n    bandits > 0\n");
+        let evaluation = story_engine.evaluate_until_settled(HashSet::new(), &mut ambush_facts(1), 10);
+        assert_eq!(failures(&evaluation), 1);
+        assert_eq!(evaluation.steps.last(), Some(&(0, StoryStep::Failed { beat: Some(0), failure: 0 })));
+        assert!(story_engine.stories[0].is_failed);
+    }
+}
@@ -0,0 +1,433 @@
+use crate::beats::assets::ResponseAsset;
+use crate::beats::data::{Condition, Effect, Fact, FactError, FactErrorOccurred, FactErrorSource, FactSource, FactsOfTheWorld};
+use crate::beats::parser::{
+    block_heading, condition_or_effect_line, float_literal, heading, parse_line, rest_of_line, ConditionOrEffect,
+    ParseResult, StoryParseError,
+};
+use crate::loading::StoryAssets;
+use bevy::ecs::entity::Entity;
+use bevy::log::{info, warn};
+use bevy::prelude::{Assets, Event, EventReader, EventWriter, Res, ResMut, Resource, Time};
+use bevy::utils::hashbrown::HashMap;
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::{space0, space1};
+use nom::combinator::{all_consuming, map, value};
+use nom::sequence::{delimited, preceded, tuple};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+/*
+Contextual dialogue: the game asks for a concept, like a guard seeing the player, together with
+facts about the situation, and the ResponseTable answers with the response that matches it best.
+Responses are written in `.responses` files:
+
+# See Player                      <- the concept the responses below answer
+Guard Warns:                      <- a response
+    self_alerted && allies > 1    <- criteria, all of them must hold
+    > Over there, get them!       <- a line that is said, in order when there are more
+    => alarms += 1                <- an effect applied when the response is said
+    cooldown 5                    <- seconds before the response can be said again
+    once                          <- the response is only said once
+Guard Mutters:
+    > Must have been the wind.
+
+The response with the most criteria wins, each side of `&&` is a criterion so `a && b` counts twice
+while `a || b` counts once, and a random one of the best when they tie. Every response that is said
+sets a fact in the FactsOfTheWorld named after its concept and name, `said_see_player_guard_warns`
+for the response above, which `once` checks and which other criteria can check as well.
+ */
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Response {
+    pub name: String,
+    pub concept: String,
+    pub criteria: Vec<Condition>,
+    pub lines: Vec<String>,
+    #[serde(default)]
+    pub effects: Vec<Effect>,
+    // Seconds before the response can be said again
+    #[serde(default)]
+    pub cooldown: f32,
+    #[serde(default)]
+    pub once: bool,
+    // When the response was said last, in seconds since the game started
+    #[serde(default)]
+    pub last_said: Option<f64>,
+}
+
+impl Response {
+    pub fn new(name: String, concept: String) -> Self {
+        Response {
+            name,
+            concept,
+            criteria: Vec::new(),
+            lines: Vec::new(),
+            effects: Vec::new(),
+            cooldown: 0.,
+            once: false,
+            last_said: None,
+        }
+    }
+
+    // The fact that remembers the response was said, `Guard Warns` to `See Player` becomes
+    // `said_see_player_guard_warns`. The concept keeps responses with the same name apart.
+    pub fn memory_fact(&self) -> String {
+        let snake_case = |text: &str| -> String {
+            text.chars()
+                .map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+                .collect()
+        };
+        format!("said_{}_{}", snake_case(&self.concept), snake_case(&self.name))
+    }
+
+    // How specific the response is, the table says the one with the highest score
+    pub fn score(&self) -> usize {
+        self.criteria.iter().map(Condition::conjunct_count).sum()
+    }
+
+    pub fn is_available(&self, facts: &dyn FactSource, now: f64) -> bool {
+        if self.last_said.is_some_and(|last_said| now < last_said + self.cooldown as f64) {
+            return false;
+        }
+        if self.once && matches!(facts.fact(&self.memory_fact()), Some(Fact::Bool(_, true))) {
+            return false;
+        }
+        self.criteria.iter().all(|criterion| criterion.evaluate(facts))
+    }
+}
+
+// A concept to respond to, with facts about the situation that come before the FactsOfTheWorld
+#[derive(Debug, Clone, PartialEq)]
+pub struct DialogueQuery {
+    pub concept: String,
+    pub context: HashMap<String, Fact>,
+}
+
+impl DialogueQuery {
+    pub fn new(concept: impl Into<String>) -> Self {
+        DialogueQuery {
+            concept: concept.into(),
+            context: HashMap::new(),
+        }
+    }
+
+    pub fn with_fact(mut self, fact: Fact) -> Self {
+        self.context.insert(fact.name().to_string(), fact);
+        self
+    }
+}
+
+// The context of a query on top of the facts of the world
+struct QueryFacts<'a> {
+    context: &'a HashMap<String, Fact>,
+    world: &'a dyn FactSource,
+}
+
+impl FactSource for QueryFacts<'_> {
+    fn fact(&self, name: &str) -> Option<&Fact> {
+        self.context.get(name).or_else(|| self.world.fact(name))
+    }
+}
+
+#[derive(Resource, Debug, Clone, Default, PartialEq)]
+pub struct ResponseTable {
+    pub responses: Vec<Response>,
+    // Ties are broken at random, the same seed breaks them the same way
+    pub seed: u64,
+    // The number of queries answered so far
+    pub answers: u64,
+}
+
+impl ResponseTable {
+    pub fn new(seed: u64) -> Self {
+        ResponseTable {
+            responses: Vec::new(),
+            seed,
+            answers: 0,
+        }
+    }
+
+    pub fn add_response(&mut self, response: Response) {
+        self.responses.push(response);
+    }
+
+    pub fn add_responses(&mut self, responses: impl IntoIterator<Item = Response>) {
+        self.responses.extend(responses);
+    }
+
+    pub fn response(&self, response: usize) -> Option<&Response> {
+        self.responses.get(response)
+    }
+
+    // The responses to the concept whose criteria hold, with their scores, best first
+    pub fn matching(&self, query: &DialogueQuery, facts: &dyn FactSource, now: f64) -> Vec<(usize, usize)> {
+        let facts = QueryFacts {
+            context: &query.context,
+            world: facts,
+        };
+        let mut matching: Vec<(usize, usize)> = self
+            .responses
+            .iter()
+            .enumerate()
+            .filter(|(_, response)| response.concept == query.concept && response.is_available(&facts, now))
+            .map(|(index, response)| (index, response.score()))
+            .collect();
+        matching.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+        matching
+    }
+
+    // Chooses the best response without saying it, one of the best at random when they tie
+    pub fn choose(&self, query: &DialogueQuery, facts: &dyn FactSource, now: f64) -> Option<usize> {
+        let matching = self.matching(query, facts, now);
+        let best_score = matching.first()?.1;
+        let best: Vec<usize> = matching
+            .into_iter()
+            .take_while(|&(_, score)| score == best_score)
+            .map(|(index, _)| index)
+            .collect();
+        let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(self.answers));
+        Some(best[rng.gen_range(0..best.len())])
+    }
+
+    // Says the response, starting its cooldown, remembering it and applying its effects
+    pub fn say(&mut self, response: usize, facts: &mut FactsOfTheWorld, now: f64) -> Vec<FactError> {
+        let Some(said) = self.responses.get_mut(response) else {
+            return Vec::new();
+        };
+        said.last_said = Some(now);
+        self.answers += 1;
+        let mut fact_errors: Vec<FactError> = facts.try_store_bool(said.memory_fact(), true).err().into_iter().collect();
+        fact_errors.extend(said.effects.iter().filter_map(|effect| effect.apply(facts).err()));
+        fact_errors
+    }
+
+    // Chooses the best response to the query and says it
+    pub fn answer(&mut self, query: &DialogueQuery, facts: &mut FactsOfTheWorld, now: f64) -> Option<(usize, Vec<FactError>)> {
+        let response = self.choose(query, &facts.facts, now)?;
+        Some((response, self.say(response, facts, now)))
+    }
+}
+
+#[derive(Clone)]
+enum ResponseLine<'a> {
+    Blank,
+    Concept(&'a str),
+    Response(&'a str),
+    Text(&'a str),
+    Cooldown(f32),
+    Once,
+    Criterion(Condition),
+    Effect(Effect),
+}
+
+// Parses the contents of a `.responses` file into its responses
+pub fn parse_responses(input: &str) -> Result<Vec<Response>, StoryParseError> {
+    let mut responses: Vec<Response> = Vec::new();
+    let mut concept: Option<&str> = None;
+
+    for (index, raw_line) in input.lines().enumerate() {
+        let line_number = index + 1;
+        let line = raw_line.trim_end();
+        let response_line = parse_line(line_number, line, response_line)?;
+        let response = match response_line {
+            ResponseLine::Blank => continue,
+            ResponseLine::Concept(name) => {
+                concept = Some(name);
+                continue;
+            }
+            ResponseLine::Response(name) => {
+                let Some(concept) = concept else {
+                    return Err(StoryParseError::new(line_number, 1, "a response answers a concept, add a `# Concept` heading first"));
+                };
+                responses.push(Response::new(name.to_string(), concept.to_string()));
+                continue;
+            }
+            _ => match responses.last_mut() {
+                Some(response) => response,
+                None => return Err(StoryParseError::new(line_number, 1, "indented line outside of a response")),
+            },
+        };
+        match response_line {
+            ResponseLine::Text(text) => response.lines.push(text.to_string()),
+            ResponseLine::Cooldown(cooldown) => response.cooldown = cooldown,
+            ResponseLine::Once => response.once = true,
+            ResponseLine::Criterion(condition) => response.criteria.push(condition),
+            ResponseLine::Effect(effect) => response.effects.push(effect),
+            _ => {}
+        }
+    }
+    Ok(responses)
+}
+
+fn response_line(input: &str) -> ParseResult<'_, ResponseLine<'_>> {
+    alt((
+        value(ResponseLine::Blank, all_consuming(space0)),
+        value(ResponseLine::Blank, preceded(tuple((space0, tag("//"))), rest_of_line)),
+        map(heading("#"), ResponseLine::Concept),
+        map(preceded(tuple((space1, tag(">"), space0)), rest_of_line), ResponseLine::Text),
+        map(
+            all_consuming(delimited(
+                tuple((space1, tag("cooldown"), space1)),
+                alt((float_literal, map(nom::character::complete::u32, |seconds| seconds as f32))),
+                space0,
+            )),
+            ResponseLine::Cooldown,
+        ),
+        value(ResponseLine::Once, all_consuming(tuple((space1, tag("once"), space0)))),
+        map(condition_or_effect_line, |line| match line {
+            ConditionOrEffect::Condition(condition) => ResponseLine::Criterion(condition),
+            ConditionOrEffect::Effect(effect) => ResponseLine::Effect(effect),
+        }),
+        map(block_heading, ResponseLine::Response),
+    ))(input)
+}
+
+// Asks the ResponseTable for the best response to the query, the speaker is sent back with it
+#[derive(Event, Debug, Clone)]
+pub struct DialogueRequest {
+    pub speaker: Option<Entity>,
+    pub query: DialogueQuery,
+}
+
+// The index of the response in the ResponseTable
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DialogueResponse {
+    pub speaker: Option<Entity>,
+    pub response: usize,
+}
+
+pub fn load_responses_from_assets(
+    mut response_table: ResMut<ResponseTable>,
+    story_assets: Res<StoryAssets>,
+    responses: Res<Assets<ResponseAsset>>,
+) {
+    for response_asset in story_assets.responses.iter().filter_map(|handle| responses.get(handle)) {
+        response_table.add_responses(response_asset.responses.iter().cloned());
+    }
+}
+
+pub fn dialogue_responder(
+    mut requests: EventReader<DialogueRequest>,
+    mut response_table: ResMut<ResponseTable>,
+    mut cool_fact_store: ResMut<FactsOfTheWorld>,
+    time: Res<Time>,
+    mut response_writer: EventWriter<DialogueResponse>,
+    mut fact_error_writer: EventWriter<FactErrorOccurred>,
+) {
+    for request in requests.read() {
+        let now = time.elapsed_seconds_f64();
+        let Some((response, fact_errors)) = response_table.answer(&request.query, &mut cool_fact_store, now) else {
+            info!("Nothing to say to '{}'", request.query.concept);
+            continue;
+        };
+        let name = &response_table.responses[response].name;
+        for error in fact_errors {
+            warn!("Response '{}': {}", name, error);
+            fact_error_writer.send(FactErrorOccurred {
+                source: FactErrorSource::Response(name.clone()),
+                error,
+            });
+        }
+        response_writer.send(DialogueResponse {
+            speaker: request.speaker,
+            response,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beats::expression::parse_condition;
+
+    fn response(name: &str, criteria: &[&str]) -> Response {
+        let mut response = Response::new(name.to_string(), "See Player".to_string());
+        response.criteria = criteria.iter().map(|criterion| parse_condition(criterion).unwrap()).collect();
+        response
+    }
+
+    #[test]
+    fn answers_with_the_guard_barks() {
+        let mut table = ResponseTable::new(0);
+        table.add_responses(parse_responses(include_str!("../../assets/guard_barks.responses")).unwrap());
+        let mut facts = FactsOfTheWorld::new();
+        let greet = DialogueQuery::new("Greet");
+        let first = table.answer(&greet, &mut facts, 0.0).map(|(response, _)| table.responses[response].name.clone());
+        assert_eq!(first.as_deref(), Some("First Greeting"));
+        let second = table.answer(&greet, &mut facts, 1.0).map(|(response, _)| table.responses[response].name.clone());
+        assert_eq!(second.as_deref(), Some("Greeting"));
+
+        let query = DialogueQuery::new("See Player")
+            .with_fact(Fact::Bool("player_visible".to_string(), true))
+            .with_fact(Fact::Int("guards_nearby".to_string(), 2));
+        let (response, errors) = table.answer(&query, &mut facts, 2.0).unwrap();
+        assert_eq!(table.responses[response].name, "Guard Warns");
+        assert!(errors.is_empty());
+        assert_eq!(facts.get_int("alarms"), Some(&1));
+    }
+
+    #[test]
+    fn remembers_responses_of_different_concepts_apart() {
+        let mut table = ResponseTable::new(0);
+        table.add_response(Response::new("Hello".to_string(), "Greet".to_string()));
+        let mut farewell = Response::new("Hello".to_string(), "Leave".to_string());
+        farewell.once = true;
+        table.add_response(farewell);
+        assert_eq!(table.responses[0].memory_fact(), "said_greet_hello");
+        assert_eq!(table.responses[1].memory_fact(), "said_leave_hello");
+
+        let mut facts = FactsOfTheWorld::new();
+        let greeted = table.answer(&DialogueQuery::new("Greet"), &mut facts, 0.0);
+        assert_eq!(greeted.map(|(response, _)| response), Some(0));
+        let left = table.answer(&DialogueQuery::new("Leave"), &mut facts, 0.0);
+        assert_eq!(left.map(|(response, _)| response), Some(1));
+    }
+
+    fn answers(table: &mut ResponseTable, facts: &mut FactsOfTheWorld, times: &[f64]) -> Vec<usize> {
+        let query = DialogueQuery::new("See Player").with_fact(Fact::Bool("spotted".to_string(), true));
+        times
+            .iter()
+            .filter_map(|&now| table.answer(&query, facts, now).map(|(response, _)| response))
+            .collect()
+    }
+
+    #[test]
+    fn waits_for_the_cooldown() {
+        let mut table = ResponseTable::new(0);
+        let mut warns = response("Warns", &["spotted"]);
+        warns.cooldown = 5.;
+        table.add_response(warns);
+        table.add_response(response("Mutters", &[]));
+        let mut facts = FactsOfTheWorld::new();
+        assert_eq!(answers(&mut table, &mut facts, &[0.0, 2.0, 4.9, 5.0]), vec![0, 1, 1, 0]);
+    }
+
+    #[test]
+    fn says_once_responses_once() {
+        let mut table = ResponseTable::new(0);
+        let mut warns = response("Warns", &["spotted"]);
+        warns.once = true;
+        table.add_response(warns);
+        table.add_response(response("Mutters", &[]));
+        let mut facts = FactsOfTheWorld::new();
+        assert_eq!(answers(&mut table, &mut facts, &[0.0, 100.0, 200.0]), vec![0, 1, 1]);
+        assert_eq!(facts.get_bool("said_see_player_warns"), Some(&true));
+    }
+
+    #[test]
+    fn breaks_ties_the_same_way_with_the_same_seed() {
+        let table = |seed| {
+            let mut table = ResponseTable::new(seed);
+            for name in ["Warns", "Shouts", "Points"] {
+                table.add_response(response(name, &["spotted"]));
+            }
+            table
+        };
+        let times: Vec<f64> = (0..12).map(f64::from).collect();
+        let picks = answers(&mut table(7), &mut FactsOfTheWorld::new(), &times);
+        assert!(picks.iter().any(|&response| response != picks[0]));
+        assert_eq!(answers(&mut table(7), &mut FactsOfTheWorld::new(), &times), picks);
+    }
+}
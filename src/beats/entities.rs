@@ -0,0 +1,514 @@
+use crate::beats::data::{depended_facts_hash, Fact, FactError, FactErrorOccurred, FactErrorSource, FactRemoved, FactScope, FactSource, FactUpdated, FactsOfTheWorld, Story, StoryEvaluationError, StoryEvaluationSettings, StoryStep};
+use bevy::ecs::entity::Entity;
+use bevy::ecs::system::Local;
+use bevy::log::{error, warn};
+use bevy::prelude::{Added, Bundle, Changed, Commands, Component, Deref, DerefMut, DetectChanges, DetectChangesMut, Event, EventReader, EventWriter, Name, Query, Res, ResMut, With};
+use bevy::utils::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+
+/*
+Stories can also live on entities instead of in the StoryEngine, so game code can attach its own
+components to them (a quest giver, a reward table), query them and despawn them:
+
+    commands.spawn((StoryBundle::new(story), QuestGiver(npc)));
+
+The story_entity_evaluator system does for these entities what story_evaluator does for the
+StoryEngine. StoryProgress only changes when the story does, and an ActiveBeat component is on
+every entity whose story is running, so both work with Bevy change detection.
+
+Entities keep their own state in a Facts component. A story entity with a StorySubject reads and
+writes the Facts of its subject as `self.alerted`, and any story entity reaches the Facts of an
+entity with a Name as `guard_3.alerted`, so one quest can be spawned once for every guard. Stories
+of the StoryEngine can't, the validation reports them:
+
+    commands.spawn((StoryBundle::new(guard_quest.clone()), StorySubject(guard)));
+ */
+
+// Facts of a single entity, with the same API as the FactsOfTheWorld
+#[derive(Component, Debug, Clone, Deref, DerefMut, Serialize, Deserialize)]
+pub struct Facts(pub FactsOfTheWorld);
+
+impl Facts {
+    pub fn new() -> Self {
+        Facts(FactsOfTheWorld::new())
+    }
+}
+
+impl Default for Facts {
+    fn default() -> Self {
+        Facts::new()
+    }
+}
+
+// The entity a story entity is about, the `self.` facts of the story are in its Facts
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorySubject(pub Entity);
+
+// The story as it was written, it is not changed while the story is played
+#[derive(Component, Debug, Clone)]
+pub struct StoryDefinition {
+    story: Story,
+    fact_names: HashSet<String>,
+}
+
+impl StoryDefinition {
+    pub fn new(story: Story) -> Self {
+        let fact_names = story.fact_names().into_iter().map(str::to_string).collect();
+        StoryDefinition { story, fact_names }
+    }
+
+    pub fn story(&self) -> &Story {
+        &self.story
+    }
+
+    pub fn depends_on(&self, fact_name: &str) -> bool {
+        self.fact_names.contains(fact_name)
+    }
+
+    // Whether the story looks at facts of its subject, or of the entity with the name when given
+    pub fn depends_on_entity(&self, entity_name: Option<&str>) -> bool {
+        self.fact_names.iter().any(|fact_name| match FactScope::of(fact_name).0 {
+            FactScope::Subject => entity_name.is_none(),
+            FactScope::Entity(name) => entity_name == Some(name),
+            FactScope::Global => false,
+        })
+    }
+}
+
+// How far the story of the entity got, this is what a save needs to store
+#[derive(Component, Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct StoryProgress {
+    pub is_started: bool,
+    pub is_failed: bool,
+    pub active_beats: Vec<String>,
+    pub finished_beats: Vec<String>,
+    // Completed objectives as (beat, objective)
+    pub completed_objectives: Vec<(String, String)>,
+    // Failures whose rules still hold since they triggered as (beat, failure), the beat is None for
+    // failures of the story
+    #[serde(default)]
+    pub triggered_failures: Vec<(Option<String>, String)>,
+}
+
+impl StoryProgress {
+    pub fn of(story: &Story) -> Self {
+        StoryProgress {
+            is_started: story.is_started,
+            is_failed: story.is_failed,
+            active_beats: story.active_beats.clone(),
+            finished_beats: story
+                .beats
+                .iter()
+                .filter(|beat| beat.finished)
+                .map(|beat| beat.name.clone())
+                .collect(),
+            completed_objectives: story
+                .beats
+                .iter()
+                .flat_map(|beat| {
+                    beat.objectives
+                        .iter()
+                        .filter(|objective| objective.completed)
+                        .map(|objective| (beat.name.clone(), objective.name.clone()))
+                })
+                .collect(),
+            triggered_failures: story
+                .failures
+                .iter()
+                .filter(|failure| failure.triggered)
+                .map(|failure| (None, failure.name.clone()))
+                .chain(story.beats.iter().flat_map(|beat| {
+                    beat.failures
+                        .iter()
+                        .filter(|failure| failure.triggered)
+                        .map(|failure| (Some(beat.name.clone()), failure.name.clone()))
+                }))
+                .collect(),
+        }
+    }
+
+    // The story of the definition as far as this progress got
+    pub fn apply_to(&self, definition: &Story) -> Story {
+        let mut story = definition.clone();
+        story.is_started = self.is_started;
+        story.is_failed = self.is_failed;
+        story.active_beats = self.active_beats.clone();
+        for failure in story.failures.iter_mut() {
+            failure.triggered = self.triggered_failures.contains(&(None, failure.name.clone()));
+        }
+        for beat in story.beats.iter_mut() {
+            beat.finished = self.finished_beats.contains(&beat.name);
+            for objective in beat.objectives.iter_mut() {
+                objective.completed = self
+                    .completed_objectives
+                    .iter()
+                    .any(|(beat_name, objective_name)| beat_name == &beat.name && objective_name == &objective.name);
+            }
+            for failure in beat.failures.iter_mut() {
+                failure.triggered = self.triggered_failures.iter().any(|(beat_name, failure_name)| {
+                    beat_name.as_deref() == Some(beat.name.as_str()) && failure_name == &failure.name
+                });
+            }
+        }
+        story
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.is_started && !self.is_failed && self.active_beats.is_empty()
+    }
+}
+
+// The beats the story of the entity is at, only present while the story is running
+#[derive(Component, Debug, Clone, PartialEq, Eq)]
+pub struct ActiveBeat {
+    pub beats: Vec<String>,
+}
+
+#[derive(Bundle)]
+pub struct StoryBundle {
+    pub definition: StoryDefinition,
+    pub progress: StoryProgress,
+}
+
+impl StoryBundle {
+    // Keeps the progress the story already has
+    pub fn new(story: Story) -> Self {
+        StoryBundle {
+            progress: StoryProgress::of(&story),
+            definition: StoryDefinition::new(story),
+        }
+    }
+}
+
+// Everything that happens to a story entity, beats, objectives and failures are indices into
+// the beats of its StoryDefinition
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoryEntityStep {
+    pub entity: Entity,
+    pub step: StoryStep,
+}
+
+// Looks scoped fact names up in the Facts of the subject or of the entity with that Name
+struct ScopedFacts<'a, 'w, 's, 'f> {
+    global: &'a FactsOfTheWorld,
+    subject: Option<Entity>,
+    named_entities: &'a HashMap<String, Entity>,
+    entity_facts: &'a Query<'w, 's, (Entity, &'f mut Facts)>,
+}
+
+impl FactSource for ScopedFacts<'_, '_, '_, '_> {
+    fn fact(&self, name: &str) -> Option<&Fact> {
+        let (scope, fact_name) = FactScope::of(name);
+        let entity = match scope {
+            FactScope::Global => return self.global.facts.get(name),
+            FactScope::Subject => self.subject?,
+            FactScope::Entity(entity_name) => *self.named_entities.get(entity_name)?,
+        };
+        self.entity_facts.get(entity).ok()?.1.facts.get(fact_name)
+    }
+}
+
+// Evaluates the story entities and applies their effects until nothing changes anymore, like
+// StoryEngine::evaluate_until_settled does for the stories of the engine. The story entities it
+// stopped at stall until a fact they depend on gets another value.
+pub fn story_entity_evaluator(
+    mut fact_updated: EventReader<FactUpdated>,
+    mut fact_removed: EventReader<FactRemoved>,
+    added_stories: Query<Entity, Added<StoryDefinition>>,
+    mut stories: Query<(Entity, &StoryDefinition, &mut StoryProgress, Option<&StorySubject>)>,
+    mut entity_facts: Query<(Entity, &mut Facts)>,
+    names: Query<(Entity, &Name), With<Facts>>,
+    mut cool_fact_store: ResMut<FactsOfTheWorld>,
+    settings: Res<StoryEvaluationSettings>,
+    mut pending: Local<HashSet<Entity>>,
+    mut stalled: Local<HashMap<Entity, u64>>,
+    mut step_writer: EventWriter<StoryEntityStep>,
+    mut fact_error_writer: EventWriter<FactErrorOccurred>,
+) {
+    let mut changed_facts: HashSet<String> = fact_updated
+        .read()
+        .map(|event| event.fact.name().to_string())
+        .chain(fact_removed.read().map(|event| event.name.clone()))
+        .collect();
+    let mut changed_entities: HashSet<Entity> = entity_facts
+        .iter_mut()
+        .filter(|(_, facts)| facts.is_changed())
+        .map(|(entity, _)| entity)
+        .collect();
+    pending.extend(added_stories.iter());
+
+    let named_entities: HashMap<String, Entity> = names.iter().map(|(entity, name)| (name.to_string(), entity)).collect();
+    let mut fact_errors = Vec::new();
+    let mut seen_states = HashSet::new();
+    let mut evaluation_error = None;
+    for iteration in 0..=settings.max_iterations {
+        if changed_facts.is_empty() && changed_entities.is_empty() && pending.is_empty() {
+            break;
+        }
+        if iteration == settings.max_iterations {
+            evaluation_error = Some(StoryEvaluationError::IterationLimit {
+                max_iterations: settings.max_iterations,
+            });
+            break;
+        }
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        for (entity, _, progress, _) in stories.iter() {
+            (entity, &*progress).hash(&mut hasher);
+        }
+        for (entity, facts) in entity_facts.iter() {
+            entity.hash(&mut hasher);
+            facts.hash_facts(&mut hasher);
+        }
+        cool_fact_store.hash_facts(&mut hasher);
+        if !seen_states.insert(hasher.finish()) {
+            evaluation_error = Some(StoryEvaluationError::Cycle { iteration });
+            break;
+        }
+
+        let changed_names: Vec<&str> = named_entities
+            .iter()
+            .filter(|(_, entity)| changed_entities.contains(*entity))
+            .map(|(name, _)| name.as_str())
+            .collect();
+        let evaluated: HashSet<Entity> = std::mem::take(&mut *pending);
+        let mut next_changed_facts = HashSet::new();
+        let mut next_changed_entities = HashSet::new();
+        for (entity, definition, mut progress, subject) in stories.iter_mut() {
+            let subject = subject.map(|subject| subject.0);
+            let affected = evaluated.contains(&entity)
+                || changed_facts.iter().any(|name| definition.depends_on(name))
+                || subject.is_some_and(|subject| changed_entities.contains(&subject) && definition.depends_on_entity(None))
+                || changed_names.iter().any(|name| definition.depends_on_entity(Some(name)));
+            if !affected || (progress.is_started && progress.active_beats.is_empty()) {
+                continue;
+            }
+
+            let scoped_facts = ScopedFacts {
+                global: &cool_fact_store,
+                subject,
+                named_entities: &named_entities,
+                entity_facts: &entity_facts,
+            };
+            if let Some(&stalled_facts) = stalled.get(&entity) {
+                if stalled_facts == depended_facts_hash(definition.story(), &scoped_facts) {
+                    continue;
+                }
+                stalled.remove(&entity);
+            }
+
+            let mut story = progress.apply_to(definition.story());
+            let steps = story.evaluate(&scoped_facts);
+            if steps.iter().any(StoryStep::advances) {
+                pending.insert(entity);
+            }
+            for step in steps {
+                let Some((beat, effects)) = story.step_effects(step) else {
+                    step_writer.send(StoryEntityStep { entity, step });
+                    continue;
+                };
+                for effect in effects {
+                    let (scope, fact_name) = FactScope::of(effect.fact_name());
+                    let target = match scope {
+                        FactScope::Global => None,
+                        FactScope::Subject => Some(subject),
+                        FactScope::Entity(entity_name) => Some(named_entities.get(entity_name).copied()),
+                    };
+                    let result = match target {
+                        None => effect.apply(&mut cool_fact_store).map(|_| {
+                            next_changed_facts.insert(fact_name.to_string());
+                        }),
+                        Some(Some(target)) => match entity_facts.get_mut(target) {
+                            Ok((_, mut facts)) => effect.apply_as(fact_name, &mut facts).map(|_| {
+                                next_changed_entities.insert(target);
+                            }),
+                            Err(_) => Err(FactError::MissingKey(effect.fact_name().to_string())),
+                        },
+                        Some(None) => Err(FactError::MissingKey(effect.fact_name().to_string())),
+                    };
+                    if let Err(error) = result {
+                        fact_errors.push(FactErrorOccurred {
+                            source: FactErrorSource::Beat {
+                                story: story.name.clone(),
+                                beat: beat.to_string(),
+                            },
+                            error,
+                        });
+                    }
+                }
+                step_writer.send(StoryEntityStep { entity, step });
+            }
+            progress.set_if_neq(StoryProgress::of(&story));
+        }
+        changed_facts = next_changed_facts;
+        changed_entities = next_changed_entities;
+    }
+
+    // Stalls the story entities the next iteration would have evaluated, the changes of entity
+    // Facts made here aren't seen as changes on the next frame
+    if evaluation_error.is_some() {
+        for (entity, definition, _, subject) in stories.iter() {
+            if !pending.remove(&entity) && !changed_facts.iter().any(|name| definition.depends_on(name)) {
+                continue;
+            }
+            let scoped_facts = ScopedFacts {
+                global: &cool_fact_store,
+                subject: subject.map(|subject| subject.0),
+                named_entities: &named_entities,
+                entity_facts: &entity_facts,
+            };
+            stalled.insert(entity, depended_facts_hash(definition.story(), &scoped_facts));
+        }
+        pending.clear();
+    }
+
+    for fact_error in fact_errors {
+        warn!("Effect of {} failed: {}", fact_error.source, fact_error.error);
+        fact_error_writer.send(fact_error);
+    }
+    if let Some(error) = evaluation_error {
+        error!("Story entity evaluation stopped: {}", error);
+    }
+}
+
+// Keeps ActiveBeat in line with the progress of the stories that changed
+pub fn active_beat_updater(
+    mut commands: Commands,
+    stories: Query<(Entity, &StoryProgress, Option<&ActiveBeat>), Changed<StoryProgress>>,
+) {
+    for (entity, progress, active_beat) in stories.iter() {
+        match active_beat {
+            Some(_) if progress.active_beats.is_empty() => {
+                commands.entity(entity).remove::<ActiveBeat>();
+            }
+            Some(active_beat) if active_beat.beats == progress.active_beats => {}
+            _ if progress.active_beats.is_empty() => {}
+            _ => {
+                commands.entity(entity).insert(ActiveBeat {
+                    beats: progress.active_beats.clone(),
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beats::parser::parse_stories;
+    use crate::beats::systems::fact_update_event_broadcaster;
+    use bevy::prelude::{App, Events, IntoSystemConfigs, Update};
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_event::<FactUpdated>()
+            .add_event::<FactRemoved>()
+            .add_event::<StoryEntityStep>()
+            .add_event::<FactErrorOccurred>()
+            .insert_resource(FactsOfTheWorld::new())
+            .init_resource::<StoryEvaluationSettings>()
+            .add_systems(Update, (fact_update_event_broadcaster, story_entity_evaluator, active_beat_updater).chain());
+        app
+    }
+
+    fn story(source: &str) -> Story {
+        parse_stories(source).unwrap().remove(0)
+    }
+
+    fn steps(app: &mut App) -> Vec<StoryStep> {
+        app.world.resource_mut::<Events<StoryEntityStep>>().drain().map(|event| event.step).collect()
+    }
+
+    #[test]
+    fn plays_stories_on_the_facts_of_their_subject_and_of_named_entities() {
+        let mut app = app();
+        let mut guard_facts = Facts::new();
+        guard_facts.store_bool("alerted".to_string(), false);
+        let guard = app.world.spawn((guard_facts, Name::new("guard_3"))).id();
+        let patrol = story(
+            "# Patrol\n## Watch\nAlerted:\n    self.alerted\nEffects:\n    self.alarms = 1\n    raised = true\n\
+             ## Chase\nCaught:\n    guard_3.caught && raised\n",
+        );
+        let quest = app.world.spawn((StoryBundle::new(patrol), StorySubject(guard))).id();
+
+        app.update();
+        assert_eq!(app.world.get::<ActiveBeat>(quest).unwrap().beats, vec!["Watch".to_string()]);
+
+        app.world.get_mut::<Facts>(guard).unwrap().store_bool("alerted".to_string(), true);
+        app.update();
+        assert_eq!(app.world.get::<ActiveBeat>(quest).unwrap().beats, vec!["Chase".to_string()]);
+        assert_eq!(app.world.get::<Facts>(guard).unwrap().get_int("alarms"), Some(&1));
+        assert_eq!(app.world.resource::<FactsOfTheWorld>().get_bool("raised"), Some(&true));
+
+        app.world.get_mut::<Facts>(guard).unwrap().store_bool("caught".to_string(), true);
+        app.update();
+        assert!(app.world.get::<StoryProgress>(quest).unwrap().is_finished());
+        assert!(app.world.get::<ActiveBeat>(quest).is_none());
+    }
+
+    #[test]
+    fn triggers_failures_of_story_entities_once_while_they_hold() {
+        let mut app = app();
+        app.world.resource_mut::<FactsOfTheWorld>().store_int("gold".to_string(), 100);
+        let mut caravan_facts = Facts::new();
+        caravan_facts.store_int("bandits".to_string(), 1);
+        let caravan = app.world.spawn(caravan_facts).id();
+        let escort = story(
+            "# Escort\n! Ambush -> Hide:\n    self.bandits > 0\n    => gold -= 10\n\
+             ## Travel\nArrived:\n    self.arrived\n## Hide\nSafe:\n    self.arrived\n",
+        );
+        let quest = app.world.spawn((StoryBundle::new(escort), StorySubject(caravan))).id();
+
+        for _ in 0..3 {
+            app.update();
+        }
+        assert_eq!(app.world.resource::<FactsOfTheWorld>().get_int("gold"), Some(&90));
+        let progress = app.world.get::<StoryProgress>(quest).unwrap();
+        assert_eq!(progress.active_beats, vec!["Hide".to_string()]);
+        assert_eq!(progress.triggered_failures, vec![(None, "Ambush".to_string())]);
+    }
+
+    #[test]
+    fn stalls_story_entities_that_cycle() {
+        let mut app = app();
+        app.world.resource_mut::<FactsOfTheWorld>().store_bool("lit".to_string(), false);
+        let mut blink = story(
+            "# Blink\n## On\nDark:\n    !lit\nEffects:\n    toggle lit\nNext -> Off:\n\
+             ## Off\nBright:\n    lit\nEffects:\n    toggle lit\nNext -> On:\n",
+        );
+        blink.is_started = true;
+        blink.active_beats = vec!["On".to_string()];
+        for beat in blink.beats.iter_mut() {
+            beat.finished = true;
+        }
+        app.world.spawn(StoryBundle::new(blink));
+
+        app.update();
+        assert!(!steps(&mut app).is_empty());
+        // The updates of `lit` are broadcast on the next frame, they don't start the cycle over
+        app.update();
+        assert!(steps(&mut app).is_empty());
+    }
+
+    #[test]
+    fn keeps_the_progress_of_a_story() {
+        let source = "# Heist\n! Caught:\n    caught\n## Plan\n- Map:\n    has_map\n- Crew:\n    crew > 2\n\
+                      ! Leak -> Plan:\n    leaked\n## Vault\n";
+        let definition = story(source);
+        let mut story = definition.clone();
+        story.is_started = true;
+        story.active_beats = vec!["Vault".to_string()];
+        story.failures[0].triggered = true;
+        story.beats[0].finished = true;
+        story.beats[0].objectives[1].completed = true;
+        story.beats[0].failures[0].triggered = true;
+
+        let progress = StoryProgress::of(&story);
+        assert_eq!(progress.completed_objectives, vec![("Plan".to_string(), "Crew".to_string())]);
+        assert_eq!(
+            progress.triggered_failures,
+            vec![(None, "Caught".to_string()), (Some("Plan".to_string()), "Leak".to_string())]
+        );
+        assert_eq!(progress.apply_to(&definition), story);
+    }
+}
@@ -0,0 +1,358 @@
+use crate::beats::data::{Condition, FLOAT_EPSILON};
+use crate::beats::parser::{error_position, failure, float_literal, identifier, literal, string_literal, Literal, ParseResult};
+use nom::branch::alt;
+use nom::bytes::complete::tag;
+use nom::character::complete::{char, space0, space1};
+use nom::combinator::{all_consuming, cut, opt, value};
+use nom::error::{context, VerboseError};
+use nom::multi::separated_list1;
+use nom::sequence::{delimited, preceded, terminated, tuple};
+use std::fmt::{Display, Formatter};
+
+/*
+A small expression language for conditions, so writers don't have to spell out the Condition enum:
+
+    button_pressed > 3 && !quest_one_complete && "sword" in inventory
+
+Comparisons are `fact == value` and `fact != value` for numbers, "strings" and true / false, and
+`>`, `<`, `>=` and `<=` for numbers. Floats are written with a decimal point, `==` on them allows a
+difference of FLOAT_EPSILON unless another one is given as in `reputation == 0.5 within 0.05`.
+An integer compares as an integer, so `reputation > 0` never holds for a float fact, write
+`reputation > 0.0`. Validation reports such comparisons. `"value" in list` checks a string list and
+a bare `flag` checks that a boolean is true. `fact != value` is the negation of `fact == value`, so
+it also holds when the fact isn't set or has another type, `gold > 3 || gold < 3` only holds for a
+gold that is set. Conditions combine with `!`, `&&` and `||`, in that order of precedence, and can
+be grouped with parentheses. `at_least(2, a, b, c)` holds when two of the conditions hold.
+Facts are global unless scoped, `self.alerted` is a fact of the subject of a story entity and
+`guard_3.alerted` one of the entity with the Name guard_3.
+ */
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpressionError {
+    pub expression: String,
+    pub column: usize,
+    pub message: String,
+}
+
+impl Display for ExpressionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "column {}: {}", self.column, self.message)?;
+        writeln!(f, "    {}", self.expression)?;
+        write!(f, "    {}^", " ".repeat(self.column - 1))
+    }
+}
+
+impl std::error::Error for ExpressionError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Equals,
+    NotEquals,
+    MoreThan,
+    LessThan,
+    AtLeast,
+    AtMost,
+}
+
+// Parses a condition expression like `button_pressed > 3 && !quest_one_complete`
+pub fn parse_condition(input: &str) -> Result<Condition, ExpressionError> {
+    all_consuming(delimited(space0, expression, space0))(input)
+        .map(|(_, condition)| condition)
+        .map_err(|error| {
+            let (column, message) = error_position(input, error);
+            ExpressionError {
+                expression: input.to_string(),
+                column,
+                message,
+            }
+        })
+}
+
+pub(super) fn expression(input: &str) -> ParseResult<'_, Condition> {
+    chain("||", and_expression, Condition::Any)(input)
+}
+
+fn and_expression(input: &str) -> ParseResult<'_, Condition> {
+    chain("&&", unary_expression, Condition::All)(input)
+}
+
+// Parses operands separated by the operator, more than one operand are combined into a group
+fn chain<'a>(
+    operator: &'static str,
+    operand: fn(&'a str) -> ParseResult<'a, Condition>,
+    combine: fn(Vec<Condition>) -> Condition,
+) -> impl FnMut(&'a str) -> ParseResult<'a, Condition> {
+    move |input| {
+        let (mut rest, first) = operand(input)?;
+        let mut conditions = vec![first];
+        while let Ok((after_operator, _)) = delimited(space0, tag::<_, _, VerboseError<&str>>(operator), space0)(rest) {
+            let (after_operand, condition) = context("expected a condition after the operator", cut(operand))(after_operator)?;
+            conditions.push(condition);
+            rest = after_operand;
+        }
+        if conditions.len() == 1 {
+            Ok((rest, conditions.remove(0)))
+        } else {
+            Ok((rest, combine(conditions)))
+        }
+    }
+}
+
+fn unary_expression(input: &str) -> ParseResult<'_, Condition> {
+    if let Ok((rest, _)) = terminated(char::<_, VerboseError<&str>>('!'), space0)(input) {
+        let (rest, condition) = cut(unary_expression)(rest)?;
+        return Ok((rest, Condition::Not(Box::new(condition))));
+    }
+    primary(input)
+}
+
+fn primary(input: &str) -> ParseResult<'_, Condition> {
+    if let Ok((rest, _)) = terminated(char::<_, VerboseError<&str>>('('), space0)(input) {
+        return terminated(
+            cut(expression),
+            context("expected `)` to close the group", cut(preceded(space0, char(')')))),
+        )(rest);
+    }
+    if let Ok((rest, _)) = tuple((tag::<_, _, VerboseError<&str>>("at_least"), space0, char('(')))(input) {
+        return at_least(rest);
+    }
+    comparison(input).map_err(|error| match error {
+        nom::Err::Error(_) => failure(
+            input,
+            "expected a condition such as `fact > 3`, `\"value\" in list`, `flag` or `(...)`",
+        ),
+        error => error,
+    })
+}
+
+fn at_least(input: &str) -> ParseResult<'_, Condition> {
+    let (rest, count) = context(
+        "expected how many conditions must hold",
+        cut(delimited(space0, nom::character::complete::u32, space0)),
+    )(input)?;
+    let (rest, _) = context("expected `,` after the count", cut(terminated(char(','), space0)))(rest)?;
+    let (rest, conditions) = separated_list1(delimited(space0, char(','), space0), cut(expression))(rest)?;
+    let (rest, _) = context("expected `)` to close at_least", cut(preceded(space0, char(')'))))(rest)?;
+    Ok((rest, Condition::AtLeast(count as usize, conditions)))
+}
+
+fn operator(input: &str) -> ParseResult<'_, Operator> {
+    alt((
+        value(Operator::Equals, tag("==")),
+        value(Operator::NotEquals, tag("!=")),
+        value(Operator::AtLeast, tag(">=")),
+        value(Operator::AtMost, tag("<=")),
+        value(Operator::MoreThan, char('>')),
+        value(Operator::LessThan, char('<')),
+    ))(input)
+}
+
+fn comparison(input: &str) -> ParseResult<'_, Condition> {
+    if let Ok((rest, expected_value)) = string_literal(input) {
+        let (rest, fact_name) = preceded(
+            context("expected `in` after the value", cut(delimited(space1, tag("in"), space1))),
+            cut(identifier),
+        )(rest)?;
+        return Ok((
+            rest,
+            Condition::ListContains {
+                fact_name: fact_name.to_string(),
+                expected_value,
+            },
+        ));
+    }
+
+    let (rest, fact_name) = identifier(input)?;
+    let fact_name = fact_name.to_string();
+    let Ok((rest, operator)) = preceded(space0, operator)(rest) else {
+        return Ok((
+            rest,
+            Condition::BoolEquals {
+                fact_name,
+                expected_value: true,
+            },
+        ));
+    };
+    let (literal_start, _) = space0(rest)?;
+    let (rest, literal) = cut(literal)(literal_start)?;
+
+    if let Literal::Float(expected_value) = literal {
+        return float_comparison(rest, fact_name, operator, expected_value);
+    }
+
+    let condition = match (operator, literal) {
+        (Operator::Equals | Operator::NotEquals, Literal::Int(expected_value)) => Condition::IntEquals {
+            fact_name,
+            expected_value,
+        },
+        (Operator::Equals | Operator::NotEquals, Literal::String(expected_value)) => Condition::StringEquals {
+            fact_name,
+            expected_value,
+        },
+        (Operator::Equals | Operator::NotEquals, Literal::Bool(expected_value)) => Condition::BoolEquals {
+            fact_name,
+            expected_value,
+        },
+        (Operator::MoreThan, Literal::Int(expected_value)) => Condition::IntMoreThan {
+            fact_name,
+            expected_value,
+        },
+        (Operator::LessThan, Literal::Int(expected_value)) => Condition::IntLessThan {
+            fact_name,
+            expected_value,
+        },
+        (Operator::AtLeast, Literal::Int(value)) => Condition::IntMoreThan {
+            fact_name,
+            expected_value: value
+                .checked_sub(1)
+                .ok_or_else(|| failure(literal_start, "the value is too small for `>=`"))?,
+        },
+        (Operator::AtMost, Literal::Int(value)) => Condition::IntLessThan {
+            fact_name,
+            expected_value: value
+                .checked_add(1)
+                .ok_or_else(|| failure(literal_start, "the value is too large for `<=`"))?,
+        },
+        (Operator::Equals | Operator::NotEquals, Literal::List(_)) => {
+            return Err(failure(literal_start, "lists can not be compared, use `\"value\" in list`"));
+        }
+        _ => {
+            return Err(failure(literal_start, "`>`, `<`, `>=` and `<=` can only compare numbers"));
+        }
+    };
+
+    // Not only the other values, a missing fact and a fact of another type aren't equal either
+    if operator == Operator::NotEquals {
+        Ok((rest, Condition::Not(Box::new(condition))))
+    } else {
+        Ok((rest, condition))
+    }
+}
+
+// `fact == 0.5 within 0.01` compares with a custom epsilon instead of FLOAT_EPSILON
+fn float_comparison(input: &str, fact_name: String, operator: Operator, expected_value: f32) -> ParseResult<'_, Condition> {
+    let (rest, epsilon) = opt(preceded(
+        delimited(space1, tag("within"), space1),
+        context("expected a float after `within`", cut(float_literal)),
+    ))(input)?;
+    let equals = Condition::float_equals_within(fact_name.clone(), expected_value, epsilon.unwrap_or(FLOAT_EPSILON));
+
+    let condition = match operator {
+        Operator::Equals => equals,
+        Operator::NotEquals => Condition::Not(Box::new(equals)),
+        Operator::MoreThan => Condition::float_more_than(fact_name, expected_value),
+        Operator::LessThan => Condition::float_less_than(fact_name, expected_value),
+        Operator::AtLeast => Condition::Any(vec![Condition::float_more_than(fact_name, expected_value), equals]),
+        Operator::AtMost => Condition::Any(vec![Condition::float_less_than(fact_name, expected_value), equals]),
+    };
+    Ok((rest, condition))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beats::data::FactsOfTheWorld;
+
+    fn bool_fact(fact_name: &str) -> Condition {
+        Condition::BoolEquals {
+            fact_name: fact_name.to_string(),
+            expected_value: true,
+        }
+    }
+
+    fn int_more_than(fact_name: &str, expected_value: i32) -> Condition {
+        Condition::IntMoreThan {
+            fact_name: fact_name.to_string(),
+            expected_value,
+        }
+    }
+
+    #[test]
+    fn parses_the_example_expression() {
+        let condition = parse_condition("button_pressed > 3 && !quest_one_complete && \"sword\" in inventory").unwrap();
+        assert_eq!(
+            condition,
+            Condition::All(vec![
+                int_more_than("button_pressed", 3),
+                Condition::Not(Box::new(bool_fact("quest_one_complete"))),
+                Condition::ListContains {
+                    fact_name: "inventory".to_string(),
+                    expected_value: "sword".to_string(),
+                },
+            ])
+        );
+        let serialized = ron::to_string(&condition).unwrap();
+        assert_eq!(ron::from_str::<Condition>(&serialized).unwrap(), condition);
+    }
+
+    #[test]
+    fn binds_and_tighter_than_or() {
+        let condition = parse_condition("a || b && !(c || d)").unwrap();
+        assert_eq!(
+            condition,
+            Condition::Any(vec![
+                bool_fact("a"),
+                Condition::All(vec![
+                    bool_fact("b"),
+                    Condition::Not(Box::new(Condition::Any(vec![bool_fact("c"), bool_fact("d")]))),
+                ]),
+            ])
+        );
+    }
+
+    #[test]
+    fn turns_inclusive_int_comparisons_into_strict_ones() {
+        assert_eq!(parse_condition("gold >= 10").unwrap(), int_more_than("gold", 9));
+        assert_eq!(
+            parse_condition("gold <= 10").unwrap(),
+            Condition::IntLessThan {
+                fact_name: "gold".to_string(),
+                expected_value: 11,
+            }
+        );
+        assert!(parse_condition(&format!("gold >= {}", i32::MIN)).is_err());
+    }
+
+    #[test]
+    fn parses_floats_scopes_and_at_least() {
+        assert_eq!(
+            parse_condition("reputation == 0.5 within 0.05").unwrap(),
+            Condition::float_equals_within("reputation", 0.5, 0.05)
+        );
+        assert_eq!(parse_condition("self.alerted").unwrap(), bool_fact("self.alerted"));
+        assert_eq!(
+            parse_condition("at_least(2, a, b, c)").unwrap(),
+            Condition::AtLeast(2, vec![bool_fact("a"), bool_fact("b"), bool_fact("c")])
+        );
+    }
+
+    #[test]
+    fn evaluates_parsed_conditions() {
+        let mut facts = FactsOfTheWorld::new();
+        facts.store_int("gold".to_string(), 12);
+        facts.add_to_list("inventory".to_string(), "sword".to_string());
+
+        assert!(parse_condition("gold >= 12 && \"sword\" in inventory").unwrap().evaluate(&facts.facts));
+        assert!(!parse_condition("gold > 12 || \"torch\" in inventory").unwrap().evaluate(&facts.facts));
+        assert!(parse_condition("gold != 3").unwrap().evaluate(&facts.facts));
+        assert!(parse_condition("silver != 3").unwrap().evaluate(&facts.facts));
+        assert!(!parse_condition("silver > 3 || silver < 3").unwrap().evaluate(&facts.facts));
+    }
+
+    #[test]
+    fn reports_the_column_of_errors() {
+        let error = parse_condition("gold > ").unwrap_err();
+        assert_eq!(error.column, 8);
+
+        let error = parse_condition("(a && b").unwrap_err();
+        assert_eq!(error.column, 8);
+        assert_eq!(error.message, "expected `)` to close the group");
+
+        let error = parse_condition("a && ").unwrap_err();
+        assert_eq!(error.column, 6);
+
+        let error = parse_condition("name > \"smith\"").unwrap_err();
+        assert_eq!(error.column, 8);
+        assert_eq!(error.message, "`>`, `<`, `>=` and `<=` can only compare numbers");
+    }
+}
@@ -1,10 +1,25 @@
+use crate::beats::analysis::story_analyzer;
+use crate::beats::assets::{
+    ConversationAsset, ConversationLoader, ResponseAsset, ResponseLoader, RonStoryLoader, RonStoryletLoader, StoryAsset,
+    StoryLoader, StoryletAsset,
+};
+use crate::beats::conversations::{
+    beat_conversation_starter, conversation_runner, load_conversations_from_assets, ActiveConversation, ConversationEnded,
+    Conversations, DialogueChoiceMade, StartConversation,
+};
 use crate::beats::data::*;
+use crate::beats::dialogue::{dialogue_responder, load_responses_from_assets, DialogueRequest, DialogueResponse, ResponseTable};
+use crate::beats::entities::{active_beat_updater, story_entity_evaluator, StoryEntityStep};
+use crate::beats::storylets::{load_storylets_from_assets, storylet_picker, PickStorylet, StoryletPlayed, StoryletPool};
 use crate::beats::systems::*;
+use crate::beats::text::{story_text_validator, TextTemplates};
+use crate::beats::validation::{story_validator, FactSchema, FactType};
 use crate::GameState;
 use bevy::app::{App, Plugin, Update};
+use bevy::asset::AssetApp;
 use bevy::prelude::{in_state, Component, IntoSystemConfigs, OnEnter, Commands, not, any_with_component, Query, Entity, With, Res, Time, PositionType, Val, Color};
 use bevy_inspector_egui::quick::WorldInspectorPlugin;
-use crate::ui::fps_widget;
+use crate::ui::{dialogue_widget, fps_widget};
 use sickle_ui::{
     ui_builder::{UiBuilderExt, UiRoot},
     ui_commands::SetTextExt,
@@ -18,10 +33,21 @@ use sickle_ui::{
     },
 };
 use crate::ui::banner_widget::{BannerWidget, BannerWidgetCommands, BannerWidgetConfig, UiBannerWidgetExt};
+use crate::ui::dialogue_widget::{DialogueWidget, UiDialogueWidgetExt};
 use crate::ui::fps_widget::{FpsWidget, UiFPSWidgetExt};
 
+pub mod analysis;
+pub mod assets;
+pub mod conversations;
 pub mod data;
+pub mod dialogue;
+pub mod entities;
+pub mod expression;
+pub mod parser;
+pub mod storylets;
 pub mod systems;
+pub mod text;
+pub mod validation;
 mod builders;
 
 pub struct StoryPlugin;
@@ -31,24 +57,76 @@ impl Plugin for StoryPlugin {
         app.insert_resource(FactsOfTheWorld::new())
             .add_plugins(WorldInspectorPlugin::new())
             .add_plugins(fps_widget::plugin)
+            .add_plugins(dialogue_widget::plugin)
             .insert_resource(StoryEngine::new())
+            .init_resource::<StoryEvaluationSettings>()
+            // The facts the demo sets in code rather than through effects
+            .insert_resource(FactSchema::new().with_fact("button_pressed", FactType::Int))
+            .init_resource::<StoryletPool>()
+            .init_resource::<ResponseTable>()
+            .init_resource::<Conversations>()
+            .init_resource::<ActiveConversation>()
+            .init_resource::<TextTemplates>()
+            .init_asset::<StoryAsset>()
+            .init_asset_loader::<StoryLoader>()
+            .init_asset_loader::<RonStoryLoader>()
+            .init_asset::<ResponseAsset>()
+            .init_asset_loader::<ResponseLoader>()
+            .init_asset::<ConversationAsset>()
+            .init_asset_loader::<ConversationLoader>()
+            .init_asset::<StoryletAsset>()
+            .init_asset_loader::<RonStoryletLoader>()
             .add_event::<FactUpdated>()
-            .add_event::<RuleUpdated>()
-            .add_event::<StoryBeatFinished>()
+            .add_event::<FactRemoved>()
+            .add_event::<StoryStarted>()
+            .add_event::<BeatActivated>()
+            .add_event::<ObjectiveCompleted>()
+            .add_event::<BeatFinished>()
+            .add_event::<StoryFinished>()
+            .add_event::<StoryFailed>()
+            .add_event::<RuleBecameTrue>()
+            .add_event::<RuleBecameFalse>()
+            .add_event::<StoryEntityStep>()
+            .add_event::<PickStorylet>()
+            .add_event::<StoryletPlayed>()
+            .add_event::<DialogueRequest>()
+            .add_event::<DialogueResponse>()
+            .add_event::<StartConversation>()
+            .add_event::<DialogueChoiceMade>()
+            .add_event::<ConversationEnded>()
+            .add_event::<FactErrorOccurred>()
             .add_systems(
                 OnEnter(GameState::Story),
-                (setup_stories), //setup, spawn_layout, 
+                (
+                    setup_stories,
+                    load_stories_from_assets,
+                    load_responses_from_assets,
+                    load_conversations_from_assets,
+                    load_storylets_from_assets,
+                    story_text_validator,
+                    story_validator,
+                    story_analyzer,
+                )
+                    .chain(), //setup, spawn_layout, 
             )
             .add_systems(
                 Update,
+                // Chained so every change of a frame is evaluated in that same frame
                 (
+                    button_system,
+                    hot_reload_stories,
+                    storylet_picker,
+                    dialogue_responder,
+                    conversation_runner,
                     fact_update_event_broadcaster,
+                    story_evaluator,
+                    story_entity_evaluator,
+                    active_beat_updater,
                     fact_event_system,
                     rule_event_system,
-                    button_system,
-                    story_evaluator,
-                    story_beat_effect_applier
+                    beat_conversation_starter,
                 )
+                    .chain()
                     .run_if(in_state(GameState::Story)),
             )
             .add_systems(
@@ -57,6 +135,7 @@ impl Plugin for StoryPlugin {
                     spawn_simple_widget.run_if(not(any_with_component::<SimpleWidget>)),
                     spawn_fps_widget.run_if(not(any_with_component::<FpsWidget>)),
                     spawn_banner_widgets.run_if(not(any_with_component::<BannerWidget>)),
+                    spawn_dialogue_widget.run_if(not(any_with_component::<DialogueWidget>)),
                     move_banner_example,
                 ).run_if(in_state(GameState::Story)))
         ;
@@ -106,6 +185,10 @@ fn spawn_fps_widget(mut commands: Commands) {
     commands.ui_builder(UiRoot).fps();
 }
 
+fn spawn_dialogue_widget(mut commands: Commands) {
+    commands.ui_builder(UiRoot).dialogue_box();
+}
+
 #[derive(Component)]
 struct FlyingExample;
 
@@ -115,23 +198,19 @@ fn spawn_banner_widgets(mut commands: Commands) {
 
     commands
         .ui_builder(UiRoot)
-        .banner_widget(BannerWidgetConfig::new("Hello, World!", font, font_size))
+        .banner_widget(BannerWidgetConfig::new("banner.hello", font, font_size))
         .entity_commands()
         .set_position(100.0, 100.0);
 
     commands
         .ui_builder(UiRoot)
-        .banner_widget(BannerWidgetConfig::new(
-            "Bonjour, le Monde!",
-            font,
-            font_size,
-        ))
+        .banner_widget(BannerWidgetConfig::new("banner.presses", font, font_size).with_count_fact("button_pressed"))
         .entity_commands()
         .set_position(300.0, 300.0);
 
     commands
         .ui_builder(UiRoot)
-        .banner_widget(BannerWidgetConfig::new("¡Hola, Mundo!", font, font_size))
+        .banner_widget(BannerWidgetConfig::new("banner.welcome", font, font_size))
         .entity_commands()
         .set_position(700.0, 100.0)
         .insert(FlyingExample);
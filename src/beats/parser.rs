@@ -0,0 +1,796 @@
+use crate::beats::data::{CompletionPolicy, Condition, Effect, Fact, Failure, HashableFloat, Objective, Rule, Story, StoryBeat, StringHashSet, Transition};
+use crate::beats::expression::expression;
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_until, take_while, take_while1};
+use nom::character::complete::{char, digit1, space0, space1};
+use nom::combinator::{all_consuming, cut, map, map_res, not, opt, peek, recognize, value};
+use nom::error::{context, ParseError, VerboseError, VerboseErrorKind};
+use nom::multi::separated_list0;
+use nom::sequence::{delimited, preceded, terminated, tuple};
+use std::fmt::{Display, Formatter};
+
+/*
+The story format is line based and deliberately close to markdown, so writers can edit it
+without knowing anything about the engine:
+
+# Hero's Journey                  <- starts a new story
+Start Criteria:                   <- a named rule, before the first beat it is a pre-requisite
+    button_pressed > 1            <- indented conditions, all of them must hold
+! Hero Died:                      <- a failure, before the first beat it fails the story whatever beat
+    hero_health <= 0                 is active, in a beat only while that beat is active
+    => hero_lost = true           <- an effect applied when the failure triggers
+
+## The Call to Adventure          <- starts a new beat in the current story
+Enough Presses:                   <- a named rule of the beat
+    button_pressed > 3
+Effects:                          <- effects applied when the beat is finished
+    quest_one_complete = true
+Answer -> The Mentor:             <- a transition, once the beat is finished the first transition whose
+    courage > 2                      conditions hold leads to the named beat, END ends the story
+Refuse -> END:
+
+## Gather Supplies
+Complete: 2                       <- how many objectives must be completed, all, any or a number
+- Find Rope:                      <- an objective, completed on its own in any order with the others
+    "rope" in inventory
+    => gold += 5                  <- an effect applied when the objective is completed
+- Find Food:
+    food > 0
+! Supplies Stolen -> Chase:       <- a failure with a recovery beat continues there instead of failing
+    thieves_spotted
+
+## Meet the Smith
+Conversation: Smith Greeting      <- a conversation that starts when the beat is activated
+
+Without transitions a beat continues with the next beat in the file. Transitions can lead to any
+beat of the story, so stories can branch, join up again and loop.
+
+A story heading can declare parameters as in `# Fetch {item} for {npc} (npc, item)`, which makes the
+story a template. `{npc}` and `{item}` in names, fact names and strings are filled in for each
+instance by StoryEngine::instantiate.
+
+Conditions are expressions like `button_pressed > 3 && !quest_one_complete && "sword" in inventory`,
+see [`crate::beats::expression`] for everything they support.
+Effects are `fact = value` where value is an integer, a float like 0.5, a "string", true / false or
+["a", "list"], `fact += 1`, `fact -= 1`, `fact *= 2` and `fact = clamp(0, 10)` for numbers, `list += "value"` and
+`list -= "value"` for lists, and `toggle flag`, `clear list` and `remove fact`.
+Empty lines and lines starting with // are ignored.
+ */
+
+pub(super) type ParseResult<'a, T> = nom::IResult<&'a str, T, VerboseError<&'a str>>;
+
+const EFFECTS_BLOCK: &str = "Effects";
+pub(super) const END_TARGET: &str = "END";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoryParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl StoryParseError {
+    pub(super) fn new(line: usize, column: usize, message: impl Into<String>) -> Self {
+        StoryParseError {
+            line,
+            column,
+            message: message.into(),
+        }
+    }
+
+    fn from_nom(line_number: usize, line: &str, error: nom::Err<VerboseError<&str>>) -> Self {
+        let (column, message) = error_position(line, error);
+        StoryParseError::new(line_number, column, message)
+    }
+}
+
+// The column the error points at in `line`, counted from 1, and the message of its innermost context
+pub(super) fn error_position(line: &str, error: nom::Err<VerboseError<&str>>) -> (usize, String) {
+    match error {
+        nom::Err::Error(error) | nom::Err::Failure(error) => {
+            let remaining = error.errors.first().map(|(remaining, _)| *remaining).unwrap_or(line);
+            let column = line[..line.len() - remaining.len()].chars().count() + 1;
+            let message = error
+                .errors
+                .iter()
+                .find_map(|(_, kind)| match kind {
+                    VerboseErrorKind::Context(context) => Some(context.to_string()),
+                    _ => None,
+                })
+                .unwrap_or_else(|| "unexpected input".to_string());
+            (column, message)
+        }
+        nom::Err::Incomplete(_) => (line.chars().count() + 1, "unexpected end of line".to_string()),
+    }
+}
+
+impl Display for StoryParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for StoryParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Literal {
+    Int(i32),
+    Float(f32),
+    String(String),
+    Bool(bool),
+    List(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Assignment {
+    Set,
+    Add,
+    Subtract,
+    Multiply,
+}
+
+#[derive(Clone)]
+enum Line<'a> {
+    Blank,
+    Story(&'a str, Vec<&'a str>),
+    Beat(&'a str),
+    Block(&'a str),
+    Transition(&'a str, &'a str),
+    Objective(&'a str),
+    Failure(&'a str, Option<&'a str>),
+    Completion(CompletionPolicy),
+    Conversation(&'a str),
+    Item,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Block {
+    None,
+    PreRequisite,
+    BeatRule,
+    Transition,
+    Objective,
+    StoryFailure,
+    BeatFailure,
+    Effects,
+}
+
+// Parses the contents of a `.story` file into the stories it declares
+pub fn parse_stories(input: &str) -> Result<Vec<Story>, StoryParseError> {
+    let mut stories: Vec<Story> = Vec::new();
+    let mut block = Block::None;
+    // Targets are checked once all beats of a story are known
+    let mut transition_lines: Vec<(usize, usize, String)> = Vec::new();
+
+    for (index, raw_line) in input.lines().enumerate() {
+        let line_number = index + 1;
+        let line = raw_line.trim_end();
+        let parsed_line = classify_line(line)
+            .map(|(_, parsed_line)| parsed_line)
+            .map_err(|error| StoryParseError::from_nom(line_number, line, error))?;
+
+        match parsed_line {
+            Line::Blank => {}
+            Line::Story(name, parameters) => {
+                let mut story = Story::new(name.to_string(), Vec::new(), Vec::new());
+                story.parameters = parameters.into_iter().map(str::to_string).collect();
+                stories.push(story);
+                block = Block::None;
+            }
+            Line::Beat(name) => {
+                let Some(story) = stories.last_mut() else {
+                    return Err(StoryParseError::new(line_number, 1, "a beat must belong to a story, add a `# Story` heading first"));
+                };
+                story.beats.push(StoryBeat::new(name.to_string(), Vec::new(), Vec::new()));
+                block = Block::None;
+            }
+            Line::Block(name) => {
+                let Some(story) = stories.last_mut() else {
+                    return Err(StoryParseError::new(line_number, 1, "a rule must belong to a story, add a `# Story` heading first"));
+                };
+                block = match story.beats.last_mut() {
+                    Some(_) if name == EFFECTS_BLOCK => Block::Effects,
+                    None if name == EFFECTS_BLOCK => {
+                        return Err(StoryParseError::new(line_number, 1, "effects belong to a beat, add a `## Beat` heading first"));
+                    }
+                    Some(beat) => {
+                        beat.rules.push(Rule::new(name.to_string(), Vec::new()));
+                        Block::BeatRule
+                    }
+                    None => {
+                        story.pre_requisites.push(Rule::new(name.to_string(), Vec::new()));
+                        Block::PreRequisite
+                    }
+                };
+            }
+            Line::Transition(name, target) => {
+                let story_index = stories.len().saturating_sub(1);
+                let Some(beat) = stories.last_mut().and_then(|story| story.beats.last_mut()) else {
+                    return Err(StoryParseError::new(line_number, 1, "a transition belongs to a beat, add a `## Beat` heading first"));
+                };
+                let target = (target != END_TARGET).then(|| target.to_string());
+                if let Some(target) = &target {
+                    transition_lines.push((line_number, story_index, target.clone()));
+                }
+                beat.transitions.push(Transition::new(
+                    name.to_string(),
+                    vec![Rule::new(name.to_string(), Vec::new())],
+                    target,
+                ));
+                block = Block::Transition;
+            }
+            Line::Objective(name) => {
+                let Some(beat) = stories.last_mut().and_then(|story| story.beats.last_mut()) else {
+                    return Err(StoryParseError::new(line_number, 1, "an objective belongs to a beat, add a `## Beat` heading first"));
+                };
+                beat.objectives.push(Objective::new(
+                    name.to_string(),
+                    vec![Rule::new(name.to_string(), Vec::new())],
+                    Vec::new(),
+                ));
+                block = Block::Objective;
+            }
+            Line::Failure(name, recovery) => {
+                let story_index = stories.len().saturating_sub(1);
+                let Some(story) = stories.last_mut() else {
+                    return Err(StoryParseError::new(line_number, 1, "a failure must belong to a story, add a `# Story` heading first"));
+                };
+                let recovery = recovery.filter(|recovery| *recovery != END_TARGET).map(str::to_string);
+                if let Some(recovery) = &recovery {
+                    transition_lines.push((line_number, story_index, recovery.clone()));
+                }
+                let failure = Failure::new(
+                    name.to_string(),
+                    vec![Rule::new(name.to_string(), Vec::new())],
+                    Vec::new(),
+                    recovery,
+                );
+                block = match story.beats.last_mut() {
+                    Some(beat) => {
+                        beat.failures.push(failure);
+                        Block::BeatFailure
+                    }
+                    None => {
+                        story.failures.push(failure);
+                        Block::StoryFailure
+                    }
+                };
+            }
+            Line::Completion(completion) => {
+                let Some(beat) = stories.last_mut().and_then(|story| story.beats.last_mut()) else {
+                    return Err(StoryParseError::new(line_number, 1, "`Complete:` belongs to a beat, add a `## Beat` heading first"));
+                };
+                beat.completion = completion;
+                block = Block::None;
+            }
+            Line::Conversation(name) => {
+                let Some(beat) = stories.last_mut().and_then(|story| story.beats.last_mut()) else {
+                    return Err(StoryParseError::new(line_number, 1, "`Conversation:` belongs to a beat, add a `## Beat` heading first"));
+                };
+                beat.conversation = Some(name.to_string());
+                block = Block::None;
+            }
+            Line::Item => {
+                let story = stories.last_mut();
+                match block {
+                    Block::None => {
+                        return Err(StoryParseError::new(line_number, 1, "indented line outside of a rule or an `Effects:` block"));
+                    }
+                    Block::PreRequisite => {
+                        let condition = parse_line(line_number, line, condition_line)?;
+                        if let Some(rule) = story.and_then(|story| story.pre_requisites.last_mut()) {
+                            rule.conditions.push(condition);
+                        }
+                    }
+                    Block::BeatRule => {
+                        let condition = parse_line(line_number, line, condition_line)?;
+                        if let Some(rule) = story
+                            .and_then(|story| story.beats.last_mut())
+                            .and_then(|beat| beat.rules.last_mut())
+                        {
+                            rule.conditions.push(condition);
+                        }
+                    }
+                    Block::Transition => {
+                        let condition = parse_line(line_number, line, condition_line)?;
+                        if let Some(rule) = story
+                            .and_then(|story| story.beats.last_mut())
+                            .and_then(|beat| beat.transitions.last_mut())
+                            .and_then(|transition| transition.rules.last_mut())
+                        {
+                            rule.conditions.push(condition);
+                        }
+                    }
+                    Block::Objective => {
+                        let objective = story
+                            .and_then(|story| story.beats.last_mut())
+                            .and_then(|beat| beat.objectives.last_mut());
+                        if let Some(objective) = objective {
+                            match parse_line(line_number, line, condition_or_effect_line)? {
+                                ConditionOrEffect::Condition(condition) => push_condition(&mut objective.rules, condition),
+                                ConditionOrEffect::Effect(effect) => objective.effects.push(effect),
+                            }
+                        }
+                    }
+                    Block::StoryFailure | Block::BeatFailure => {
+                        let failure = match block {
+                            Block::StoryFailure => story.and_then(|story| story.failures.last_mut()),
+                            _ => story
+                                .and_then(|story| story.beats.last_mut())
+                                .and_then(|beat| beat.failures.last_mut()),
+                        };
+                        if let Some(failure) = failure {
+                            match parse_line(line_number, line, condition_or_effect_line)? {
+                                ConditionOrEffect::Condition(condition) => push_condition(&mut failure.rules, condition),
+                                ConditionOrEffect::Effect(effect) => failure.effects.push(effect),
+                            }
+                        }
+                    }
+                    Block::Effects => {
+                        let effect = parse_line(line_number, line, effect_line)?;
+                        if let Some(beat) = story.and_then(|story| story.beats.last_mut()) {
+                            beat.effects.push(effect);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    for (line_number, story_index, target) in transition_lines {
+        if stories[story_index].beat(&target).is_none() {
+            return Err(StoryParseError::new(
+                line_number,
+                1,
+                format!("'{}' is not a beat of this story", target),
+            ));
+        }
+    }
+
+    Ok(stories)
+}
+
+pub(super) fn parse_line<'a, T>(
+    line_number: usize,
+    line: &'a str,
+    parser: impl FnMut(&'a str) -> ParseResult<'a, T>,
+) -> Result<T, StoryParseError> {
+    all_consuming(parser)(line)
+        .map(|(_, parsed)| parsed)
+        .map_err(|error| StoryParseError::from_nom(line_number, line, error))
+}
+
+fn classify_line(input: &str) -> ParseResult<'_, Line<'_>> {
+    alt((
+        value(Line::Blank, all_consuming(space0)),
+        value(Line::Blank, preceded(tuple((space0, tag("//"))), rest_of_line)),
+        value(Line::Item, peek(space1)),
+        map(heading("##"), Line::Beat),
+        map(heading("#"), |heading| {
+            let (name, parameters) = story_heading(heading);
+            Line::Story(name, parameters)
+        }),
+        map(preceded(terminated(char('-'), space1), block_heading), Line::Objective),
+        map(failure_heading, |(name, recovery)| Line::Failure(name, recovery)),
+        map(transition_heading, |(name, target)| Line::Transition(name, target)),
+        map(completion_line, Line::Completion),
+        map(conversation_line, Line::Conversation),
+        map(block_heading, Line::Block),
+        context(
+            "expected a `# Story` or `## Beat` heading, a `Rule name:`, a `Name -> Beat:`, a `- Objective:`, a `! Failure:` or an indented line",
+            |input| Err(nom::Err::Failure(VerboseError::from_error_kind(input, nom::error::ErrorKind::Alt))),
+        ),
+    ))(input)
+}
+
+pub(super) fn heading<'a>(level: &'static str) -> impl FnMut(&'a str) -> ParseResult<'a, &'a str> {
+    preceded(
+        terminated(tag(level), space1),
+        context("expected a name after the heading marker", cut(non_empty_rest)),
+    )
+}
+
+// `Fetch Quest (npc, item)` declares the parameters of a template, a name that merely ends in
+// parentheses like `The Hero (Part 2)` is kept as it is
+fn story_heading(heading: &str) -> (&str, Vec<&str>) {
+    let Some((name, parameters)) = heading.strip_suffix(')').and_then(|heading| heading.rsplit_once('(')) else {
+        return (heading, Vec::new());
+    };
+    let parameters: Vec<&str> = parameters.split(',').map(str::trim).collect();
+    if parameters.iter().all(|parameter| is_word(parameter)) {
+        (name.trim_end(), parameters)
+    } else {
+        (heading, Vec::new())
+    }
+}
+
+fn is_word(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+pub(super) fn block_heading(input: &str) -> ParseResult<'_, &str> {
+    let (remaining, name) = terminated(take_while1(|c| c != ':'), char(':'))(input)?;
+    if remaining.trim().is_empty() {
+        Ok(("", name.trim()))
+    } else {
+        Err(nom::Err::Error(VerboseError::from_error_kind(remaining, nom::error::ErrorKind::Eof)))
+    }
+}
+
+// `Name -> Target beat:`, the name can be left out and is then the name of the target
+pub(super) fn transition_heading(input: &str) -> ParseResult<'_, (&str, &str)> {
+    let (rest, name) = terminated(take_until("->"), tag("->"))(input)?;
+    let (rest, target) = context(
+        "expected `Name -> Beat:` with the name of a beat or END",
+        cut(block_heading),
+    )(rest)?;
+    let name = name.trim();
+    if target.is_empty() {
+        return Err(failure(rest, "expected the name of a beat or END after `->`"));
+    }
+    Ok((rest, (if name.is_empty() { target } else { name }, target)))
+}
+
+// `! Name:` or `! Name -> Recovery beat:`
+fn failure_heading(input: &str) -> ParseResult<'_, (&str, Option<&str>)> {
+    let (rest, _) = terminated(char('!'), space1)(input)?;
+    if let Ok((rest, (name, recovery))) = transition_heading(rest) {
+        return Ok((rest, (name, Some(recovery))));
+    }
+    context(
+        "expected `! Failure:` or `! Failure -> Recovery beat:`",
+        cut(map(block_heading, |name| (name, None))),
+    )(rest)
+}
+
+// `Conversation: Name`
+fn conversation_line(input: &str) -> ParseResult<'_, &str> {
+    preceded(
+        terminated(tag("Conversation:"), space0),
+        context("expected the name of a conversation", cut(non_empty_rest)),
+    )(input)
+}
+
+// `Complete: all`, `Complete: any` or `Complete: 2`
+fn completion_line(input: &str) -> ParseResult<'_, CompletionPolicy> {
+    preceded(
+        terminated(tag("Complete:"), space0),
+        context(
+            "expected all, any or how many objectives must be completed",
+            cut(terminated(
+                alt((
+                    value(CompletionPolicy::All, tag("all")),
+                    value(CompletionPolicy::Any, tag("any")),
+                    map(nom::character::complete::u32, |count| CompletionPolicy::AtLeast(count as usize)),
+                )),
+                all_consuming(space0),
+            )),
+        ),
+    )(input)
+}
+
+pub(super) fn rest_of_line(input: &str) -> ParseResult<'_, &str> {
+    Ok(("", input))
+}
+
+fn non_empty_rest(input: &str) -> ParseResult<'_, &str> {
+    map(take_while1(|_| true), str::trim)(input)
+}
+
+// A fact name, optionally scoped as in `self.alerted` or `guard_3.alerted`, and in templates
+// with parameters as in `{npc}_trust`
+pub(super) fn identifier(input: &str) -> ParseResult<'_, &str> {
+    let word = || take_while1(|c: char| c.is_alphanumeric() || c == '_' || c == '{' || c == '}');
+    context(
+        "expected a fact name",
+        recognize(tuple((word(), opt(preceded(char('.'), word()))))),
+    )(input)
+}
+
+pub(super) fn string_literal(input: &str) -> ParseResult<'_, String> {
+    map(
+        delimited(char('"'), take_while(|c| c != '"'), context("missing closing `\"`", cut(char('"')))),
+        str::to_string,
+    )(input)
+}
+
+fn bool_literal(input: &str) -> ParseResult<'_, bool> {
+    terminated(
+        alt((value(true, tag("true")), value(false, tag("false")))),
+        not(take_while1(|c: char| c.is_alphanumeric() || c == '_')),
+    )(input)
+}
+
+fn list_literal(input: &str) -> ParseResult<'_, Vec<String>> {
+    delimited(
+        terminated(char('['), space0),
+        separated_list0(delimited(space0, char(','), space0), string_literal),
+        context("expected `]` to close the list", cut(preceded(space0, char(']')))),
+    )(input)
+}
+
+pub(super) fn literal(input: &str) -> ParseResult<'_, Literal> {
+    context(
+        "expected a number, a \"string\", true, false or a [\"list\"]",
+        alt((
+            map(string_literal, Literal::String),
+            map(bool_literal, Literal::Bool),
+            number,
+            map(list_literal, Literal::List),
+        )),
+    )(input)
+}
+
+// Numbers with a decimal point are floats, all others are integers
+fn number(input: &str) -> ParseResult<'_, Literal> {
+    alt((
+        map(float_literal, Literal::Float),
+        map(nom::character::complete::i32, Literal::Int),
+    ))(input)
+}
+
+pub(super) fn float_literal(input: &str) -> ParseResult<'_, f32> {
+    map_res(
+        recognize(tuple((opt(char('-')), digit1, char('.'), digit1))),
+        str::parse::<f32>,
+    )(input)
+}
+
+fn condition_line(input: &str) -> ParseResult<'_, Condition> {
+    let (input, _) = space1(input)?;
+    terminated(expression, space0)(input)
+}
+
+pub(super) enum ConditionOrEffect {
+    Condition(Condition),
+    Effect(Effect),
+}
+
+// Lines of objectives and failures are conditions, or effects when they start with `=>`
+pub(super) fn condition_or_effect_line(input: &str) -> ParseResult<'_, ConditionOrEffect> {
+    if let Ok((rest, _)) = tuple((space1::<_, VerboseError<&str>>, tag("=>")))(input) {
+        return map(cut(effect_line), ConditionOrEffect::Effect)(rest);
+    }
+    map(condition_line, ConditionOrEffect::Condition)(input)
+}
+
+// Objectives and failures have a single rule named after them
+fn push_condition(rules: &mut [Rule], condition: Condition) {
+    if let Some(rule) = rules.last_mut() {
+        rule.conditions.push(condition);
+    }
+}
+
+pub(super) fn effect_line(input: &str) -> ParseResult<'_, Effect> {
+    let (input, _) = space1(input)?;
+    if let Ok((rest, effect)) = keyword_effect(input) {
+        return Ok((rest, effect));
+    }
+
+    let (rest, fact_name) = identifier(input)?;
+    let fact_name = fact_name.to_string();
+    let (rest, operator) = context(
+        "expected `=`, `+=`, `-=` or `*=` after the fact name",
+        cut(delimited(space0, assignment, space0)),
+    )(rest)?;
+
+    if operator == Assignment::Set {
+        if let (rest, Some(bounds)) = opt(clamp)(rest)? {
+            let effect = match bounds {
+                (Literal::Int(min), Literal::Int(max)) => Effect::ClampInt { fact_name, min, max },
+                (min, max) => Effect::ClampFloat {
+                    fact_name,
+                    min: HashableFloat(as_float(&min)),
+                    max: HashableFloat(as_float(&max)),
+                },
+            };
+            return Ok((rest, effect));
+        }
+    }
+
+    let literal_start = rest;
+    let (rest, literal) = cut(terminated(literal, space0))(rest)?;
+    let effect = match (operator, literal) {
+        (Assignment::Set, Literal::Int(value)) => Effect::SetFact(Fact::Int(fact_name, value)),
+        (Assignment::Set, Literal::String(value)) => Effect::SetFact(Fact::String(fact_name, value)),
+        (Assignment::Set, Literal::Bool(value)) => Effect::SetFact(Fact::Bool(fact_name, value)),
+        (Assignment::Set, Literal::Float(value)) => Effect::SetFact(Fact::Float(fact_name, HashableFloat(value))),
+        (Assignment::Set, Literal::List(values)) => Effect::SetFact(Fact::StringList(fact_name, string_list(values))),
+        (Assignment::Add, Literal::Int(value)) => Effect::AddInt { fact_name, value },
+        (Assignment::Add, Literal::String(value)) => {
+            Effect::SetFact(Fact::StringList(fact_name, string_list(vec![value])))
+        }
+        (Assignment::Add, Literal::List(values)) => Effect::SetFact(Fact::StringList(fact_name, string_list(values))),
+        (Assignment::Subtract, Literal::Int(value)) => Effect::SubtractInt { fact_name, value },
+        (Assignment::Subtract, Literal::String(value)) => Effect::RemoveFromList { fact_name, value },
+        (Assignment::Multiply, Literal::Int(value)) => Effect::MultiplyInt { fact_name, value },
+        (Assignment::Add, Literal::Float(value)) => Effect::AddFloat { fact_name, value: HashableFloat(value) },
+        (Assignment::Subtract, Literal::Float(value)) => Effect::SubtractFloat { fact_name, value: HashableFloat(value) },
+        (Assignment::Multiply, Literal::Float(value)) => Effect::MultiplyFloat { fact_name, value: HashableFloat(value) },
+        _ => {
+            return Err(failure(
+                literal_start,
+                "`+=` takes a number or strings, `-=` a number or a string and `*=` a number",
+            ));
+        }
+    };
+    Ok((rest, effect))
+}
+
+fn keyword_effect(input: &str) -> ParseResult<'_, Effect> {
+    let (rest, (keyword, _, fact_name, _)) = tuple((
+        alt((tag("toggle"), tag("clear"), tag("remove"))),
+        space1,
+        identifier,
+        all_consuming(space0),
+    ))(input)?;
+    let fact_name = fact_name.to_string();
+    let effect = match keyword {
+        "toggle" => Effect::ToggleBool { fact_name },
+        "clear" => Effect::ClearList { fact_name },
+        _ => Effect::RemoveFact { fact_name },
+    };
+    Ok((rest, effect))
+}
+
+fn assignment(input: &str) -> ParseResult<'_, Assignment> {
+    alt((
+        value(Assignment::Add, tag("+=")),
+        value(Assignment::Subtract, tag("-=")),
+        value(Assignment::Multiply, tag("*=")),
+        value(Assignment::Set, char('=')),
+    ))(input)
+}
+
+fn clamp(input: &str) -> ParseResult<'_, (Literal, Literal)> {
+    let bound = |input| context("expected a number", cut(number))(input);
+    preceded(
+        terminated(tag("clamp"), space0),
+        delimited(
+            context("expected `(` after clamp", cut(terminated(char('('), space0))),
+            tuple((
+                bound,
+                preceded(context("expected `,` between min and max", cut(delimited(space0, char(','), space0))), bound),
+            )),
+            context("expected `)` to close clamp", cut(preceded(space0, char(')')))),
+        ),
+    )(input)
+}
+
+fn as_float(literal: &Literal) -> f32 {
+    match literal {
+        Literal::Float(value) => *value,
+        Literal::Int(value) => *value as f32,
+        _ => 0.0,
+    }
+}
+
+fn string_list(values: Vec<String>) -> StringHashSet {
+    let mut list = StringHashSet::new();
+    for value in values {
+        list.insert(value);
+    }
+    list
+}
+
+pub(super) fn failure<'a>(input: &'a str, message: &'static str) -> nom::Err<VerboseError<&'a str>> {
+    nom::Err::Failure(VerboseError {
+        errors: vec![(input, VerboseErrorKind::Context(message))],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STORY_EXAMPLE: &str = include_str!("../../assets/story_example.story");
+
+    fn int_more_than(fact_name: &str, expected_value: i32) -> Condition {
+        Condition::IntMoreThan {
+            fact_name: fact_name.to_string(),
+            expected_value,
+        }
+    }
+
+    #[test]
+    fn parses_the_example_story() {
+        let stories = parse_stories(STORY_EXAMPLE).unwrap();
+        assert_eq!(stories.len(), 1);
+        let story = &stories[0];
+        assert_eq!(story.name, "Neverending Story");
+        assert_eq!(story.pre_requisites.len(), 1);
+        assert_eq!(story.pre_requisites[0].name, "Start Criteria");
+        assert_eq!(
+            story.pre_requisites[0].conditions,
+            vec![Condition::BoolEquals {
+                fact_name: "quest_two_complete".to_string(),
+                expected_value: true,
+            }]
+        );
+
+        let beat_names: Vec<&str> = story.beats.iter().map(|beat| beat.name.as_str()).collect();
+        assert_eq!(beat_names, vec!["Chapter 1: Entering the dungeon", "Chapter 2: The first room"]);
+        let entrance = &story.beats[0];
+        assert_eq!(entrance.conversation.as_deref(), Some("Smith Greeting"));
+        assert_eq!(entrance.rules[0].name, "Found The Entrance");
+        assert_eq!(entrance.rules[0].conditions, vec![int_more_than("button_pressed", 7)]);
+        assert_eq!(
+            entrance.effects,
+            vec![
+                Effect::SetFact(Fact::Bool("entered_dungeon".to_string(), true)),
+                Effect::SetFact(Fact::StringList("inventory".to_string(), string_list(vec!["torch".to_string()]))),
+            ]
+        );
+
+        let first_room = &story.beats[1];
+        assert_eq!(first_room.rules[0].conditions.len(), 2);
+        assert_eq!(first_room.rules[0].conditions[1], int_more_than("button_pressed", 9));
+        assert_eq!(
+            first_room.effects,
+            vec![Effect::SetFact(Fact::String("current_room".to_string(), "The first room".to_string()))]
+        );
+    }
+
+    #[test]
+    fn parsed_stories_survive_a_round_trip_through_ron() {
+        let stories = parse_stories(STORY_EXAMPLE).unwrap();
+        let serialized = ron::to_string(&stories).unwrap();
+        let deserialized: Vec<Story> = ron::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, stories);
+    }
+
+    #[test]
+    fn parses_transitions_objectives_and_failures() {
+        let input = "\
+# Heist
+## Plan
+Complete: any
+- Hire A Thief:
+    thief_hired
+    => gold -= 50
+! Caught -> Prison:
+    alarm_raised
+Ready -> Prison:
+    crew_ready
+## Prison
+Escape -> END:
+    door_open
+Spotted -> Plan:
+    alarm_raised
+";
+        let stories = parse_stories(input).unwrap();
+        let plan = &stories[0].beats[0];
+        assert_eq!(plan.completion, CompletionPolicy::Any);
+        assert_eq!(plan.objectives.len(), 1);
+        assert_eq!(
+            plan.objectives[0].effects,
+            vec![Effect::SubtractInt {
+                fact_name: "gold".to_string(),
+                value: 50,
+            }]
+        );
+        assert_eq!(plan.failures[0].recovery.as_deref(), Some("Prison"));
+        assert_eq!(plan.transitions[0].target.as_deref(), Some("Prison"));
+        assert_eq!(stories[0].beats[1].transitions[0].target, None);
+    }
+
+    #[test]
+    fn reports_the_line_and_column_of_errors() {
+        let error = parse_stories("# Story\n## Beat\nRule:\n    gold > \n").unwrap_err();
+        assert_eq!((error.line, error.column), (4, 11));
+
+        let error = parse_stories("# Story\n## Beat\nEffects:\n    name = \"unclosed\n").unwrap_err();
+        assert_eq!(error.line, 4);
+        assert_eq!(error.column, 21);
+        assert_eq!(error.message, "missing closing `\"`");
+
+        let error = parse_stories("## Beat\n").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 1));
+    }
+
+    #[test]
+    fn rejects_transitions_to_unknown_beats() {
+        let error = parse_stories("# Story\n## Beat\nDone -> Nowhere:\n    finished\n").unwrap_err();
+        assert_eq!(error.line, 3);
+    }
+}
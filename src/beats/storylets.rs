@@ -0,0 +1,319 @@
+use crate::beats::assets::StoryletAsset;
+use crate::beats::data::{Condition, Effect, FactError, FactErrorOccurred, FactErrorSource, FactSource, FactsOfTheWorld, Rule};
+use bevy::log::{info, warn};
+use crate::loading::StoryAssets;
+use bevy::prelude::{Assets, Event, EventReader, EventWriter, Res, ResMut, Resource};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+
+/*
+Storylets are small pieces of story that don't belong to a quest line. Each one has rules that
+decide when it is available and a weight, and the StoryletPool picks one of the available
+storylets whenever the game asks for one with a PickStorylet event:
+
+    pool.add_storylet(
+        StoryletBuilder::new("Tavern Brawl")
+            .with_rule("Drunk", |rule| rule.with_expr("ale > 3"))
+            .with_weight(2)
+            .with_cooldown(5)
+            .build(),
+    );
+
+Storylets are also loaded from `.storylets.ron` files, a serialized list of Storylet values.
+
+A weighted pick is random, but seeded by the pool, so the same seed and the same picks give the
+same storylets. Picking the most specific storylet chooses the one whose rules check the most
+conditions, the one that fits the situation best. Each side of `&&` is a condition of its own while
+`a || b` is one.
+ */
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct Storylet {
+    pub name: String,
+    pub rules: Vec<Rule>,
+    // Applied when the storylet is played
+    #[serde(default)]
+    pub effects: Vec<Effect>,
+    // How likely a weighted pick chooses this storylet compared to the other available ones
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    // Picks from the pool that have to pass before the storylet is available again
+    #[serde(default)]
+    pub cooldown: u64,
+    // A one shot storylet is only played once
+    #[serde(default)]
+    pub one_shot: bool,
+    // The pick of the pool that played the storylet last
+    #[serde(default)]
+    pub last_played: Option<u64>,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+impl Storylet {
+    pub fn new(name: String, rules: Vec<Rule>, effects: Vec<Effect>) -> Self {
+        Storylet {
+            name,
+            rules,
+            effects,
+            weight: default_weight(),
+            cooldown: 0,
+            one_shot: false,
+            last_played: None,
+        }
+    }
+
+    // Whether the storylet can be played at the given pick of the pool
+    pub fn is_available(&self, facts: &dyn FactSource, pick: u64) -> bool {
+        let resting = match self.last_played {
+            Some(_) if self.one_shot => true,
+            Some(last_played) => pick <= last_played + self.cooldown,
+            None => false,
+        };
+        !resting && self.rules.iter().all(|rule| rule.evaluate(facts))
+    }
+
+    // How closely the storylet fits the situation, a MostSpecific pick prefers the highest
+    pub fn specificity(&self) -> usize {
+        self.rules
+            .iter()
+            .flat_map(|rule| rule.conditions.iter())
+            .map(Condition::conjunct_count)
+            .sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StoryletSelection {
+    // Random, storylets with a higher weight are picked more often
+    #[default]
+    Weighted,
+    // The storylet with the most conditions, the higher weight wins a tie
+    MostSpecific,
+}
+
+#[derive(Resource, Debug, Clone, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct StoryletPool {
+    pub storylets: Vec<Storylet>,
+    // Weighted picks are random but the same seed gives the same picks
+    pub seed: u64,
+    // The number of storylets played so far, cooldowns count these
+    pub picks: u64,
+}
+
+impl StoryletPool {
+    pub fn new(seed: u64) -> Self {
+        StoryletPool {
+            storylets: Vec::new(),
+            seed,
+            picks: 0,
+        }
+    }
+
+    pub fn add_storylet(&mut self, storylet: Storylet) {
+        self.storylets.push(storylet);
+    }
+
+    pub fn add_storylets(&mut self, storylets: impl IntoIterator<Item = Storylet>) {
+        self.storylets.extend(storylets);
+    }
+
+    pub fn storylet(&self, storylet: usize) -> Option<&Storylet> {
+        self.storylets.get(storylet)
+    }
+
+    // The indices of the storylets that can be played right now
+    pub fn available(&self, facts: &dyn FactSource) -> Vec<usize> {
+        self.storylets
+            .iter()
+            .enumerate()
+            .filter(|(_, storylet)| storylet.is_available(facts, self.picks))
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    // Chooses one of the available storylets without playing it
+    pub fn choose(&self, facts: &dyn FactSource, selection: StoryletSelection) -> Option<usize> {
+        let available = self.available(facts);
+        match selection {
+            StoryletSelection::Weighted => {
+                let total_weight: u64 = available.iter().map(|&index| self.storylets[index].weight as u64).sum();
+                if total_weight == 0 {
+                    return None;
+                }
+                // Seeded by the number of picks so a loaded save picks the same as before
+                let mut rng = StdRng::seed_from_u64(self.seed.wrapping_add(self.picks));
+                let mut roll = rng.gen_range(0..total_weight);
+                available.into_iter().find(|&index| {
+                    let weight = self.storylets[index].weight as u64;
+                    if roll < weight {
+                        return true;
+                    }
+                    roll -= weight;
+                    false
+                })
+            }
+            StoryletSelection::MostSpecific => available.into_iter().min_by_key(|&index| {
+                let storylet = &self.storylets[index];
+                (Reverse(storylet.specificity()), Reverse(storylet.weight))
+            }),
+        }
+    }
+
+    // Plays the storylet, starting its cooldown and applying its effects
+    pub fn play(&mut self, storylet: usize, facts: &mut FactsOfTheWorld) -> Vec<FactError> {
+        let Some(played) = self.storylets.get_mut(storylet) else {
+            return Vec::new();
+        };
+        played.last_played = Some(self.picks);
+        self.picks += 1;
+        played
+            .effects
+            .iter()
+            .filter_map(|effect| effect.apply(facts).err())
+            .collect()
+    }
+
+    // Chooses one of the available storylets and plays it
+    pub fn pick(&mut self, facts: &mut FactsOfTheWorld, selection: StoryletSelection) -> Option<(usize, Vec<FactError>)> {
+        let storylet = self.choose(&facts.facts, selection)?;
+        Some((storylet, self.play(storylet, facts)))
+    }
+}
+
+// Asks the StoryletPool to pick and play one of the available storylets
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PickStorylet {
+    pub selection: StoryletSelection,
+}
+
+// The index of the storylet in the StoryletPool
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoryletPlayed {
+    pub storylet: usize,
+}
+
+pub fn load_storylets_from_assets(
+    mut pool: ResMut<StoryletPool>,
+    story_assets: Res<StoryAssets>,
+    storylets: Res<Assets<StoryletAsset>>,
+) {
+    for storylet_asset in story_assets.storylets.iter().filter_map(|handle| storylets.get(handle)) {
+        pool.add_storylets(storylet_asset.storylets.iter().cloned());
+    }
+}
+
+pub fn storylet_picker(
+    mut requests: EventReader<PickStorylet>,
+    mut pool: ResMut<StoryletPool>,
+    mut cool_fact_store: ResMut<FactsOfTheWorld>,
+    mut played_writer: EventWriter<StoryletPlayed>,
+    mut fact_error_writer: EventWriter<FactErrorOccurred>,
+) {
+    for request in requests.read() {
+        let Some((storylet, fact_errors)) = pool.pick(&mut cool_fact_store, request.selection) else {
+            info!("No storylet is available");
+            continue;
+        };
+        let name = &pool.storylets[storylet].name;
+        for error in fact_errors {
+            warn!("Storylet '{}': {}", name, error);
+            fact_error_writer.send(FactErrorOccurred {
+                source: FactErrorSource::Storylet(name.clone()),
+                error,
+            });
+        }
+        played_writer.send(StoryletPlayed { storylet });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beats::expression::parse_condition;
+
+    fn storylet(name: &str, conditions: &[&str]) -> Storylet {
+        let conditions = conditions.iter().map(|condition| parse_condition(condition).unwrap()).collect();
+        Storylet::new(name.to_string(), vec![Rule::new(name.to_string(), conditions)], Vec::new())
+    }
+
+    #[test]
+    fn reads_the_tavern_storylets() {
+        let storylets: Vec<Storylet> = ron::from_str(include_str!("../../assets/tavern.storylets.ron")).unwrap();
+        let mut pool = StoryletPool::new(0);
+        pool.add_storylets(storylets);
+        assert_eq!(pool.storylets.len(), 2);
+        assert_eq!((pool.storylets[0].weight, pool.storylets[0].cooldown), (2, 5));
+        assert!(pool.storylets[1].one_shot);
+        assert_eq!(pool.storylets[1].weight, 1);
+
+        let mut facts = FactsOfTheWorld::new();
+        assert_eq!(pool.available(&facts.facts), vec![1]);
+        facts.store_int("button_pressed".to_string(), 4);
+        assert_eq!(pool.available(&facts.facts), vec![0, 1]);
+    }
+
+    fn picks(pool: &mut StoryletPool, selection: StoryletSelection, count: usize) -> Vec<usize> {
+        let mut facts = FactsOfTheWorld::new();
+        facts.store_bool("night".to_string(), true);
+        (0..count).filter_map(|_| pool.pick(&mut facts, selection).map(|(storylet, _)| storylet)).collect()
+    }
+
+    #[test]
+    fn rests_storylets_for_their_cooldown() {
+        let mut pool = StoryletPool::new(0);
+        let mut brawl = storylet("Brawl", &["night"]);
+        brawl.cooldown = 2;
+        pool.add_storylet(brawl);
+        pool.add_storylet(storylet("Quiet Evening", &[]));
+        assert_eq!(picks(&mut pool, StoryletSelection::MostSpecific, 5), vec![0, 1, 1, 0, 1]);
+    }
+
+    #[test]
+    fn plays_one_shot_storylets_once() {
+        let mut pool = StoryletPool::new(0);
+        let mut bard = storylet("Wandering Bard", &["night"]);
+        bard.one_shot = true;
+        pool.add_storylet(bard);
+        pool.add_storylet(storylet("Quiet Evening", &[]));
+        assert_eq!(picks(&mut pool, StoryletSelection::MostSpecific, 3), vec![0, 1, 1]);
+    }
+
+    #[test]
+    fn prefers_the_heavier_of_equally_specific_storylets() {
+        let mut pool = StoryletPool::new(0);
+        pool.add_storylet(storylet("Brawl", &["night"]));
+        let mut bard = storylet("Wandering Bard", &["night"]);
+        bard.weight = 3;
+        pool.add_storylet(bard);
+        assert_eq!(picks(&mut pool, StoryletSelection::MostSpecific, 1), vec![1]);
+    }
+
+    #[test]
+    fn repeats_weighted_picks_with_the_same_seed() {
+        let pool = |seed| {
+            let mut pool = StoryletPool::new(seed);
+            for (name, weight) in [("Brawl", 1), ("Wandering Bard", 2), ("Quiet Evening", 3), ("Never", 0)] {
+                let mut storylet = storylet(name, &[]);
+                storylet.weight = weight;
+                pool.add_storylet(storylet);
+            }
+            pool
+        };
+        let played = picks(&mut pool(7), StoryletSelection::Weighted, 20);
+        assert_eq!(played.len(), 20);
+        assert!(!played.contains(&3));
+        assert!(played.iter().any(|&storylet| storylet != played[0]));
+        assert_eq!(picks(&mut pool(7), StoryletSelection::Weighted, 20), played);
+
+        // A pool loaded from a save picks what the saved one would have picked
+        let mut saved = pool(7);
+        picks(&mut saved, StoryletSelection::Weighted, 10);
+        let mut loaded: StoryletPool = ron::from_str(&ron::to_string(&saved).unwrap()).unwrap();
+        assert_eq!(picks(&mut loaded, StoryletSelection::Weighted, 10), played[10..]);
+    }
+}
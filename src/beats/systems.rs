@@ -1,11 +1,18 @@
-use crate::beats::data::{Condition, FactsOfTheWorld, FactUpdated, Rule, RuleUpdated, StoryBeatFinished, StoryEngine};
+use crate::beats::assets::StoryAsset;
+use crate::beats::data::{BeatActivated, BeatFinished, BeatId, Condition, FactErrorOccurred, FactRemoved, FactsOfTheWorld, FactUpdated, ObjectiveCompleted, Rule, RuleBecameFalse, RuleBecameTrue, RuleId, StoryEngine, StoryFailed, StoryEvaluationSettings, StoryFinished, StoryId, StoryStarted, StoryStep};
+use crate::beats::text::TextTemplates;
 use crate::beats::TextComponent;
-use bevy::asset::{AssetServer, Assets, Handle};
+use bevy::asset::{AssetEvent, AssetServer, Assets, Handle};
+use bevy::ecs::system::SystemParam;
+use bevy::log::{error, info, warn};
 use bevy::hierarchy::{ChildBuilder, Children};
 use bevy::math::Vec2;
 use bevy::prelude::{default, AlignItems, BackgroundColor, BorderColor, BuildChildren, Button, ButtonBundle, Changed, Color, ColorMaterial, Commands, Display, EventReader, EventWriter, Font, GridPlacement, GridTrack, Interaction, JustifyContent, JustifyItems, Mesh, NodeBundle, PositionType, Query, RepeatedGridTrack, Res, ResMut, Style, Text, TextBundle, TextStyle, Transform, Triangle2d, UiRect, Val, Visibility, With, JustifyText};
 use bevy::sprite::{MaterialMesh2dBundle, Mesh2dHandle};
+use bevy::utils::HashSet;
 use crate::beats::builders::StoryBuilder;
+use crate::loading::StoryAssets;
+use crate::localization::{CurrentLocale, Localization};
 use crate::ui::builders::{add_button, NodeBundleBuilder};
 
 pub fn spawn_layout(mut commands: Commands, asset_server: Res<AssetServer>) {
@@ -220,7 +227,12 @@ const PRESSED_BUTTON: Color = Color::rgb(0.35, 0.75, 0.35);
 pub fn fact_event_system(
     mut query: Query<&mut Text, With<TextComponent>>,
     mut fact_update_events: EventReader<FactUpdated>,
-    mut story_beat_updated: EventReader<StoryBeatFinished>,
+    mut story_beat_updated: EventReader<BeatFinished>,
+    story_engine: Res<StoryEngine>,
+    cool_fact_store: Res<FactsOfTheWorld>,
+    localization: Res<Localization>,
+    current_locale: Res<CurrentLocale>,
+    mut text_templates: ResMut<TextTemplates>,
 ) {
     for event in fact_update_events.read() {
         for mut text in query.iter_mut() {
@@ -228,29 +240,35 @@ pub fn fact_event_system(
         }
     }
 
-    for story_updated in story_beat_updated.read() {
+    for story_updated in story_beat_updated.read().filter_map(|event| story_engine.beat(event.beat)) {
         for mut text in query.iter_mut() {
-            text.sections[0].value = format!("{}\n Story Beat updated: {:?}\n", text.sections[0].value, story_updated.beat.name);
+            let name = text_templates.render(localization.text(&current_locale.0, &story_updated.name), &cool_fact_store.facts);
+            text.sections[0].value = format!("{}\n Story Beat updated: {:?}\n", text.sections[0].value, name);
         }
     }
 }
 
 pub fn fact_update_event_broadcaster(
     mut event_writer: EventWriter<FactUpdated>,
+    mut removed_writer: EventWriter<FactRemoved>,
     mut storage: ResMut<FactsOfTheWorld>,
 ) {
     for fact in storage.updated_facts.drain() {
         event_writer.send(FactUpdated { fact });
     }
+    for name in storage.removed_facts.drain() {
+        removed_writer.send(FactRemoved { name });
+    }
 }
 
 pub fn rule_event_system(
     mut query: Query<&mut Text, With<TextComponent>>,
-    mut rule_updated_events: EventReader<RuleUpdated>,
+    mut rule_became_true_events: EventReader<RuleBecameTrue>,
+    story_engine: Res<StoryEngine>,
 ) {
-    for event in rule_updated_events.read() {
+    for rule in rule_became_true_events.read().filter_map(|event| story_engine.rule(event.rule)) {
         for mut text in query.iter_mut() {
-            text.sections[0].value = format!("{}\n{:?}", text.sections[0].value, event.rule);
+            text.sections[0].value = format!("{}\n Rule holds: {}", text.sections[0].value, rule.name);
         }
     }
 }
@@ -325,40 +343,94 @@ pub fn setup(
     }
 }
 
-pub fn story_evaluator(
-    mut fact_updated: EventReader<FactUpdated>,
-    mut story_engine: ResMut<StoryEngine>,
-    cool_fact_store: Res<FactsOfTheWorld>,
-    mut story_beat_writer: EventWriter<StoryBeatFinished>,
-) {
-    if !fact_updated.is_empty() {
-        fact_updated.clear();
-        for story in &mut story_engine.stories.iter_mut().filter(|s| !s.is_started) {
-            story.start_if_possible(&cool_fact_store.facts);
-        }
+// Writers for all lifecycle events of the story engine
+#[derive(SystemParam)]
+pub struct StoryEventWriters<'w> {
+    story_started: EventWriter<'w, StoryStarted>,
+    beat_activated: EventWriter<'w, BeatActivated>,
+    objective_completed: EventWriter<'w, ObjectiveCompleted>,
+    beat_finished: EventWriter<'w, BeatFinished>,
+    story_finished: EventWriter<'w, StoryFinished>,
+    story_failed: EventWriter<'w, StoryFailed>,
+    rule_became_true: EventWriter<'w, RuleBecameTrue>,
+    rule_became_false: EventWriter<'w, RuleBecameFalse>,
+}
 
-        for story in &mut story_engine.stories.iter_mut().filter(|s| s.is_started && !s.is_finished()) {
-            match story.evaluate_active_beat(&cool_fact_store.facts) {
-                None => {}
-                Some(story_beat) => {
-                    story_beat_writer.send(StoryBeatFinished {
-                        story: story.clone(),
-                        beat: story_beat.clone(),
-                    });
+impl StoryEventWriters<'_> {
+    pub fn send(&mut self, story: StoryId, story_step: StoryStep) {
+        let beat_id = |beat| BeatId { story, beat };
+        match story_step {
+            StoryStep::Started => {
+                self.story_started.send(StoryStarted { story });
+            }
+            StoryStep::BeatActivated(beat) => {
+                self.beat_activated.send(BeatActivated { beat: beat_id(beat) });
+            }
+            StoryStep::ObjectiveCompleted {
+                beat,
+                objective,
+                completed,
+                total,
+            } => {
+                self.objective_completed.send(ObjectiveCompleted {
+                    beat: beat_id(beat),
+                    objective,
+                    completed,
+                    total,
+                });
+            }
+            StoryStep::BeatFinished(beat) => {
+                self.beat_finished.send(BeatFinished { beat: beat_id(beat) });
+            }
+            StoryStep::Finished => {
+                self.story_finished.send(StoryFinished { story });
+            }
+            StoryStep::Failed { beat, failure } => {
+                self.story_failed.send(StoryFailed {
+                    story,
+                    beat: beat.map(beat_id),
+                    failure,
+                });
+            }
+            StoryStep::RuleChanged { beat, rule, holds } => {
+                let rule = RuleId { story, beat, rule };
+                if holds {
+                    self.rule_became_true.send(RuleBecameTrue { rule });
+                } else {
+                    self.rule_became_false.send(RuleBecameFalse { rule });
                 }
             }
         }
     }
 }
 
-pub fn story_beat_effect_applier(
-    mut story_beat_reader: EventReader<StoryBeatFinished>,
+// Evaluates the stories and applies their effects until nothing changes anymore, see
+// StoryEngine::evaluate_until_settled
+pub fn story_evaluator(
+    mut fact_updated: EventReader<FactUpdated>,
+    mut fact_removed: EventReader<FactRemoved>,
+    mut story_engine: ResMut<StoryEngine>,
     mut cool_fact_store: ResMut<FactsOfTheWorld>,
+    settings: Res<StoryEvaluationSettings>,
+    mut story_events: StoryEventWriters,
+    mut fact_error_writer: EventWriter<FactErrorOccurred>,
 ) {
-    for event in story_beat_reader.read() {
-        for effect in event.beat.effects.iter() {
-            effect.apply(&mut cool_fact_store);
-        }
+    let changed_facts: HashSet<String> = fact_updated
+        .read()
+        .map(|event| event.fact.name().to_string())
+        .chain(fact_removed.read().map(|event| event.name.clone()))
+        .collect();
+
+    let evaluation = story_engine.evaluate_until_settled(changed_facts, &mut cool_fact_store, settings.max_iterations);
+    for (story_index, story_step) in evaluation.steps {
+        story_events.send(StoryId(story_index), story_step);
+    }
+    for fact_error in evaluation.fact_errors {
+        warn!("Effect of {} failed: {}", fact_error.source, fact_error.error);
+        fact_error_writer.send(fact_error);
+    }
+    if let Some(error) = evaluation.error {
+        error!("Story evaluation stopped after {} iterations: {}", evaluation.iterations, error);
     }
 }
 
@@ -376,10 +448,7 @@ pub fn setup_stories(
      */
     let story = StoryBuilder::new("Hero's Journey")
         .add_pre_requisite("Before We Start", |pre_req| {
-            pre_req.with_condition(Condition::IntMoreThan {
-                fact_name: "button_pressed".to_string(),
-                expected_value: 1,
-            })
+            pre_req.with_expr("button_pressed > 1")
         })
         .add_story_beat("The Call to Adventure", |beat| {
             beat.with_rule("Enough Presses", |rule| {
@@ -407,3 +476,113 @@ pub fn setup_stories(
 
     story_engine.add_story(story);
 }
+
+pub fn load_stories_from_assets(
+    mut story_engine: ResMut<StoryEngine>,
+    story_assets: Res<StoryAssets>,
+    stories: Res<Assets<StoryAsset>>,
+) {
+    for story_asset in story_assets.stories.iter().filter_map(|handle| stories.get(handle)) {
+        for story in story_asset.stories.iter() {
+            if story.is_template() {
+                story_engine.add_template(story.clone());
+            } else {
+                story_engine.add_story(story.clone());
+            }
+        }
+    }
+}
+
+pub fn hot_reload_stories(
+    mut asset_events: EventReader<AssetEvent<StoryAsset>>,
+    mut story_engine: ResMut<StoryEngine>,
+    stories: Res<Assets<StoryAsset>>,
+) {
+    for event in asset_events.read() {
+        let AssetEvent::Modified { id } = event else {
+            continue;
+        };
+        let Some(story_asset) = stories.get(*id) else {
+            continue;
+        };
+        for story in story_asset.stories.iter() {
+            if story.is_template() {
+                story_engine.add_template(story.clone());
+                info!("Reloaded story template '{}'", story.name);
+                continue;
+            }
+            for warning in story_engine.reload_story(story.clone()) {
+                warn!("{}", warning);
+            }
+            info!("Reloaded story '{}'", story.name);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beats::parser::parse_stories;
+    use bevy::prelude::{App, Event, Events, Update};
+
+    fn events<E: Event + Copy>(app: &mut App) -> Vec<E> {
+        app.world.resource_mut::<Events<E>>().drain().collect()
+    }
+
+    #[test]
+    fn sends_id_events_for_an_evaluation() {
+        let mut story_engine = StoryEngine::new();
+        let source = "# Forge\n## Light\n- Coal:\n    coal\nHot:\n    heat > 3\n\
+                      # Raid\n! Caught:\n    caught\n## Sneak\nOut:\n    escaped\n";
+        for story in parse_stories(source).unwrap() {
+            story_engine.add_story(story);
+        }
+        let mut facts = FactsOfTheWorld::new();
+        facts.store_bool("coal".to_string(), true);
+        facts.store_int("heat".to_string(), 5);
+        facts.store_bool("caught".to_string(), true);
+        facts.store_bool("escaped".to_string(), false);
+
+        let mut app = App::new();
+        app.add_event::<FactUpdated>()
+            .add_event::<FactRemoved>()
+            .add_event::<FactErrorOccurred>()
+            .add_event::<StoryStarted>()
+            .add_event::<BeatActivated>()
+            .add_event::<ObjectiveCompleted>()
+            .add_event::<BeatFinished>()
+            .add_event::<StoryFinished>()
+            .add_event::<StoryFailed>()
+            .add_event::<RuleBecameTrue>()
+            .add_event::<RuleBecameFalse>()
+            .insert_resource(story_engine)
+            .insert_resource(facts)
+            .init_resource::<StoryEvaluationSettings>()
+            .add_systems(Update, story_evaluator);
+        app.update();
+
+        let forge = StoryId(0);
+        let raid = StoryId(1);
+        let light = BeatId { story: forge, beat: 0 };
+        assert_eq!(events::<StoryStarted>(&mut app), vec![StoryStarted { story: forge }, StoryStarted { story: raid }]);
+        assert_eq!(
+            events::<BeatActivated>(&mut app),
+            vec![BeatActivated { beat: light }, BeatActivated { beat: BeatId { story: raid, beat: 0 } }]
+        );
+        assert_eq!(
+            events::<RuleBecameTrue>(&mut app),
+            vec![RuleBecameTrue { rule: RuleId { story: forge, beat: Some(0), rule: 0 } }]
+        );
+        assert!(events::<RuleBecameFalse>(&mut app).is_empty());
+        assert_eq!(
+            events::<ObjectiveCompleted>(&mut app),
+            vec![ObjectiveCompleted { beat: light, objective: 0, completed: 1, total: 1 }]
+        );
+        assert_eq!(events::<BeatFinished>(&mut app), vec![BeatFinished { beat: light }]);
+        assert_eq!(events::<StoryFinished>(&mut app), vec![StoryFinished { story: forge }]);
+        assert_eq!(
+            events::<StoryFailed>(&mut app),
+            vec![StoryFailed { story: raid, beat: None, failure: 0 }]
+        );
+    }
+}
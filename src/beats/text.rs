@@ -0,0 +1,571 @@
+use crate::beats::conversations::Conversations;
+use crate::beats::data::{Condition, Effect, Fact, FactSource, FactsOfTheWorld, StoryEngine};
+use crate::beats::dialogue::ResponseTable;
+use crate::beats::expression::parse_condition;
+use crate::beats::parser::{error_position, failure, ParseResult};
+use crate::beats::storylets::StoryletPool;
+use crate::beats::validation::{content_schema, FactSchema};
+use bevy::log::warn;
+use bevy::prelude::{Component, DetectChanges, Query, Ref, Res, Resource, Text};
+use bevy::utils::hashbrown::HashMap;
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_till1, take_while1};
+use nom::character::complete::{char, space0, space1};
+use nom::combinator::{all_consuming, cut, map, opt, value};
+use nom::error::context;
+use nom::multi::many0;
+use nom::sequence::{delimited, preceded, terminated, tuple};
+use std::fmt::{Display, Formatter};
+
+/*
+Texts shown to the player can contain the values of facts, filled in when they are displayed:
+
+    You have {gold} gold                  <- the value of a fact
+    You carry {inventory|count} items     <- filters change the value, from left to right
+    {if gold >= 10}Buy it?{else}Come back with more gold.{end}
+    {{ and }}                             <- literal braces
+
+The filters are `count` for the number of items in a list, `upper`, `lower` and `capitalize`,
+`join:sep` to join a list with something else than `, `, `decimals:2` for numbers and
+`default:text` for facts that aren't set or are empty. `{if}` takes a condition in the expression
+language and can be nested, `{else}` is optional. A fact that isn't set and has no default is shown
+as its placeholder, `validate_story_texts` reports those before the player sees them.
+ */
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextError {
+    pub text: String,
+    pub message: String,
+}
+
+impl TextError {
+    fn new(text: &str, message: impl Into<String>) -> Self {
+        TextError {
+            text: text.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for TextError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} in \"{}\"", self.message, self.text)
+    }
+}
+
+impl std::error::Error for TextError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextFilter {
+    Count,
+    Upper,
+    Lower,
+    Capitalize,
+    Join(String),
+    Decimals(usize),
+    Default(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextPart {
+    Literal(String),
+    Fact { name: String, filters: Vec<TextFilter> },
+    Conditional { condition: Condition, then: Vec<TextPart>, otherwise: Vec<TextPart> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextTemplate {
+    pub parts: Vec<TextPart>,
+}
+
+impl TextTemplate {
+    // Parses a text like `You have {gold} gold`
+    pub fn parse(text: &str) -> Result<TextTemplate, TextError> {
+        let (_, tokens) = all_consuming(many0(token))(text).map_err(|error| {
+            let (column, message) = error_position(text, error);
+            TextError::new(text, format!("column {}: {}", column, message))
+        })?;
+        let mut tokens = tokens.into_iter();
+        let (parts, end) = parts_until_end(&mut tokens, text)?;
+        match end {
+            None => Ok(TextTemplate { parts }),
+            Some(token) => Err(TextError::new(text, format!("`{}` without `{{if}}`", token.tag()))),
+        }
+    }
+
+    // A template of the text without placeholders
+    pub fn literal(text: impl Into<String>) -> Self {
+        TextTemplate {
+            parts: vec![TextPart::Literal(text.into())],
+        }
+    }
+
+    pub fn render(&self, facts: &dyn FactSource) -> String {
+        let mut rendered = String::new();
+        render_parts(&self.parts, facts, &mut rendered);
+        rendered
+    }
+
+    // The facts shown by the text or checked by its conditions
+    pub fn fact_names(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        collect_fact_names(&self.parts, &mut names);
+        names.sort_unstable();
+        names.dedup();
+        names
+    }
+}
+
+// The parsed templates of texts rendered by systems, so each text is only parsed the first time it
+// is shown. Texts that aren't valid templates are remembered as None and shown as they are.
+#[derive(Resource, Debug, Clone, Default)]
+pub struct TextTemplates {
+    templates: HashMap<String, Option<TextTemplate>>,
+}
+
+impl TextTemplates {
+    pub fn render(&mut self, text: &str, facts: &dyn FactSource) -> String {
+        if !text.contains('{') {
+            return text.to_string();
+        }
+        let template = self
+            .templates
+            .entry_ref(text)
+            .or_insert_with(|| TextTemplate::parse(text).ok());
+        match template {
+            Some(template) => template.render(facts),
+            None => text.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(String),
+    Fact(String, Vec<TextFilter>),
+    If(Condition),
+    Else,
+    End,
+}
+
+impl Token {
+    fn tag(&self) -> &'static str {
+        match self {
+            Token::Else => "{else}",
+            Token::End => "{end}",
+            _ => "",
+        }
+    }
+}
+
+fn token(input: &str) -> ParseResult<'_, Token> {
+    alt((
+        value(Token::Literal("{".to_string()), tag("{{")),
+        value(Token::Literal("}".to_string()), tag("}}")),
+        map(take_till1(|c| c == '{' || c == '}'), |text: &str| Token::Literal(text.to_string())),
+        delimited(
+            char('{'),
+            cut(alt((if_tag, fact_tag))),
+            context("expected `}` to close the placeholder", cut(char('}'))),
+        ),
+        lone_brace,
+    ))(input)
+}
+
+fn lone_brace(input: &str) -> ParseResult<'_, Token> {
+    // Only an actual `}` is an error, at the end of the text many0 has to stop normally
+    char('}')(input)?;
+    Err(failure(input, "a lone `}`, write `}}` for a brace"))
+}
+
+// `if gold >= 10`, the condition is written in the expression language
+fn if_tag(input: &str) -> ParseResult<'_, Token> {
+    let (rest, condition) = preceded(tuple((tag("if"), space1)), take_till1(|c| c == '}'))(input)?;
+    match parse_condition(condition) {
+        Ok(condition) => Ok((rest, Token::If(condition))),
+        Err(_) => Err(failure(input, "expected a condition after `if`")),
+    }
+}
+
+// `gold`, `inventory|count` or `title|default:stranger|upper`, and `else` and `end`
+fn fact_tag(input: &str) -> ParseResult<'_, Token> {
+    let (rest, name) = context(
+        "expected a fact name, `if`, `else` or `end`",
+        delimited(space0, take_while1(|c: char| c.is_alphanumeric() || c == '_' || c == '.'), space0),
+    )(input)?;
+    let (rest, filters) = many0(preceded(terminated(char('|'), space0), cut(filter)))(rest)?;
+    let token = match (name, filters.is_empty()) {
+        ("else", true) => Token::Else,
+        ("end", true) => Token::End,
+        _ => Token::Fact(name.to_string(), filters),
+    };
+    Ok((rest, token))
+}
+
+fn filter(input: &str) -> ParseResult<'_, TextFilter> {
+    let (rest, (name, argument)) = tuple((
+        terminated(take_while1(|c: char| c.is_alphanumeric() || c == '_'), space0),
+        opt(preceded(char(':'), take_till1(|c| c == '|' || c == '}'))),
+    ))(input)?;
+    let filter = match (name, argument) {
+        ("count", None) => TextFilter::Count,
+        ("upper", None) => TextFilter::Upper,
+        ("lower", None) => TextFilter::Lower,
+        ("capitalize", None) => TextFilter::Capitalize,
+        ("join", separator) => TextFilter::Join(separator.unwrap_or(", ").to_string()),
+        ("decimals", Some(decimals)) => match decimals.trim().parse() {
+            Ok(decimals) => TextFilter::Decimals(decimals),
+            Err(_) => return Err(failure(input, "`decimals` takes a number, as in `decimals:2`")),
+        },
+        ("default", Some(default)) => TextFilter::Default(default.to_string()),
+        _ => {
+            return Err(failure(
+                input,
+                "expected a filter: count, upper, lower, capitalize, join, decimals:n or default:text",
+            ))
+        }
+    };
+    Ok((rest, filter))
+}
+
+// Builds the parts up to an `{else}` or `{end}`, which is returned as well
+fn parts_until_end(tokens: &mut impl Iterator<Item = Token>, text: &str) -> Result<(Vec<TextPart>, Option<Token>), TextError> {
+    let mut parts = Vec::new();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Literal(literal) => match parts.last_mut() {
+                Some(TextPart::Literal(previous)) => previous.push_str(&literal),
+                _ => parts.push(TextPart::Literal(literal)),
+            },
+            Token::Fact(name, filters) => parts.push(TextPart::Fact { name, filters }),
+            Token::If(condition) => {
+                let (then, end) = parts_until_end(tokens, text)?;
+                let otherwise = match end {
+                    Some(Token::Else) => match parts_until_end(tokens, text)? {
+                        (otherwise, Some(Token::End)) => otherwise,
+                        _ => return Err(TextError::new(text, "`{if}` without `{end}`")),
+                    },
+                    Some(Token::End) => Vec::new(),
+                    _ => return Err(TextError::new(text, "`{if}` without `{end}`")),
+                };
+                parts.push(TextPart::Conditional { condition, then, otherwise });
+            }
+            Token::Else | Token::End => return Ok((parts, Some(token))),
+        }
+    }
+    Ok((parts, None))
+}
+
+fn render_parts(parts: &[TextPart], facts: &dyn FactSource, rendered: &mut String) {
+    for part in parts {
+        match part {
+            TextPart::Literal(literal) => rendered.push_str(literal),
+            TextPart::Fact { name, filters } => rendered.push_str(&render_fact(name, filters, facts)),
+            TextPart::Conditional { condition, then, otherwise } => {
+                let parts = if condition.evaluate(facts) { then } else { otherwise };
+                render_parts(parts, facts, rendered);
+            }
+        }
+    }
+}
+
+// A value on its way through the filters
+enum TextValue {
+    Text(String),
+    Int(i64),
+    Float(f32),
+    List(Vec<String>),
+}
+
+impl TextValue {
+    fn of(fact: &Fact) -> Self {
+        match fact {
+            Fact::Int(_, value) => TextValue::Int(*value as i64),
+            Fact::Float(_, value) => TextValue::Float(value.0),
+            Fact::Bool(_, value) => TextValue::Text(value.to_string()),
+            Fact::String(_, value) => TextValue::Text(value.clone()),
+            Fact::StringList(_, values) => {
+                let mut values: Vec<String> = values.0.iter().cloned().collect();
+                values.sort();
+                TextValue::List(values)
+            }
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            TextValue::Text(text) => text.is_empty(),
+            TextValue::List(values) => values.is_empty(),
+            TextValue::Int(_) | TextValue::Float(_) => false,
+        }
+    }
+
+    fn into_text(self) -> String {
+        match self {
+            TextValue::Text(text) => text,
+            TextValue::Int(value) => value.to_string(),
+            TextValue::Float(value) => value.to_string(),
+            TextValue::List(values) => values.join(", "),
+        }
+    }
+
+    fn filter(self, filter: &TextFilter) -> TextValue {
+        match (filter, self) {
+            (TextFilter::Count, TextValue::List(values)) => TextValue::Int(values.len() as i64),
+            (TextFilter::Count, TextValue::Text(text)) => TextValue::Int(text.chars().count() as i64),
+            (TextFilter::Upper, value) => TextValue::Text(value.into_text().to_uppercase()),
+            (TextFilter::Lower, value) => TextValue::Text(value.into_text().to_lowercase()),
+            (TextFilter::Capitalize, value) => {
+                let text = value.into_text();
+                let mut chars = text.chars();
+                TextValue::Text(match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars).collect(),
+                    None => text,
+                })
+            }
+            (TextFilter::Join(separator), TextValue::List(values)) => TextValue::Text(values.join(separator)),
+            (TextFilter::Decimals(decimals), TextValue::Float(value)) => {
+                TextValue::Text(format!("{:.*}", *decimals, value))
+            }
+            (TextFilter::Decimals(decimals), TextValue::Int(value)) => {
+                TextValue::Text(format!("{:.*}", *decimals, value as f64))
+            }
+            (TextFilter::Default(default), value) if value.is_empty() => TextValue::Text(default.clone()),
+            (_, value) => value,
+        }
+    }
+}
+
+fn render_fact(name: &str, filters: &[TextFilter], facts: &dyn FactSource) -> String {
+    let value = match facts.fact(name) {
+        Some(fact) => TextValue::of(fact),
+        None => match filters.iter().find(|filter| matches!(filter, TextFilter::Default(_))) {
+            Some(_) => TextValue::Text(String::new()),
+            None => return format!("{{{}}}", name),
+        },
+    };
+    filters.iter().fold(value, TextValue::filter).into_text()
+}
+
+fn collect_fact_names<'a>(parts: &'a [TextPart], names: &mut Vec<&'a str>) {
+    for part in parts {
+        match part {
+            TextPart::Literal(_) => {}
+            TextPart::Fact { name, filters } => {
+                // A fact with a default doesn't have to be set
+                if !filters.iter().any(|filter| matches!(filter, TextFilter::Default(_))) {
+                    names.push(name);
+                }
+            }
+            TextPart::Conditional { condition, then, otherwise } => {
+                condition.collect_fact_names(names);
+                collect_fact_names(then, names);
+                collect_fact_names(otherwise, names);
+            }
+        }
+    }
+}
+
+// Keeps a Text showing the template with the current facts
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct FactText(pub TextTemplate);
+
+pub fn fact_text_system(mut texts: Query<(Ref<FactText>, &mut Text)>, cool_fact_store: Res<FactsOfTheWorld>) {
+    for (fact_text, mut text) in texts.iter_mut() {
+        if !fact_text.is_changed() && !cool_fact_store.is_changed() {
+            continue;
+        }
+        let rendered = fact_text.0.render(&cool_fact_store.facts);
+        if let Some(section) = text.sections.first_mut() {
+            if section.value != rendered {
+                section.value = rendered;
+            }
+        }
+    }
+}
+
+// Checks the texts of all stories and conversations, reporting texts that aren't valid templates
+// and facts they show that are neither in the schema nor set by any story effect
+pub fn validate_story_texts(
+    story_engine: &StoryEngine,
+    conversations: &Conversations,
+    schema: &FactSchema,
+) -> Vec<TextError> {
+    let mut texts: Vec<&str> = Vec::new();
+    let mut effects: Vec<&Effect> = Vec::new();
+    // Templates show their parameters until they are instantiated
+    for story in story_engine.stories.iter() {
+        texts.push(&story.name);
+        effects.extend(story.failures.iter().flat_map(|failure| failure.effects.iter()));
+        for beat in story.beats.iter() {
+            texts.push(&beat.name);
+            effects.extend(beat.effects.iter());
+            effects.extend(beat.objectives.iter().flat_map(|objective| objective.effects.iter()));
+            effects.extend(beat.failures.iter().flat_map(|failure| failure.effects.iter()));
+        }
+    }
+    for conversation in conversations.conversations.iter() {
+        for node in conversation.nodes.iter() {
+            texts.extend(node.lines.iter().map(|line| line.text.as_str()));
+            texts.extend(node.choices.iter().map(|choice| choice.text.as_str()));
+        }
+    }
+
+    let mut errors = Vec::new();
+    for text in texts.into_iter().filter(|text| text.contains('{')) {
+        let template = match TextTemplate::parse(text) {
+            Ok(template) => template,
+            Err(error) => {
+                errors.push(error);
+                continue;
+            }
+        };
+        for name in template.fact_names() {
+            let is_known = schema.facts.contains_key(name) || effects.iter().any(|effect| effect.fact_name() == name);
+            if !is_known {
+                errors.push(TextError::new(text, format!("unknown fact '{}'", name)));
+            }
+        }
+    }
+    errors
+}
+
+pub fn story_text_validator(
+    story_engine: Res<StoryEngine>,
+    conversations: Res<Conversations>,
+    storylets: Res<StoryletPool>,
+    response_table: Res<ResponseTable>,
+    schema: Res<FactSchema>,
+    cool_fact_store: Res<FactsOfTheWorld>,
+) {
+    let schema = content_schema(&schema, &conversations, &storylets, &response_table).with_facts_of(&cool_fact_store);
+    for error in validate_story_texts(&story_engine, &conversations, &schema) {
+        warn!("Text: {}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beats::conversations::parse_conversations;
+    use crate::beats::parser::parse_stories;
+    use crate::beats::storylets::Storylet;
+    use crate::beats::validation::FactType;
+
+    fn facts() -> FactsOfTheWorld {
+        let mut facts = FactsOfTheWorld::new();
+        facts.store_int("gold".to_string(), 3);
+        facts.store_float("reputation".to_string(), 0.25);
+        facts.store_string("title".to_string(), "knight".to_string());
+        facts.add_to_list("inventory".to_string(), "torch".to_string());
+        facts.add_to_list("inventory".to_string(), "rope".to_string());
+        facts
+    }
+
+    fn render(text: &str) -> String {
+        TextTemplate::parse(text).unwrap().render(&facts().facts)
+    }
+
+    #[test]
+    fn parses_placeholders_filters_and_conditionals() {
+        let template = TextTemplate::parse("You have {gold} gold{if gold < 5}, spend it wisely{end}.").unwrap();
+        assert_eq!(
+            template.parts,
+            vec![
+                TextPart::Literal("You have ".to_string()),
+                TextPart::Fact {
+                    name: "gold".to_string(),
+                    filters: Vec::new(),
+                },
+                TextPart::Literal(" gold".to_string()),
+                TextPart::Conditional {
+                    condition: Condition::IntLessThan {
+                        fact_name: "gold".to_string(),
+                        expected_value: 5,
+                    },
+                    then: vec![TextPart::Literal(", spend it wisely".to_string())],
+                    otherwise: Vec::new(),
+                },
+                TextPart::Literal(".".to_string()),
+            ]
+        );
+        assert_eq!(template.fact_names(), vec!["gold"]);
+    }
+
+    #[test]
+    fn renders_facts_through_filters() {
+        assert_eq!(render("You have {gold} gold"), "You have 3 gold");
+        assert_eq!(render("{inventory|count} items: {inventory|join: and }"), "2 items: rope and torch");
+        assert_eq!(render("Sir {title|capitalize}, {title|upper}"), "Sir Knight, KNIGHT");
+        assert_eq!(render("{reputation|decimals:2}"), "0.25");
+        assert_eq!(render("Hello {name|default:stranger}"), "Hello stranger");
+        assert_eq!(render("{{literal}} {missing}"), "{literal} {missing}");
+    }
+
+    #[test]
+    fn renders_nested_conditionals() {
+        let text = "{if gold >= 10}Buy it?{else}{if \"torch\" in inventory}Light the way.{else}Go away.{end}{end}";
+        assert_eq!(render(text), "Light the way.");
+    }
+
+    #[test]
+    fn reports_broken_templates() {
+        let error = TextTemplate::parse("You have {gold gold").unwrap_err();
+        assert_eq!(error.message, "column 16: expected `}` to close the placeholder");
+
+        let error = TextTemplate::parse("{gold|shout}").unwrap_err();
+        assert!(error.message.starts_with("column 7: expected a filter"));
+
+        let error = TextTemplate::parse("{if gold > 3}rich").unwrap_err();
+        assert_eq!(error.message, "`{if}` without `{end}`");
+
+        let error = TextTemplate::parse("a } b").unwrap_err();
+        assert_eq!(error.message, "column 3: a lone `}`, write `}}` for a brace");
+
+        let error = TextTemplate::parse("rich{end}").unwrap_err();
+        assert_eq!(error.message, "`{end}` without `{if}`");
+    }
+
+    #[test]
+    fn caches_rendered_templates() {
+        let mut templates = TextTemplates::default();
+        let mut facts = facts();
+        assert_eq!(templates.render("{gold} gold", &facts.facts), "3 gold");
+        facts.store_int("gold".to_string(), 4);
+        assert_eq!(templates.render("{gold} gold", &facts.facts), "4 gold");
+        assert_eq!(templates.render("{gold", &facts.facts), "{gold");
+        assert_eq!(templates.render("plain", &facts.facts), "plain");
+        assert_eq!(templates.templates.len(), 2);
+    }
+
+    #[test]
+    fn knows_facts_of_the_schema_storylets_and_effects() {
+        let story_engine = {
+            let mut story_engine = StoryEngine::new();
+            for story in parse_stories("# Forge\n## Light\nEffects:\n    forge_lit = true\n").unwrap() {
+                story_engine.add_story(story);
+            }
+            story_engine
+        };
+        let mut conversations = Conversations::default();
+        let smith = "# Smith\n## Welcome\nSmith: {button_pressed}, {rumour}, {forge_lit}, {has_sword}, {dragon}\n\
+            => has_sword = true\n- Bye -> END:\n";
+        for conversation in parse_conversations(smith).unwrap() {
+            conversations.add_conversation(conversation);
+        }
+        let mut storylets = StoryletPool::new(0);
+        let rumour = Effect::SetFact(Fact::String("rumour".to_string(), "dragons".to_string()));
+        storylets.add_storylets(vec![Storylet::new("Rumour".to_string(), Vec::new(), vec![rumour])]);
+        let schema = FactSchema::new().with_fact("button_pressed", FactType::Int);
+
+        let schema = content_schema(&schema, &conversations, &storylets, &ResponseTable::default());
+        let errors = validate_story_texts(&story_engine, &conversations, &schema);
+        assert_eq!(
+            errors,
+            vec![TextError::new(
+                "{button_pressed}, {rumour}, {forge_lit}, {has_sword}, {dragon}",
+                "unknown fact 'dragon'"
+            )]
+        );
+    }
+}
@@ -0,0 +1,610 @@
+use crate::beats::conversations::Conversations;
+use crate::beats::data::{
+    CompletionPolicy, Condition, Effect, Fact, FactScope, FactsOfTheWorld, Rule, Story, StoryBeat, StoryEngine,
+};
+use crate::beats::dialogue::ResponseTable;
+use crate::beats::storylets::StoryletPool;
+use bevy::log::{error, warn};
+use bevy::prelude::{Res, Resource};
+use bevy::utils::hashbrown::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/*
+A lint pass over the stories, run before shipping content. It knows which facts exist from the
+effects of the stories and from a FactSchema, the facts the game sets in code, and reports:
+
+- conditions on facts that nothing ever sets, and conditions on a fact of another type than it is
+  stored as, like `gold > 3` on a fact that is only ever set to true or false
+- stories that can never start and beats that can never complete, because their rules, objectives
+  or transitions depend on such conditions
+- beats that no transition, recovery or previous beat leads to
+- stories without beats, duplicate story names, duplicate beat names and transitions to beats
+  that don't exist
+- stories of the StoryEngine that use `self.` or `guard_3.` facts, only story entities can reach
+  the Facts of entities
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum FactType {
+    Int,
+    Float,
+    Bool,
+    String,
+    StringList,
+}
+
+impl FactType {
+    pub fn of(fact: &Fact) -> Self {
+        match fact {
+            Fact::Int(_, _) => FactType::Int,
+            Fact::Float(_, _) => FactType::Float,
+            Fact::Bool(_, _) => FactType::Bool,
+            Fact::String(_, _) => FactType::String,
+            Fact::StringList(_, _) => FactType::StringList,
+        }
+    }
+
+    // The type of fact the effect stores, None when it only removes things
+    fn stored_by(effect: &Effect) -> Option<Self> {
+        match effect {
+            Effect::SetFact(fact) => Some(FactType::of(fact)),
+            Effect::AddInt { .. } | Effect::SubtractInt { .. } | Effect::MultiplyInt { .. } | Effect::ClampInt { .. } => {
+                Some(FactType::Int)
+            }
+            Effect::AddFloat { .. }
+            | Effect::SubtractFloat { .. }
+            | Effect::MultiplyFloat { .. }
+            | Effect::ClampFloat { .. } => Some(FactType::Float),
+            Effect::ToggleBool { .. } => Some(FactType::Bool),
+            Effect::ClearList { .. } => Some(FactType::StringList),
+            Effect::RemoveFromList { .. } | Effect::RemoveFact { .. } => None,
+        }
+    }
+
+    // The type of fact a comparison looks at, None for the groups
+    pub(super) fn checked_by(condition: &Condition) -> Option<(&str, Self)> {
+        match condition {
+            Condition::IntEquals { fact_name, .. }
+            | Condition::IntMoreThan { fact_name, .. }
+            | Condition::IntLessThan { fact_name, .. } => Some((fact_name, FactType::Int)),
+            Condition::FloatEquals { fact_name, .. }
+            | Condition::FloatMoreThan { fact_name, .. }
+            | Condition::FloatLessThan { fact_name, .. } => Some((fact_name, FactType::Float)),
+            Condition::BoolEquals { fact_name, .. } => Some((fact_name, FactType::Bool)),
+            Condition::StringEquals { fact_name, .. } => Some((fact_name, FactType::String)),
+            Condition::ListContains { fact_name, .. } => Some((fact_name, FactType::StringList)),
+            Condition::All(_) | Condition::Any(_) | Condition::Not(_) | Condition::AtLeast(_, _) => None,
+        }
+    }
+}
+
+impl Display for FactType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            FactType::Int => "integer",
+            FactType::Float => "float",
+            FactType::Bool => "boolean",
+            FactType::String => "string",
+            FactType::StringList => "string list",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// The facts the game sets in code, next to the ones set by the effects of stories
+#[derive(Resource, Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct FactSchema {
+    pub facts: HashMap<String, FactType>,
+}
+
+impl FactSchema {
+    pub fn new() -> Self {
+        FactSchema { facts: HashMap::new() }
+    }
+
+    pub fn with_fact(mut self, name: impl Into<String>, fact_type: FactType) -> Self {
+        self.facts.insert(name.into(), fact_type);
+        self
+    }
+
+    // Declares the facts the FactsOfTheWorld currently holds
+    pub fn with_facts_of(mut self, facts: &FactsOfTheWorld) -> Self {
+        for (name, fact) in facts.facts.iter() {
+            self.facts.insert(name.clone(), FactType::of(fact));
+        }
+        self
+    }
+
+    // Declares the facts set by effects outside of stories, like the ones of conversations
+    pub fn with_effects<'a>(mut self, effects: impl IntoIterator<Item = &'a Effect>) -> Self {
+        for effect in effects {
+            if let Some(fact_type) = FactType::stored_by(effect) {
+                self.facts.entry(effect.fact_name().to_string()).or_insert(fact_type);
+            }
+        }
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiagnosticKind {
+    UnknownFact,
+    TypeMismatch,
+    NeverStarts,
+    NeverCompletes,
+    UnreachableBeat,
+    UnknownBeat,
+    EmptyStory,
+    DuplicateStory,
+    DuplicateBeat,
+    ScopedFact,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub kind: DiagnosticKind,
+    pub story: String,
+    pub beat: Option<String>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, kind: DiagnosticKind, story: &Story, beat: Option<&StoryBeat>, message: String) -> Self {
+        Diagnostic {
+            severity,
+            kind,
+            story: story.name.clone(),
+            beat: beat.map(|beat| beat.name.clone()),
+            message,
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: story '{}'", severity, self.story)?;
+        if let Some(beat) = &self.beat {
+            write!(f, ", beat '{}'", beat)?;
+        }
+        write!(f, ": {}", self.message)
+    }
+}
+
+pub fn has_errors(diagnostics: &[Diagnostic]) -> bool {
+    diagnostics.iter().any(|diagnostic| diagnostic.severity == Severity::Error)
+}
+
+// The types every fact is stored as, by the schema or by effects
+struct KnownFacts {
+    types: HashMap<String, HashSet<FactType>>,
+}
+
+impl KnownFacts {
+    fn new(story_engine: &StoryEngine, schema: &FactSchema) -> Self {
+        let mut types: HashMap<String, HashSet<FactType>> = HashMap::new();
+        for (name, fact_type) in schema.facts.iter() {
+            types.entry(name.clone()).or_default().insert(*fact_type);
+        }
+        for effect in all_stories(story_engine).flat_map(story_effects) {
+            if let Some(fact_type) = FactType::stored_by(effect) {
+                types.entry(effect.fact_name().to_string()).or_default().insert(fact_type);
+            }
+        }
+        KnownFacts { types }
+    }
+
+    // Whether a comparison can ever hold, the fact has to be set and have the right type. Facts
+    // named after parameters of a template are only known once it is instantiated.
+    fn can_hold(&self, story: &Story, condition: &Condition) -> bool {
+        match condition {
+            Condition::All(conditions) => conditions.iter().all(|condition| self.can_hold(story, condition)),
+            Condition::Any(conditions) => conditions.iter().any(|condition| self.can_hold(story, condition)),
+            // A comparison that can't hold makes its negation always hold
+            Condition::Not(_) => true,
+            Condition::AtLeast(count, conditions) => {
+                conditions.iter().filter(|condition| self.can_hold(story, condition)).count() >= *count
+            }
+            comparison => match FactType::checked_by(comparison) {
+                Some((name, _)) if story.mentions_parameter(name) => true,
+                Some((name, fact_type)) => self.types.get(name).is_some_and(|types| types.contains(&fact_type)),
+                None => true,
+            },
+        }
+    }
+
+    fn rules_can_hold(&self, story: &Story, rules: &[Rule]) -> bool {
+        rules.iter().flat_map(|rule| rule.conditions.iter()).all(|condition| self.can_hold(story, condition))
+    }
+}
+
+pub(super) fn all_stories(story_engine: &StoryEngine) -> impl Iterator<Item = &Story> {
+    story_engine.stories.iter().chain(story_engine.templates.iter())
+}
+
+pub(super) fn story_effects(story: &Story) -> impl Iterator<Item = &Effect> {
+    story.failures.iter().flat_map(|failure| failure.effects.iter()).chain(story.beats.iter().flat_map(|beat| {
+        beat.effects
+            .iter()
+            .chain(beat.objectives.iter().flat_map(|objective| objective.effects.iter()))
+            .chain(beat.failures.iter().flat_map(|failure| failure.effects.iter()))
+    }))
+}
+
+pub(super) fn beat_rules(beat: &StoryBeat) -> impl Iterator<Item = &Rule> {
+    beat.rules
+        .iter()
+        .chain(beat.objectives.iter().flat_map(|objective| objective.rules.iter()))
+        .chain(beat.failures.iter().flat_map(|failure| failure.rules.iter()))
+        .chain(beat.transitions.iter().flat_map(|transition| transition.rules.iter()))
+}
+
+// The comparisons of a condition, with the groups taken apart
+pub(super) fn comparisons<'a>(condition: &'a Condition, found: &mut Vec<&'a Condition>) {
+    match condition {
+        Condition::All(conditions) | Condition::Any(conditions) | Condition::AtLeast(_, conditions) => {
+            for condition in conditions {
+                comparisons(condition, found);
+            }
+        }
+        Condition::Not(condition) => comparisons(condition, found),
+        comparison => found.push(comparison),
+    }
+}
+
+// Checks all stories and story templates against the facts the effects and the schema set
+pub fn validate(story_engine: &StoryEngine, schema: &FactSchema) -> Vec<Diagnostic> {
+    let known_facts = KnownFacts::new(story_engine, schema);
+    let mut diagnostics = Vec::new();
+    let mut story_names: HashSet<&str> = HashSet::new();
+    for story in all_stories(story_engine) {
+        if !story_names.insert(story.name.as_str()) {
+            diagnostics.push(Diagnostic::new(
+                Severity::Error,
+                DiagnosticKind::DuplicateStory,
+                story,
+                None,
+                "there is another story with this name".to_string(),
+            ));
+        }
+        validate_story(story, &known_facts, &mut diagnostics);
+        // Templates can still be spawned as story entities
+        if !story.is_template() {
+            validate_scopes(story, &mut diagnostics);
+        }
+    }
+    diagnostics
+}
+
+fn validate_story(story: &Story, known_facts: &KnownFacts, diagnostics: &mut Vec<Diagnostic>) {
+    if story.beats.is_empty() {
+        diagnostics.push(Diagnostic::new(
+            Severity::Warning,
+            DiagnosticKind::EmptyStory,
+            story,
+            None,
+            "the story has no beats".to_string(),
+        ));
+    }
+
+    let story_rules = story.pre_requisites.iter().chain(story.failures.iter().flat_map(|failure| failure.rules.iter()));
+    validate_facts(story, None, story_rules, known_facts, diagnostics);
+    if !known_facts.rules_can_hold(story, &story.pre_requisites) {
+        diagnostics.push(Diagnostic::new(
+            Severity::Error,
+            DiagnosticKind::NeverStarts,
+            story,
+            None,
+            "the pre-requisites can never all hold".to_string(),
+        ));
+    }
+
+    let mut beat_names: HashSet<&str> = HashSet::new();
+    // The first beat is where the story starts
+    let mut reachable: HashSet<&str> = story.beats.first().map(|beat| beat.name.as_str()).into_iter().collect();
+    let mut targets: Vec<(Option<&StoryBeat>, &str)> = story
+        .failures
+        .iter()
+        .filter_map(|failure| failure.recovery.as_deref())
+        .map(|recovery| (None, recovery))
+        .collect();
+
+    for (index, beat) in story.beats.iter().enumerate() {
+        if !beat_names.insert(beat.name.as_str()) {
+            diagnostics.push(Diagnostic::new(
+                Severity::Error,
+                DiagnosticKind::DuplicateBeat,
+                story,
+                Some(beat),
+                "there is another beat with this name in the story".to_string(),
+            ));
+        }
+        validate_facts(story, Some(beat), beat_rules(beat), known_facts, diagnostics);
+        if let Some(reason) = never_completes(story, beat, known_facts) {
+            diagnostics.push(Diagnostic::new(
+                Severity::Error,
+                DiagnosticKind::NeverCompletes,
+                story,
+                Some(beat),
+                format!("the beat can never complete, {}", reason),
+            ));
+        }
+
+        if beat.transitions.is_empty() {
+            if let Some(next) = story.beats.get(index + 1) {
+                reachable.insert(next.name.as_str());
+            }
+        }
+        targets.extend(beat.transitions.iter().filter_map(|transition| transition.target.as_deref()).map(|target| (Some(beat), target)));
+        targets.extend(beat.failures.iter().filter_map(|failure| failure.recovery.as_deref()).map(|recovery| (Some(beat), recovery)));
+    }
+
+    for (beat, target) in targets {
+        if story.beat(target).is_none() {
+            diagnostics.push(Diagnostic::new(
+                Severity::Error,
+                DiagnosticKind::UnknownBeat,
+                story,
+                beat,
+                format!("leads to '{}', which is not a beat of the story", target),
+            ));
+        }
+        reachable.insert(target);
+    }
+    for beat in story.beats.iter().filter(|beat| !reachable.contains(beat.name.as_str())) {
+        diagnostics.push(Diagnostic::new(
+            Severity::Warning,
+            DiagnosticKind::UnreachableBeat,
+            story,
+            Some(beat),
+            "no transition, recovery or previous beat leads to this beat".to_string(),
+        ));
+    }
+}
+
+// Reports facts of the subject or of a named entity, a story of the StoryEngine would read and write
+// them as facts of the FactsOfTheWorld with a dot in their name
+fn validate_scopes(story: &Story, diagnostics: &mut Vec<Diagnostic>) {
+    let mut reported: HashSet<&str> = HashSet::new();
+    for name in story.fact_names().into_iter().chain(story_effects(story).map(Effect::fact_name)) {
+        if FactScope::of(name).0 == FactScope::Global || !reported.insert(name) {
+            continue;
+        }
+        diagnostics.push(Diagnostic::new(
+            Severity::Error,
+            DiagnosticKind::ScopedFact,
+            story,
+            None,
+            format!("'{}' is a fact of an entity, only stories spawned as entities can use it", name),
+        ));
+    }
+}
+
+// Reports conditions on facts that are never set or that are set with another type
+fn validate_facts<'a>(
+    story: &Story,
+    beat: Option<&StoryBeat>,
+    rules: impl Iterator<Item = &'a Rule>,
+    known_facts: &KnownFacts,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut found = Vec::new();
+    for condition in rules.flat_map(|rule| rule.conditions.iter()) {
+        comparisons(condition, &mut found);
+    }
+    let mut reported: HashSet<(&str, FactType)> = HashSet::new();
+    for (name, fact_type) in found.into_iter().filter_map(FactType::checked_by) {
+        if !reported.insert((name, fact_type)) || story.mentions_parameter(name) {
+            continue;
+        }
+        match known_facts.types.get(name) {
+            None => diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                DiagnosticKind::UnknownFact,
+                story,
+                beat,
+                format!("'{}' is never set by an effect or declared in the fact schema", name),
+            )),
+            Some(types) if !types.contains(&fact_type) => {
+                let mut stored: Vec<String> = types.iter().map(FactType::to_string).collect();
+                stored.sort();
+                // `reputation > 0` compares as an integer and never holds for a float fact
+                let hint = if fact_type == FactType::Int && types.contains(&FactType::Float) {
+                    ", write the number with a decimal point as in `0.0` to compare it as a float"
+                } else {
+                    ""
+                };
+                diagnostics.push(Diagnostic::new(
+                    Severity::Error,
+                    DiagnosticKind::TypeMismatch,
+                    story,
+                    beat,
+                    format!("'{}' is checked as {} but only stored as {}{}", name, fact_type, stored.join(" or "), hint),
+                ));
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+// Why the beat can never complete, if it can't
+fn never_completes(story: &Story, beat: &StoryBeat, known_facts: &KnownFacts) -> Option<String> {
+    if !known_facts.rules_can_hold(story, &beat.rules) {
+        return Some("its rules can never all hold".to_string());
+    }
+    let total = beat.objectives.len();
+    let possible = beat
+        .objectives
+        .iter()
+        .filter(|objective| objective.completed || known_facts.rules_can_hold(story, &objective.rules))
+        .count();
+    if !beat.completion.is_met(possible, total) {
+        let required = match beat.completion {
+            CompletionPolicy::All => total,
+            CompletionPolicy::Any => 1,
+            CompletionPolicy::AtLeast(count) => count.min(total),
+        };
+        return Some(format!("only {} of the {} objectives it needs can be completed", possible, required));
+    }
+    if !beat.transitions.is_empty()
+        && !beat.transitions.iter().any(|transition| known_facts.rules_can_hold(story, &transition.rules))
+    {
+        return Some("none of its transitions can ever be taken".to_string());
+    }
+    None
+}
+
+// The schema with the facts set by conversations, storylets and responses, which are outside of
+// the stories
+pub(super) fn content_schema(
+    schema: &FactSchema,
+    conversations: &Conversations,
+    storylets: &StoryletPool,
+    response_table: &ResponseTable,
+) -> FactSchema {
+    let conversation_effects = conversations
+        .conversations
+        .iter()
+        .flat_map(|conversation| conversation.nodes.iter())
+        .flat_map(|node| node.effects.iter().chain(node.choices.iter().flat_map(|choice| choice.effects.iter())));
+    let storylet_effects = storylets.storylets.iter().flat_map(|storylet| storylet.effects.iter());
+    let response_effects = response_table.responses.iter().flat_map(|response| response.effects.iter());
+    let mut schema = schema
+        .clone()
+        .with_effects(conversation_effects.chain(storylet_effects).chain(response_effects));
+    // Responses remember that they were said
+    for response in response_table.responses.iter() {
+        schema = schema.with_fact(response.memory_fact(), FactType::Bool);
+    }
+    schema
+}
+
+pub fn story_validator(
+    story_engine: Res<StoryEngine>,
+    conversations: Res<Conversations>,
+    storylets: Res<StoryletPool>,
+    response_table: Res<ResponseTable>,
+    schema: Res<FactSchema>,
+    cool_fact_store: Res<FactsOfTheWorld>,
+) {
+    let schema = content_schema(&schema, &conversations, &storylets, &response_table).with_facts_of(&cool_fact_store);
+    for diagnostic in validate(&story_engine, &schema) {
+        match diagnostic.severity {
+            Severity::Error => error!("{}", diagnostic),
+            Severity::Warning => warn!("{}", diagnostic),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::beats::parser::parse_stories;
+
+    fn story_engine(source: &str) -> StoryEngine {
+        let mut story_engine = StoryEngine::new();
+        for story in parse_stories(source).unwrap() {
+            if story.is_template() {
+                story_engine.add_template(story);
+            } else {
+                story_engine.add_story(story);
+            }
+        }
+        story_engine
+    }
+
+    fn kinds(diagnostics: &[Diagnostic]) -> Vec<DiagnosticKind> {
+        diagnostics.iter().map(|diagnostic| diagnostic.kind).collect()
+    }
+
+    #[test]
+    fn reports_facts_checked_as_another_type() {
+        let story_engine = story_engine(
+            "# Forge\n## Light\nEffects:\n    gold = true\n# Market\nRich:\n    gold > 3\n## Buy\n",
+        );
+        let diagnostics = validate(&story_engine, &FactSchema::new());
+        assert_eq!(kinds(&diagnostics), vec![DiagnosticKind::TypeMismatch, DiagnosticKind::NeverStarts]);
+        assert_eq!(diagnostics[0].story, "Market");
+        assert_eq!(diagnostics[0].message, "'gold' is checked as integer but only stored as boolean");
+        assert!(has_errors(&diagnostics));
+    }
+
+    #[test]
+    fn reports_integers_compared_with_float_facts() {
+        let schema = FactSchema::new().with_fact("reputation", FactType::Float);
+        let diagnostics = validate(&story_engine("# Fame\n## Known\nRespected:\n    reputation > 0\n"), &schema);
+        assert_eq!(kinds(&diagnostics)[0], DiagnosticKind::TypeMismatch);
+        assert_eq!(
+            diagnostics[0].message,
+            "'reputation' is checked as integer but only stored as float, write the number with a decimal point as in `0.0` to compare it as a float"
+        );
+
+        let fixed = story_engine("# Fame\n## Known\nRespected:\n    reputation > 0.0\n");
+        assert!(validate(&fixed, &schema).is_empty());
+    }
+
+    #[test]
+    fn reports_unknown_facts_unless_declared() {
+        let story_engine = story_engine("# Forge\n## Light\nHot:\n    heat > 3\n");
+        assert_eq!(
+            kinds(&validate(&story_engine, &FactSchema::new())),
+            vec![DiagnosticKind::UnknownFact, DiagnosticKind::NeverCompletes]
+        );
+        let schema = FactSchema::new().with_fact("heat", FactType::Int);
+        assert!(validate(&story_engine, &schema).is_empty());
+    }
+
+    #[test]
+    fn reports_duplicate_names() {
+        let story_engine = story_engine("# Forge\n## Light\n## Light\n# Forge\n## Cool\n");
+        assert_eq!(
+            kinds(&validate(&story_engine, &FactSchema::new())),
+            vec![DiagnosticKind::DuplicateBeat, DiagnosticKind::DuplicateStory]
+        );
+    }
+
+    #[test]
+    fn reports_missing_and_unreachable_beats() {
+        // The parser already rejects transitions to missing beats, RON stories don't
+        let mut story_engine = story_engine("# Forge\n## Light\nCool -> END:\n## Hammer\n");
+        story_engine.stories[0].beats[0].transitions[0].target = Some("Quench".to_string());
+        assert_eq!(
+            kinds(&validate(&story_engine, &FactSchema::new())),
+            vec![DiagnosticKind::UnknownBeat, DiagnosticKind::UnreachableBeat]
+        );
+    }
+
+    #[test]
+    fn reports_entity_facts_in_global_stories() {
+        let patrol =
+            story_engine("# Patrol\n## Alert\nEffects:\n    guard_3.alerted = true\n    self.alerted = true\n");
+        let diagnostics = validate(&patrol, &FactSchema::new());
+        assert_eq!(kinds(&diagnostics), vec![DiagnosticKind::ScopedFact, DiagnosticKind::ScopedFact]);
+        assert_eq!(
+            diagnostics[0].message,
+            "'guard_3.alerted' is a fact of an entity, only stories spawned as entities can use it"
+        );
+
+        let template = story_engine("# Patrol {guard} (guard)\n## Alert\nEffects:\n    self.alerted = true\n");
+        assert!(validate(&template, &FactSchema::new()).is_empty());
+    }
+
+    #[test]
+    fn knows_facts_named_after_template_parameters() {
+        let story_engine = story_engine(
+            "# Greet {npc} (npc)\nMet:\n    {npc}_met\n## Wave\nEffects:\n    {npc}_greeted = true\n",
+        );
+        assert!(validate(&story_engine, &FactSchema::new()).is_empty());
+    }
+}
@@ -2,17 +2,21 @@
 
 mod actions;
 mod audio;
-mod beats;
+pub mod beats;
 mod loading;
+pub mod localization;
 mod menu;
 mod player;
+mod save;
 mod ui;
 
 use crate::actions::ActionsPlugin;
 use crate::audio::InternalAudioPlugin;
 use crate::loading::LoadingPlugin;
+use crate::localization::LocalizationPlugin;
 use crate::menu::MenuPlugin;
 use crate::player::PlayerPlugin;
+use crate::save::SavePlugin;
 
 use crate::beats::StoryPlugin;
 use bevy::app::App;
@@ -46,6 +50,8 @@ impl Plugin for GamePlugin {
             InternalAudioPlugin,
             PlayerPlugin,
             StoryPlugin,
+            SavePlugin,
+            LocalizationPlugin,
         ));
 
         #[cfg(debug_assertions)]
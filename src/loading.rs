@@ -1,3 +1,5 @@
+use crate::beats::assets::{ConversationAsset, ResponseAsset, StoryAsset, StoryletAsset};
+use crate::localization::StringTableAsset;
 use crate::GameState;
 use bevy::prelude::*;
 use bevy_asset_loader::prelude::*;
@@ -14,7 +16,9 @@ impl Plugin for LoadingPlugin {
             LoadingState::new(GameState::Loading)
                 .continue_to_state(GameState::Menu)
                 .load_collection::<AudioAssets>()
-                .load_collection::<TextureAssets>(),
+                .load_collection::<TextureAssets>()
+                .load_collection::<StoryAssets>()
+                .load_collection::<LocaleAssets>(),
         );
     }
 }
@@ -35,3 +39,27 @@ pub struct TextureAssets {
     #[asset(path = "textures/github.png")]
     pub github: Handle<Image>,
 }
+
+#[derive(AssetCollection, Resource)]
+pub struct StoryAssets {
+    #[asset(
+        paths("story_example.story", "side_quests.stories.ron"),
+        collection(typed)
+    )]
+    pub stories: Vec<Handle<StoryAsset>>,
+    #[asset(paths("guard_barks.responses"), collection(typed))]
+    pub responses: Vec<Handle<ResponseAsset>>,
+    #[asset(paths("smith.dialogue"), collection(typed))]
+    pub conversations: Vec<Handle<ConversationAsset>>,
+    #[asset(paths("tavern.storylets.ron"), collection(typed))]
+    pub storylets: Vec<Handle<StoryletAsset>>,
+}
+
+#[derive(AssetCollection, Resource)]
+pub struct LocaleAssets {
+    #[asset(
+        paths("locales/en.strings.ron", "locales/fr.strings.ron", "locales/es.strings.ron"),
+        collection(typed)
+    )]
+    pub string_tables: Vec<Handle<StringTableAsset>>,
+}
@@ -0,0 +1,515 @@
+use crate::beats::conversations::Conversations;
+use crate::beats::data::{FactsOfTheWorld, StoryEngine};
+use crate::beats::text::{fact_text_system, FactText, TextTemplate};
+use crate::loading::LocaleAssets;
+use crate::GameState;
+use bevy::asset::io::Reader;
+use bevy::asset::{AssetLoader, AsyncReadExt, LoadContext};
+use bevy::prelude::*;
+use bevy::utils::{BoxedFuture, HashMap};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+// The locale used for strings a locale has no entry for
+pub const FALLBACK_LOCALE: &str = "en";
+
+// The ids of the strings the game itself shows, the story facing texts are their own ids
+pub const UI_TEXT_IDS: &[&str] = &[
+    "menu.play",
+    "menu.story",
+    "menu.made_with_bevy",
+    "menu.open_source",
+    "banner.hello",
+    "banner.presses",
+    "banner.welcome",
+    "dialogue.continue",
+];
+
+pub struct LocalizationPlugin;
+
+// This plugin looks the texts shown to the player up in the string table of the CurrentLocale
+// Texts with a LocalizedText are shown again whenever the locale changes, F2 switches to the next locale
+impl Plugin for LocalizationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Localization>()
+            .init_resource::<CurrentLocale>()
+            .init_asset::<StringTableAsset>()
+            .init_asset_loader::<StringTableLoader>()
+            .add_systems(OnExit(GameState::Loading), load_string_tables_from_assets)
+            .add_systems(OnEnter(GameState::Story), localization_reporter)
+            .add_systems(Update, (switch_locale, localized_text_system, fact_text_system).chain());
+    }
+}
+
+/*
+String tables are RON files, one per locale:
+
+(
+    locale: "fr",
+    plural_rule: OneForZeroAndOne,
+    strings: {
+        "menu.play": Text("Jouer"),
+        "banner.presses": Plural(one: "Appuyé {count} fois", other: "Appuyé {count} fois"),
+    },
+)
+
+Entries are looked up by id, a text from a story or conversation is its own id. Texts can show
+facts like any other story text, and plural entries get the count as `{count}`.
+ */
+
+// How a count picks the form of a plural entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub enum PluralRule {
+    // 1 is one, everything else is other, as in English, German, Spanish or Swedish
+    #[default]
+    OneOther,
+    // 0 and 1 are one, as in French
+    OneForZeroAndOne,
+    // There is only one form, as in Japanese or Chinese
+    OtherOnly,
+}
+
+impl PluralRule {
+    fn is_one(&self, count: i64) -> bool {
+        match self {
+            PluralRule::OneOther => count == 1,
+            PluralRule::OneForZeroAndOne => count == 0 || count == 1,
+            PluralRule::OtherOnly => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum LocalizedString {
+    Text(String),
+    Plural {
+        // Used for a count of zero when it is given, whatever the rule says
+        #[serde(default)]
+        zero: Option<String>,
+        #[serde(default)]
+        one: Option<String>,
+        other: String,
+    },
+}
+
+impl LocalizedString {
+    fn form(&self, rule: PluralRule, count: Option<i64>) -> &str {
+        match (self, count) {
+            (LocalizedString::Text(text), _) => text,
+            (LocalizedString::Plural { other, .. }, None) => other,
+            (LocalizedString::Plural { zero, one, other }, Some(count)) => {
+                if let (0, Some(zero)) = (count, zero) {
+                    return zero;
+                }
+                match one {
+                    Some(one) if rule.is_one(count) => one,
+                    _ => other,
+                }
+            }
+        }
+    }
+}
+
+// The strings of a single locale
+#[derive(Asset, TypePath, Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct StringTableAsset {
+    pub locale: String,
+    #[serde(default)]
+    pub plural_rule: PluralRule,
+    pub strings: HashMap<String, LocalizedString>,
+}
+
+#[derive(Resource, Debug, Clone, PartialEq)]
+pub struct CurrentLocale(pub String);
+
+impl Default for CurrentLocale {
+    fn default() -> Self {
+        CurrentLocale(FALLBACK_LOCALE.to_string())
+    }
+}
+
+// The string tables of all locales
+#[derive(Resource, Debug, Clone, Default)]
+pub struct Localization {
+    pub tables: HashMap<String, StringTableAsset>,
+}
+
+impl Localization {
+    pub fn add_table(&mut self, table: StringTableAsset) {
+        self.tables.insert(table.locale.clone(), table);
+    }
+
+    // The locales with a string table, sorted
+    pub fn locales(&self) -> Vec<&str> {
+        let mut locales: Vec<&str> = self.tables.keys().map(String::as_str).collect();
+        locales.sort_unstable();
+        locales
+    }
+
+    // The string for the id in the locale, or in the fallback locale, or the id itself
+    pub fn text<'a>(&'a self, locale: &str, id: &'a str) -> &'a str {
+        self.plural(locale, id, None)
+    }
+
+    // Like text, with the form for the count when the string is a plural entry
+    pub fn plural<'a>(&'a self, locale: &str, id: &'a str, count: Option<i64>) -> &'a str {
+        [locale, FALLBACK_LOCALE]
+            .into_iter()
+            .filter_map(|locale| self.tables.get(locale))
+            .find_map(|table| Some(table.strings.get(id)?.form(table.plural_rule, count)))
+            .unwrap_or(id)
+    }
+
+    // The string for the id with the count filled in for `{count}`
+    pub fn format(&self, locale: &str, id: &str, count: Option<i64>) -> String {
+        let text = self.plural(locale, id, count);
+        match count {
+            Some(count) => text.replace("{count}", &count.to_string()),
+            None => text.to_string(),
+        }
+    }
+}
+
+// Shows the string with the id in the current locale. The text is kept up to date with the facts
+// through a FactText, which is added when the entity has none
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct LocalizedText {
+    pub id: String,
+    // The fact that picks the plural form, when the string has one
+    pub count_fact: Option<String>,
+}
+
+impl LocalizedText {
+    pub fn new(id: impl Into<String>) -> Self {
+        LocalizedText {
+            id: id.into(),
+            count_fact: None,
+        }
+    }
+
+    pub fn plural(id: impl Into<String>, count_fact: impl Into<String>) -> Self {
+        LocalizedText {
+            id: id.into(),
+            count_fact: Some(count_fact.into()),
+        }
+    }
+}
+
+// Keys that are missing from or unused in the string table of a locale
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LocaleReport {
+    pub locale: String,
+    pub missing: Vec<String>,
+    pub unused: Vec<String>,
+}
+
+impl Display for LocaleReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "locale '{}': {} missing ({}), {} unused ({})",
+            self.locale,
+            self.missing.len(),
+            self.missing.join(", "),
+            self.unused.len(),
+            self.unused.join(", ")
+        )
+    }
+}
+
+// Reports the keys every locale is missing and the ones it has but nothing uses. Story texts are
+// their own strings in the fallback locale, so only the ids have to be in its table
+pub fn localization_report(localization: &Localization, ids: &[&str], texts: &[&str]) -> Vec<LocaleReport> {
+    localization
+        .locales()
+        .into_iter()
+        .map(|locale| {
+            let table = &localization.tables[locale];
+            let required = ids
+                .iter()
+                .chain(texts.iter().filter(|_| locale != FALLBACK_LOCALE));
+            let mut missing: Vec<String> = required
+                .filter(|key| !table.strings.contains_key(**key))
+                .map(|key| key.to_string())
+                .collect();
+            let mut unused: Vec<String> = table
+                .strings
+                .keys()
+                .filter(|key| !ids.contains(&key.as_str()) && !texts.contains(&key.as_str()))
+                .cloned()
+                .collect();
+            missing.sort();
+            missing.dedup();
+            unused.sort();
+            LocaleReport {
+                locale: locale.to_string(),
+                missing,
+                unused,
+            }
+        })
+        .collect()
+}
+
+// The story and dialogue texts shown to the player
+pub fn story_texts<'a>(story_engine: &'a StoryEngine, conversations: &'a Conversations) -> Vec<&'a str> {
+    let mut texts: Vec<&str> = Vec::new();
+    for story in story_engine.stories.iter() {
+        texts.push(&story.name);
+        texts.extend(story.beats.iter().map(|beat| beat.name.as_str()));
+    }
+    for node in conversations.conversations.iter().flat_map(|conversation| conversation.nodes.iter()) {
+        texts.extend(node.lines.iter().map(|line| line.speaker.as_str()));
+        texts.extend(node.lines.iter().map(|line| line.text.as_str()));
+        texts.extend(node.choices.iter().map(|choice| choice.text.as_str()));
+    }
+    texts.sort_unstable();
+    texts.dedup();
+    texts
+}
+
+#[derive(Debug)]
+pub enum StringTableLoaderError {
+    Io(std::io::Error),
+    Ron(ron::error::SpannedError),
+}
+
+impl Display for StringTableLoaderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StringTableLoaderError::Io(error) => write!(f, "could not read string table: {}", error),
+            StringTableLoaderError::Ron(error) => write!(f, "could not parse string table: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for StringTableLoaderError {}
+
+impl From<std::io::Error> for StringTableLoaderError {
+    fn from(error: std::io::Error) -> Self {
+        StringTableLoaderError::Io(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for StringTableLoaderError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        StringTableLoaderError::Ron(error)
+    }
+}
+
+// Loads `.strings.ron` files, one StringTableAsset per locale
+#[derive(Default)]
+pub struct StringTableLoader;
+
+impl AssetLoader for StringTableLoader {
+    type Asset = StringTableAsset;
+    type Settings = ();
+    type Error = StringTableLoaderError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            Ok(ron::de::from_bytes::<StringTableAsset>(&bytes)?)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["strings.ron"]
+    }
+}
+
+fn load_string_tables_from_assets(
+    mut localization: ResMut<Localization>,
+    locale_assets: Res<LocaleAssets>,
+    string_tables: Res<Assets<StringTableAsset>>,
+) {
+    for table in locale_assets.string_tables.iter().filter_map(|handle| string_tables.get(handle)) {
+        localization.add_table(table.clone());
+    }
+}
+
+fn switch_locale(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    localization: Res<Localization>,
+    mut current_locale: ResMut<CurrentLocale>,
+) {
+    if !keyboard_input.just_pressed(KeyCode::F2) {
+        return;
+    }
+    let locales = localization.locales();
+    if let Some(next) = locales
+        .iter()
+        .position(|locale| *locale == current_locale.0)
+        .and_then(|index| locales.get((index + 1) % locales.len()))
+        .or(locales.first())
+    {
+        info!("Switching to locale '{}'", next);
+        current_locale.0 = next.to_string();
+    }
+}
+
+// Looks the texts up again when they are added or the locale or string tables change
+fn localized_text_system(
+    mut commands: Commands,
+    mut texts: Query<(Entity, Ref<LocalizedText>, Option<&mut FactText>)>,
+    localization: Res<Localization>,
+    current_locale: Res<CurrentLocale>,
+    cool_fact_store: Res<FactsOfTheWorld>,
+) {
+    for (entity, localized_text, fact_text) in texts.iter_mut() {
+        let count = localized_text
+            .count_fact
+            .as_ref()
+            .map(|name| cool_fact_store.get_int(name).copied().unwrap_or(0))
+            .map(i64::from);
+        let counted_fact_changed = count.is_some() && cool_fact_store.is_changed();
+        if !localized_text.is_changed() && !localization.is_changed() && !current_locale.is_changed() && !counted_fact_changed {
+            continue;
+        }
+        let text = localization.format(&current_locale.0, &localized_text.id, count);
+        let template = TextTemplate::parse(&text).unwrap_or_else(|error| {
+            warn!("Locale '{}': {}", current_locale.0, error);
+            TextTemplate::literal(text)
+        });
+        match fact_text {
+            Some(mut fact_text) => {
+                if fact_text.0 != template {
+                    fact_text.0 = template;
+                }
+            }
+            None => {
+                commands.entity(entity).insert(FactText(template));
+            }
+        }
+    }
+}
+
+fn localization_reporter(
+    localization: Res<Localization>,
+    story_engine: Res<StoryEngine>,
+    conversations: Res<Conversations>,
+) {
+    let texts = story_texts(&story_engine, &conversations);
+    for report in localization_report(&localization, UI_TEXT_IDS, &texts) {
+        if report.missing.is_empty() && report.unused.is_empty() {
+            continue;
+        }
+        warn!("Localization: {}", report);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plural(zero: Option<&str>, one: Option<&str>, other: &str) -> LocalizedString {
+        LocalizedString::Plural {
+            zero: zero.map(str::to_string),
+            one: one.map(str::to_string),
+            other: other.to_string(),
+        }
+    }
+
+    fn table(locale: &str, plural_rule: PluralRule, strings: &[(&str, LocalizedString)]) -> StringTableAsset {
+        StringTableAsset {
+            locale: locale.to_string(),
+            plural_rule,
+            strings: strings.iter().map(|(id, string)| (id.to_string(), string.clone())).collect(),
+        }
+    }
+
+    #[test]
+    fn picks_plural_forms_by_the_rule_of_the_locale() {
+        let presses = plural(None, Some("one"), "other");
+        let forms = |rule| [0, 1, 2].map(|count| presses.form(rule, Some(count)));
+        assert_eq!(forms(PluralRule::OneOther), ["other", "one", "other"]);
+        assert_eq!(forms(PluralRule::OneForZeroAndOne), ["one", "one", "other"]);
+        assert_eq!(forms(PluralRule::OtherOnly), ["other", "other", "other"]);
+        assert_eq!(presses.form(PluralRule::OneOther, None), "other");
+
+        // A zero form wins over the rule, a missing one form falls back to other
+        let with_zero = plural(Some("zero"), Some("one"), "other");
+        assert_eq!(with_zero.form(PluralRule::OneForZeroAndOne, Some(0)), "zero");
+        assert_eq!(plural(None, None, "other").form(PluralRule::OneOther, Some(1)), "other");
+        assert_eq!(LocalizedString::Text("text".to_string()).form(PluralRule::OneOther, Some(1)), "text");
+    }
+
+    #[test]
+    fn falls_back_to_english_and_then_to_the_id() {
+        let mut localization = Localization::default();
+        localization.add_table(table(
+            "en",
+            PluralRule::OneOther,
+            &[
+                ("menu.play", LocalizedString::Text("Play".to_string())),
+                ("banner.presses", plural(None, Some("Pressed once"), "Pressed {count} times")),
+            ],
+        ));
+        localization.add_table(table(
+            "fr",
+            PluralRule::OneForZeroAndOne,
+            &[("banner.presses", plural(None, Some("Appuyé {count} fois"), "Appuyé {count} fois !"))],
+        ));
+
+        assert_eq!(localization.text("fr", "menu.play"), "Play");
+        assert_eq!(localization.text("de", "menu.play"), "Play");
+        assert_eq!(localization.text("fr", "menu.quit"), "menu.quit");
+        assert_eq!(localization.plural("fr", "banner.presses", Some(0)), "Appuyé {count} fois");
+        assert_eq!(localization.plural("en", "banner.presses", Some(0)), "Pressed {count} times");
+        assert_eq!(localization.format("fr", "banner.presses", Some(3)), "Appuyé 3 fois !");
+    }
+
+    #[test]
+    fn reports_missing_and_unused_keys_per_locale() {
+        let text = |text: &str| LocalizedString::Text(text.to_string());
+        let mut localization = Localization::default();
+        localization.add_table(table(
+            "en",
+            PluralRule::OneOther,
+            &[("menu.play", text("Play")), ("menu.quit", text("Quit"))],
+        ));
+        localization.add_table(table("fr", PluralRule::OneForZeroAndOne, &[("The Forge", text("La Forge"))]));
+
+        let reports = localization_report(&localization, &["menu.play"], &["The Forge", "Light the Fire"]);
+        assert_eq!(
+            reports,
+            vec![
+                LocaleReport {
+                    locale: "en".to_string(),
+                    missing: Vec::new(),
+                    unused: vec!["menu.quit".to_string()],
+                },
+                LocaleReport {
+                    locale: "fr".to_string(),
+                    missing: vec!["Light the Fire".to_string(), "menu.play".to_string()],
+                    unused: Vec::new(),
+                },
+            ]
+        );
+        assert_eq!(
+            reports[1].to_string(),
+            "locale 'fr': 2 missing (Light the Fire, menu.play), 0 unused ()"
+        );
+    }
+
+    #[test]
+    fn ships_every_ui_text_in_every_locale() {
+        let mut localization = Localization::default();
+        for source in [
+            include_str!("../assets/locales/en.strings.ron"),
+            include_str!("../assets/locales/es.strings.ron"),
+            include_str!("../assets/locales/fr.strings.ron"),
+        ] {
+            localization.add_table(ron::from_str(source).unwrap());
+        }
+        assert_eq!(localization.locales(), vec!["en", "es", "fr"]);
+        for report in localization_report(&localization, UI_TEXT_IDS, &[]) {
+            assert!(report.missing.is_empty(), "{}", report);
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use crate::loading::TextureAssets;
+use crate::localization::LocalizedText;
 use crate::GameState;
 use bevy::prelude::*;
 
@@ -69,13 +70,16 @@ fn setup_menu(mut commands: Commands, textures: Res<TextureAssets>) {
                     ChangeState(GameState::Playing),
                 ))
                 .with_children(|parent| {
-                    parent.spawn(TextBundle::from_section(
-                        "Play",
-                        TextStyle {
-                            font_size: 40.0,
-                            color: Color::rgb(0.9, 0.9, 0.9),
-                            ..default()
-                        },
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "Play",
+                            TextStyle {
+                                font_size: 40.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                                ..default()
+                            },
+                        ),
+                        LocalizedText::new("menu.play"),
                     ));
                 });
 
@@ -98,13 +102,16 @@ fn setup_menu(mut commands: Commands, textures: Res<TextureAssets>) {
                     ChangeState(GameState::Story),
                 ))
                 .with_children(|parent| {
-                    parent.spawn(TextBundle::from_section(
-                        "Story",
-                        TextStyle {
-                            font_size: 40.0,
-                            color: Color::rgb(0.9, 0.9, 0.9),
-                            ..default()
-                        },
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "Story",
+                            TextStyle {
+                                font_size: 40.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                                ..default()
+                            },
+                        ),
+                        LocalizedText::new("menu.story"),
                     ));
                 });
         });
@@ -146,13 +153,16 @@ fn setup_menu(mut commands: Commands, textures: Res<TextureAssets>) {
                     OpenLink("https://bevyengine.org"),
                 ))
                 .with_children(|parent| {
-                    parent.spawn(TextBundle::from_section(
-                        "Made with Bevy",
-                        TextStyle {
-                            font_size: 15.0,
-                            color: Color::rgb(0.9, 0.9, 0.9),
-                            ..default()
-                        },
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "Made with Bevy",
+                            TextStyle {
+                                font_size: 15.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                                ..default()
+                            },
+                        ),
+                        LocalizedText::new("menu.made_with_bevy"),
                     ));
                     parent.spawn(ImageBundle {
                         image: textures.bevy.clone().into(),
@@ -184,13 +194,16 @@ fn setup_menu(mut commands: Commands, textures: Res<TextureAssets>) {
                     OpenLink("https://github.com/NiklasEi/bevy_game_template"),
                 ))
                 .with_children(|parent| {
-                    parent.spawn(TextBundle::from_section(
-                        "Open source",
-                        TextStyle {
-                            font_size: 15.0,
-                            color: Color::rgb(0.9, 0.9, 0.9),
-                            ..default()
-                        },
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "Open source",
+                            TextStyle {
+                                font_size: 15.0,
+                                color: Color::rgb(0.9, 0.9, 0.9),
+                                ..default()
+                            },
+                        ),
+                        LocalizedText::new("menu.open_source"),
                     ));
                     parent.spawn(ImageBundle {
                         image: textures.github.clone().into(),
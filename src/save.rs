@@ -0,0 +1,409 @@
+use crate::beats::data::{FactsOfTheWorld, StoryEngine};
+use crate::beats::storylets::StoryletPool;
+use crate::GameState;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+// The version written to new save files, bump it and register a migration when
+// FactsOfTheWorld or StoryEngine change in a way `#[serde(default)]` can't cover
+pub const SAVE_VERSION: u32 = 2;
+
+pub struct SavePlugin;
+
+// This plugin stores the facts of the world and the progress of all stories in save files
+// Send a SaveGameRequest or a LoadGameRequest to use it, F5 and F9 quick save and load slot 0
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PlayTime>()
+            .init_resource::<SaveDirectory>()
+            .init_resource::<SaveMigrations>()
+            .add_event::<SaveGameRequest>()
+            .add_event::<LoadGameRequest>()
+            .add_systems(
+                Update,
+                (track_play_time, quick_save_and_load, save_game, load_game)
+                    .chain()
+                    .run_if(in_state(GameState::Story)),
+            );
+    }
+}
+
+#[derive(Event)]
+pub struct SaveGameRequest {
+    pub slot: u32,
+}
+
+#[derive(Event)]
+pub struct LoadGameRequest {
+    pub slot: u32,
+}
+
+// Seconds spent playing, carried over between saves
+#[derive(Resource, Default)]
+pub struct PlayTime(pub f64);
+
+#[derive(Resource)]
+pub struct SaveDirectory(pub PathBuf);
+
+impl Default for SaveDirectory {
+    fn default() -> Self {
+        SaveDirectory(PathBuf::from("saves"))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SaveMetadata {
+    pub version: u32,
+    pub slot: u32,
+    // Seconds since the unix epoch
+    pub timestamp: u64,
+    // Seconds played when the game was saved
+    pub play_time: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SaveGame {
+    pub metadata: SaveMetadata,
+    pub facts: FactsOfTheWorld,
+    pub story_engine: StoryEngine,
+    #[serde(default)]
+    pub storylets: StoryletPool,
+}
+
+// Only used to find out which migrations a save file needs before reading all of it
+#[derive(Deserialize)]
+struct SaveVersion {
+    metadata: SaveVersionMetadata,
+}
+
+#[derive(Deserialize)]
+struct SaveVersionMetadata {
+    version: u32,
+}
+
+impl SaveGame {
+    pub fn new(
+        slot: u32,
+        play_time: f64,
+        facts: &FactsOfTheWorld,
+        story_engine: &StoryEngine,
+        storylets: &StoryletPool,
+    ) -> Self {
+        SaveGame {
+            metadata: SaveMetadata {
+                version: SAVE_VERSION,
+                slot,
+                timestamp: unix_timestamp(),
+                play_time,
+            },
+            facts: facts.clone(),
+            story_engine: story_engine.clone(),
+            storylets: storylets.clone(),
+        }
+    }
+
+    pub fn to_ron(&self) -> Result<String, SaveError> {
+        ron::ser::to_string_pretty(self, PrettyConfig::default()).map_err(SaveError::Serialize)
+    }
+
+    pub fn from_ron(contents: &str, migrations: &SaveMigrations) -> Result<Self, SaveError> {
+        let version = ron::from_str::<SaveVersion>(contents)
+            .map_err(SaveError::Deserialize)?
+            .metadata
+            .version;
+        let contents = migrations.migrate(version, contents.to_string())?;
+        let mut save_game = ron::from_str::<SaveGame>(&contents).map_err(SaveError::Deserialize)?;
+        save_game.metadata.version = SAVE_VERSION;
+        save_game.story_engine.rebuild_index(&save_game.facts);
+        Ok(save_game)
+    }
+
+    pub fn write(&self, directory: &Path) -> Result<PathBuf, SaveError> {
+        let path = slot_path(directory, self.metadata.slot);
+        std::fs::create_dir_all(directory).map_err(SaveError::Io)?;
+        std::fs::write(&path, self.to_ron()?).map_err(SaveError::Io)?;
+        Ok(path)
+    }
+
+    pub fn read(directory: &Path, slot: u32, migrations: &SaveMigrations) -> Result<Self, SaveError> {
+        let contents = std::fs::read_to_string(slot_path(directory, slot)).map_err(SaveError::Io)?;
+        SaveGame::from_ron(&contents, migrations)
+    }
+}
+
+pub fn slot_path(directory: &Path, slot: u32) -> PathBuf {
+    directory.join(format!("slot_{}.ron", slot))
+}
+
+// Turns the contents of a save file of one version into the contents of the next version
+pub type SaveMigration = fn(String) -> Result<String, SaveError>;
+
+// Migrations keyed by the version they upgrade from, they don't need to touch `metadata.version`
+#[derive(Resource)]
+pub struct SaveMigrations {
+    migrations: HashMap<u32, SaveMigration>,
+}
+
+impl Default for SaveMigrations {
+    fn default() -> Self {
+        let mut migrations = SaveMigrations {
+            migrations: HashMap::new(),
+        };
+        migrations.add(1, active_beat_index_to_names);
+        migrations
+    }
+}
+
+impl SaveMigrations {
+    pub fn add(&mut self, from_version: u32, migration: SaveMigration) -> &mut Self {
+        self.migrations.insert(from_version, migration);
+        self
+    }
+
+    pub fn migrate(&self, from_version: u32, mut contents: String) -> Result<String, SaveError> {
+        if from_version > SAVE_VERSION {
+            return Err(SaveError::UnsupportedVersion(from_version));
+        }
+        for version in from_version..SAVE_VERSION {
+            let migration = self
+                .migrations
+                .get(&version)
+                .ok_or(SaveError::MissingMigration(version))?;
+            contents = migration(contents)?;
+        }
+        Ok(contents)
+    }
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Serialize(ron::Error),
+    Deserialize(ron::error::SpannedError),
+    UnsupportedVersion(u32),
+    MissingMigration(u32),
+    Migration(String),
+}
+
+impl Display for SaveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveError::Io(error) => write!(f, "could not access save file: {}", error),
+            SaveError::Serialize(error) => write!(f, "could not write save: {}", error),
+            SaveError::Deserialize(error) => write!(f, "could not read save: {}", error),
+            SaveError::UnsupportedVersion(version) => write!(
+                f,
+                "save version {} is newer than the supported version {}",
+                version, SAVE_VERSION
+            ),
+            SaveError::MissingMigration(version) => {
+                write!(f, "no migration registered for save version {}", version)
+            }
+            SaveError::Migration(message) => write!(f, "could not migrate save: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+// Version 2 tracks the active beats of a story by name instead of the index of a single beat
+fn active_beat_index_to_names(contents: String) -> Result<String, SaveError> {
+    // The indices are read from the save as a ron::Value, which keeps the fields of the stories
+    // but drops the names of enum variants like `Int("gold", 3)`, so the active beats are written
+    // on the typed stories instead. Version 1 only differs from version 2 in this field.
+    let save = ron::from_str::<ron::Value>(&contents).map_err(SaveError::Deserialize)?;
+    let old_stories = field(&save, "story_engine")
+        .and_then(|story_engine| field(story_engine, "stories"))
+        .and_then(|stories| match stories {
+            ron::Value::Seq(stories) => Some(stories),
+            _ => None,
+        })
+        .ok_or_else(|| SaveError::Migration("the save has no stories".to_string()))?;
+    let mut save_game = ron::from_str::<SaveGame>(&contents).map_err(SaveError::Deserialize)?;
+    if old_stories.len() != save_game.story_engine.stories.len() {
+        return Err(SaveError::Migration("the stories of the save don't match".to_string()));
+    }
+
+    for (story, old_story) in save_game.story_engine.stories.iter_mut().zip(old_stories) {
+        // The field defaulted to 0 when it was missing
+        let index = match field(old_story, "active_beat_index") {
+            None => 0,
+            Some(ron::Value::Number(number)) => number
+                .as_i64()
+                .and_then(|index| usize::try_from(index).ok())
+                .ok_or_else(|| SaveError::Migration(format!("story '{}' has an invalid active beat index", story.name)))?,
+            Some(_) => {
+                return Err(SaveError::Migration(format!(
+                    "story '{}' has an invalid active beat index",
+                    story.name
+                )))
+            }
+        };
+        // Finished stories had an index past their last beat
+        story.active_beats = match story.beats.get(index) {
+            Some(beat) if story.is_started => vec![beat.name.clone()],
+            _ => Vec::new(),
+        };
+    }
+    save_game.to_ron()
+}
+
+fn field<'a>(value: &'a ron::Value, name: &str) -> Option<&'a ron::Value> {
+    match value {
+        ron::Value::Map(map) => map
+            .iter()
+            .find(|(key, _)| matches!(key, ron::Value::String(key) if key == name))
+            .map(|(_, value)| value),
+        _ => None,
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default()
+    }
+    // There is no system clock on the web
+    #[cfg(target_arch = "wasm32")]
+    {
+        0
+    }
+}
+
+fn track_play_time(mut play_time: ResMut<PlayTime>, time: Res<Time>) {
+    play_time.0 += time.delta_seconds_f64();
+}
+
+fn quick_save_and_load(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mut save_requests: EventWriter<SaveGameRequest>,
+    mut load_requests: EventWriter<LoadGameRequest>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F5) {
+        save_requests.send(SaveGameRequest { slot: 0 });
+    }
+    if keyboard_input.just_pressed(KeyCode::F9) {
+        load_requests.send(LoadGameRequest { slot: 0 });
+    }
+}
+
+fn save_game(
+    mut save_requests: EventReader<SaveGameRequest>,
+    facts: Res<FactsOfTheWorld>,
+    story_engine: Res<StoryEngine>,
+    storylets: Res<StoryletPool>,
+    play_time: Res<PlayTime>,
+    save_directory: Res<SaveDirectory>,
+) {
+    for request in save_requests.read() {
+        let save_game = SaveGame::new(request.slot, play_time.0, &facts, &story_engine, &storylets);
+        match save_game.write(&save_directory.0) {
+            Ok(path) => info!("Saved game to {}", path.display()),
+            Err(error) => error!("Could not save slot {}: {}", request.slot, error),
+        }
+    }
+}
+
+fn load_game(
+    mut load_requests: EventReader<LoadGameRequest>,
+    mut facts: ResMut<FactsOfTheWorld>,
+    mut story_engine: ResMut<StoryEngine>,
+    mut storylets: ResMut<StoryletPool>,
+    mut play_time: ResMut<PlayTime>,
+    save_directory: Res<SaveDirectory>,
+    migrations: Res<SaveMigrations>,
+) {
+    for request in load_requests.read() {
+        match SaveGame::read(&save_directory.0, request.slot, &migrations) {
+            Ok(save_game) => {
+                *facts = save_game.facts;
+                *story_engine = save_game.story_engine;
+                *storylets = save_game.storylets;
+                play_time.0 = save_game.metadata.play_time;
+                info!("Loaded slot {}", request.slot);
+            }
+            Err(error) => error!("Could not load slot {}: {}", request.slot, error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A version 1 save, where a fact and a beat contain the text of the old field and the second
+    // story was written without it
+    const VERSION_1_SAVE: &str = r#"(
+    metadata: (version: 1, slot: 0, timestamp: 0, play_time: 12.5),
+    facts: (
+        facts: {
+            "gold": Int("gold", 3),
+            "note": String("note", "active_beat_index: 7"),
+        },
+        updated_facts: [],
+    ),
+    story_engine: (
+        stories: [
+            (
+                name: "The forge",
+                pre_requisites: [],
+                beats: [
+                    (name: "active_beat_index: 1", rules: [], effects: []),
+                    (name: "Temper the blade", rules: [], effects: []),
+                ],
+                is_started: true,
+                active_beat_index: 1,
+            ),
+            (
+                name: "The mine",
+                pre_requisites: [],
+                beats: [(name: "Dig", rules: [], effects: [])],
+                is_started: true,
+            ),
+            (
+                name: "The end",
+                pre_requisites: [],
+                beats: [(name: "Done", rules: [], effects: [])],
+                is_started: true,
+                active_beat_index: 1,
+            ),
+        ],
+    ),
+)"#;
+
+    #[test]
+    fn migrates_active_beat_index_to_names() {
+        let save_game = SaveGame::from_ron(VERSION_1_SAVE, &SaveMigrations::default()).unwrap();
+        assert_eq!(save_game.metadata.version, SAVE_VERSION);
+        assert_eq!(save_game.facts.get_int("gold"), Some(&3));
+        assert_eq!(save_game.facts.get_string("note").map(String::as_str), Some("active_beat_index: 7"));
+
+        let active_beats: Vec<&[String]> = save_game
+            .story_engine
+            .stories
+            .iter()
+            .map(|story| story.active_beats.as_slice())
+            .collect();
+        assert_eq!(
+            active_beats,
+            vec![&["Temper the blade".to_string()][..], &["Dig".to_string()][..], &[][..]]
+        );
+        assert_eq!(save_game.story_engine.stories[0].beats[0].name, "active_beat_index: 1");
+    }
+
+    #[test]
+    fn rejects_saves_newer_than_supported() {
+        let newer = VERSION_1_SAVE.replace("version: 1", &format!("version: {}", SAVE_VERSION + 1));
+        assert!(matches!(
+            SaveGame::from_ron(&newer, &SaveMigrations::default()),
+            Err(SaveError::UnsupportedVersion(_))
+        ));
+    }
+}
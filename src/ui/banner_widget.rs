@@ -1,3 +1,4 @@
+use crate::localization::LocalizedText;
 use bevy::{
     ecs::system::{EntityCommand, EntityCommands},
     prelude::*,
@@ -23,9 +24,12 @@ pub struct BannerWidget;
 struct BannerLabel;
 
 pub struct BannerWidgetConfig {
+    // The id of the label in the string tables, or the label itself
     pub label: String,
     pub font: String,
     pub font_size: f32,
+    // The fact that picks the plural form of the label
+    pub count_fact: Option<String>,
 }
 
 impl BannerWidgetConfig {
@@ -34,8 +38,14 @@ impl BannerWidgetConfig {
             label: label.into(),
             font: font.into(),
             font_size,
+            count_fact: None,
         }
     }
+
+    pub fn with_count_fact(mut self, count_fact: impl Into<String>) -> Self {
+        self.count_fact = Some(count_fact.into());
+        self
+    }
 }
 
 pub trait UiBannerWidgetExt<'w, 's> {
@@ -69,10 +79,16 @@ impl<'w, 's> UiBannerWidgetExt<'w, 's> for UiBuilder<'w, 's, '_, UiRoot> {
                 // Move us a few pixels down so we look nice relative to our font.
                 .top(Val::Px(10.0));
 
+            // The label is looked up in the string table of the current locale
+            let localized_text = LocalizedText {
+                id: config.label.clone(),
+                count_fact: config.count_fact,
+            };
+
             // We would like to set a default text style without having to pass in the AssetServer.
             label
                 .entity_commands()
-                .insert(BannerLabel)
+                .insert((BannerLabel, localized_text))
                 .set_text(config.label, None)
                 .font(
                     config.font,
@@ -0,0 +1,146 @@
+use crate::beats::conversations::{ActiveConversation, Conversations, DialogueChoiceMade};
+use crate::beats::data::FactsOfTheWorld;
+use crate::beats::text::TextTemplates;
+use crate::localization::{CurrentLocale, Localization};
+use bevy::prelude::*;
+use sickle_ui::{
+    ui_builder::{UiBuilder, UiBuilderExt, UiRoot},
+    ui_commands::SetTextExt,
+    ui_style::{
+        SetBackgroundColorExt, SetNodeBottomExt, SetNodeHeightExt, SetNodeLeftExt, SetNodePositionTypeExt,
+        SetNodeWidthExt,
+    },
+    widgets::{
+        column::UiColumnExt,
+        container::UiContainerExt,
+        label::{LabelConfig, UiLabelExt},
+    },
+};
+
+const CHOICE_COLOR: Color = Color::rgb(0.15, 0.15, 0.15);
+const HOVERED_CHOICE_COLOR: Color = Color::rgb(0.25, 0.25, 0.25);
+const PRESSED_CHOICE_COLOR: Color = Color::rgb(0.35, 0.75, 0.35);
+
+pub fn plugin(app: &mut App) {
+    app.add_systems(Update, (update_dialogue_box, dialogue_choice_system));
+}
+
+#[derive(Component)]
+pub struct DialogueWidget;
+
+// The index of the choice a choice entry makes. Choice entries aren't `Button`s, so the demo
+// button system leaves them alone.
+#[derive(Component, Clone, Copy)]
+struct DialogueChoiceEntry(usize);
+
+pub trait UiDialogueWidgetExt<'w, 's> {
+    fn dialogue_box<'a>(&'a mut self) -> UiBuilder<'w, 's, 'a, Entity>;
+}
+
+impl<'w, 's> UiDialogueWidgetExt<'w, 's> for UiBuilder<'w, 's, '_, UiRoot> {
+    fn dialogue_box<'a>(&'a mut self) -> UiBuilder<'w, 's, 'a, Entity> {
+        self.column(|dialogue| {
+            dialogue.entity_commands().insert(DialogueWidget);
+            // The lines and choices are filled in by update_dialogue_box
+            dialogue
+                .style()
+                .position_type(PositionType::Absolute)
+                .left(Val::Px(10.0))
+                .bottom(Val::Px(100.0))
+                .width(Val::Px(600.0))
+                .height(Val::Auto)
+                .background_color(Color::rgba(0.0, 0.0, 0.0, 0.8));
+        })
+    }
+}
+
+// Shows the lines and available choices of the node the active conversation is at, and hides the
+// box when there is no conversation
+fn update_dialogue_box(
+    mut commands: Commands,
+    mut widgets: Query<(Entity, Ref<DialogueWidget>, &mut Style)>,
+    active_conversation: Res<ActiveConversation>,
+    conversations: Res<Conversations>,
+    cool_fact_store: Res<FactsOfTheWorld>,
+    localization: Res<Localization>,
+    current_locale: Res<CurrentLocale>,
+    mut text_templates: ResMut<TextTemplates>,
+) {
+    let locale = current_locale.0.as_str();
+    for (entity, widget, mut style) in widgets.iter_mut() {
+        // Texts show facts and choices depend on them, so a change to the facts redraws the box too
+        if !widget.is_added()
+            && !active_conversation.is_changed()
+            && !current_locale.is_changed()
+            && !cool_fact_store.is_changed()
+        {
+            continue;
+        }
+        commands.entity(entity).despawn_descendants();
+        let Some((_, node)) = active_conversation.current(&conversations) else {
+            style.display = Display::None;
+            continue;
+        };
+        style.display = Display::Flex;
+
+        let mut dialogue = commands.ui_builder(entity);
+        for line in node.lines.iter() {
+            dialogue
+                .label(LabelConfig::default())
+                .entity_commands()
+                .set_text(
+                    format!(
+                        "{}: {}",
+                        text_templates.render(localization.text(locale, &line.speaker), &cool_fact_store.facts),
+                        text_templates.render(localization.text(locale, &line.text), &cool_fact_store.facts)
+                    ),
+                    None,
+                );
+        }
+        // Nodes without choices continue with any choice
+        let choices: Vec<(usize, &str)> = if node.choices.is_empty() {
+            vec![(0, "dialogue.continue")]
+        } else {
+            node.available_choices(&cool_fact_store.facts)
+                .into_iter()
+                .map(|index| (index, node.choices[index].text.as_str()))
+                .collect()
+        };
+        for (index, text) in choices {
+            let text = format!("> {}", text_templates.render(localization.text(locale, text), &cool_fact_store.facts));
+            dialogue.container(
+                (NodeBundle::default(), Interaction::default(), DialogueChoiceEntry(index)),
+                |choice| {
+                    choice.style().background_color(CHOICE_COLOR);
+                    choice
+                        .label(LabelConfig::default())
+                        .entity_commands()
+                        .set_text(text, None);
+                },
+            );
+        }
+    }
+}
+
+fn dialogue_choice_system(
+    mut interaction_query: Query<
+        (&Interaction, &DialogueChoiceEntry, &mut BackgroundColor),
+        Changed<Interaction>,
+    >,
+    mut choice_writer: EventWriter<DialogueChoiceMade>,
+) {
+    for (interaction, entry, mut color) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = PRESSED_CHOICE_COLOR.into();
+                choice_writer.send(DialogueChoiceMade { choice: entry.0 });
+            }
+            Interaction::Hovered => {
+                *color = HOVERED_CHOICE_COLOR.into();
+            }
+            Interaction::None => {
+                *color = CHOICE_COLOR.into();
+            }
+        }
+    }
+}
@@ -1,3 +1,4 @@
 pub mod builders;
 pub mod banner_widget;
+pub mod dialogue_widget;
 pub mod fps_widget;
\ No newline at end of file